@@ -0,0 +1,164 @@
+//! Error correction and bit modulation of coded IMBE frames.
+//!
+//! A coded frame carries the prioritized chunks u<sub>0</sub>, ..., u<sub>3</sub> as
+//! (23, 12) Golay codewords, u<sub>4</sub>, ..., u<sub>6</sub> as (15, 11) Hamming
+//! codewords, and u<sub>7</sub> uncoded. Codewords c<sub>1</sub>, ..., c<sub>6</sub> are
+//! additionally XORed with a pseudo-random sequence seeded by u<sub>0</sub>.
+
+/// Generator polynomial of the (23, 12) Golay code, x<sup>11</sup> + x<sup>10</sup> +
+/// x<sup>6</sup> + x<sup>5</sup> + x<sup>4</sup> + x<sup>2</sup> + 1.
+const GOLAY_GEN: u32 = 0xC75;
+
+/// Parity check rows of the (15, 11) Hamming code. Data bits occupy the 11 MSBs of the
+/// codeword and parity bits the 4 LSBs.
+const HAMMING_CHECKS: [u32; 4] = [0x7f08, 0x78e4, 0x66d2, 0x55b1];
+
+/// Decodes (23, 12) Golay codewords.
+pub struct Golay {
+    /// Maps each syndrome to its correctable error pattern.
+    patterns: Vec<u32>,
+}
+
+impl Golay {
+    /// Create a new `Golay` decoder.
+    pub fn new() -> Golay {
+        let mut patterns = vec![0; 1 << 11];
+
+        // The code is perfect, so every syndrome corresponds to exactly one error
+        // pattern of weight 3 or less.
+        for a in 0..23 {
+            patterns[golay_rem(1 << a) as usize] = 1 << a;
+
+            for b in 0..a {
+                let e = 1 << a | 1 << b;
+                patterns[golay_rem(e) as usize] = e;
+
+                for c in 0..b {
+                    let e = e | 1 << c;
+                    patterns[golay_rem(e) as usize] = e;
+                }
+            }
+        }
+
+        Golay { patterns: patterns }
+    }
+
+    /// Decode the given 23-bit codeword into its 12 data bits and the number of bit
+    /// errors corrected.
+    pub fn decode(&self, word: u32) -> (u32, usize) {
+        let err = self.patterns[golay_rem(word) as usize];
+        ((word ^ err) >> 11, err.count_ones() as usize)
+    }
+}
+
+/// Encode the given 12 data bits as a 23-bit Golay codeword.
+#[cfg(test)]
+pub fn golay_encode(data: u32) -> u32 {
+    data << 11 | golay_rem(data << 11)
+}
+
+/// Compute the remainder of the given word divided by the Golay generator polynomial.
+fn golay_rem(mut word: u32) -> u32 {
+    for i in (11..23).rev() {
+        if word >> i & 1 == 1 {
+            word ^= GOLAY_GEN << (i - 11);
+        }
+    }
+
+    word
+}
+
+/// Decode the given 15-bit Hamming codeword into its 11 data bits and the number of bit
+/// errors corrected.
+pub fn hamming_decode(word: u32) -> (u32, usize) {
+    let syn = hamming_syndrome(word);
+
+    if syn == 0 {
+        return (word >> 4, 0);
+    }
+
+    // Find the bit whose parity check column matches the syndrome.
+    match (0..15).find(|&b| hamming_syndrome(1 << b) == syn) {
+        Some(b) => ((word ^ 1 << b) >> 4, 1),
+        None => (word >> 4, 0),
+    }
+}
+
+/// Encode the given 11 data bits as a 15-bit Hamming codeword.
+#[cfg(test)]
+pub fn hamming_encode(data: u32) -> u32 {
+    let word = data << 4;
+    word | hamming_syndrome(word)
+}
+
+/// Compute the 4-bit syndrome of the given Hamming codeword.
+fn hamming_syndrome(word: u32) -> u32 {
+    HAMMING_CHECKS.iter().fold(0, |s, &row| s << 1 | (word & row).count_ones() & 1)
+}
+
+/// Generate the modulation masks m<sub>0</sub>, ..., m<sub>7</sub> for the given
+/// u<sub>0</sub>, with each mask aligned to the LSB of its codeword.
+pub fn pn_masks(u0: u32) -> [u32; 8] {
+    let mut masks = [0; 8];
+    let mut p = 16 * u0;
+
+    for (i, m) in masks.iter_mut().enumerate().take(7).skip(1) {
+        let width = if i < 4 { 23 } else { 15 };
+
+        for _ in 0..width {
+            p = (173 * p + 13849) % 65536;
+            *m = *m << 1 | p >> 15;
+        }
+    }
+
+    masks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_golay() {
+        let g = Golay::new();
+
+        for &data in &[0, 1, 0xfff, 0x123, 0xa5a, 0x800] {
+            let word = golay_encode(data);
+
+            assert_eq!(g.decode(word), (data, 0));
+            assert_eq!(g.decode(word ^ 1 << 22), (data, 1));
+            assert_eq!(g.decode(word ^ 0b101), (data, 2));
+            assert_eq!(g.decode(word ^ (1 << 3 | 1 << 12 | 1 << 20)), (data, 3));
+        }
+
+        // Parity of a single data LSB is the generator without its leading term.
+        assert_eq!(golay_encode(1), 1 << 11 | 0x475);
+    }
+
+    #[test]
+    fn test_hamming() {
+        for &data in &[0, 1, 0x7ff, 0x2aa, 0x555, 0x400] {
+            let word = hamming_encode(data);
+
+            assert_eq!(hamming_decode(word), (data, 0));
+
+            for b in 0..15 {
+                assert_eq!(hamming_decode(word ^ 1 << b), (data, 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pn() {
+        let m = pn_masks(0);
+
+        assert_eq!(m[0], 0);
+        assert_eq!(m[7], 0);
+        assert!(m[1..4].iter().all(|&x| x >> 23 == 0));
+        assert!(m[4..7].iter().all(|&x| x >> 15 == 0));
+
+        // p(1) = 13849 and p(2) = (173 * 13849 + 13849) mod 65536 = 50430.
+        assert_eq!(m[1] >> 21, 0b01);
+        assert!(pn_masks(0x123)[1] != m[1]);
+    }
+}
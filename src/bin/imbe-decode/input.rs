@@ -0,0 +1,376 @@
+//! Parsing of frame capture formats.
+
+use std::fmt;
+
+use imbe::frame::{Chunks, Errors, CHUNK_BITS, MAX_GOLAY_ERRORS, MAX_HAMMING_ERRORS};
+use imbe::ReceivedFrame;
+
+use fec::{self, Golay};
+
+/// Widths in bits of the codewords c<sub>0</sub>, ..., c<sub>7</sub>.
+const CODED_BITS: [u32; 8] = [23, 23, 23, 23, 15, 15, 15, 7];
+
+/// Bytes in an 88-bit packed frame.
+const PACKED_LEN: usize = 11;
+/// Bytes in a 144-bit coded frame.
+const CODED_LEN: usize = 18;
+
+/// Header at the start of an `.imb` file.
+const IMB_MAGIC: &[u8] = b".imb";
+
+/// Supported capture formats.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// One frame per line, given as 22 hex digits of the packed frame. Whitespace is
+    /// ignored and `#` begins a comment.
+    Hex,
+    /// Consecutive 11-byte frames holding u<sub>0</sub>, ..., u<sub>7</sub>, MSB first.
+    Packed,
+    /// Consecutive 18-byte frames holding the deinterleaved codewords c<sub>0</sub>,
+    /// ..., c<sub>7</sub>, MSB first, before error correction and demodulation.
+    Coded,
+    /// DSD-style `.imb` file: a 4-byte header followed by 12-byte records, each an error
+    /// count and an 11-byte packed frame.
+    Imb,
+}
+
+impl Format {
+    /// Parse a format from its name on the command line.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "hex" => Some(Format::Hex),
+            "packed" => Some(Format::Packed),
+            "coded" => Some(Format::Coded),
+            "imb" => Some(Format::Imb),
+            _ => None,
+        }
+    }
+
+    /// Guess a format from the given file name.
+    pub fn from_path(path: &str) -> Option<Format> {
+        let ext = &path[path.rfind('.')? + 1..];
+
+        match ext {
+            "imb" => Some(Format::Imb),
+            "hex" | "txt" => Some(Format::Hex),
+            "bin" => Some(Format::Packed),
+            _ => None,
+        }
+    }
+}
+
+/// Error encountered while parsing a capture.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Input length isn't a whole number of frames.
+    Truncated(usize),
+    /// A hex line was malformed.
+    BadLine(usize),
+    /// An `.imb` file had a missing or unknown header.
+    BadHeader,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Truncated(n) => write!(f, "{} trailing bytes after last frame", n),
+            ParseError::BadLine(n) => write!(f, "line {}: expected 22 hex digits", n),
+            ParseError::BadHeader => write!(f, "missing .imb header"),
+        }
+    }
+}
+
+/// Parse all frames from the given capture data in the given format.
+pub fn parse(data: &[u8], fmt: Format) -> Result<Vec<ReceivedFrame>, ParseError> {
+    match fmt {
+        Format::Hex => parse_hex(data),
+        Format::Packed => parse_records(data, PACKED_LEN, |r| unpack(r, [0; 7])),
+        Format::Coded => {
+            let golay = Golay::new();
+            parse_records(data, CODED_LEN, |r| decode_coded(r, &golay))
+        },
+        Format::Imb => {
+            if !data.starts_with(IMB_MAGIC) {
+                return Err(ParseError::BadHeader);
+            }
+
+            parse_records(&data[IMB_MAGIC.len()..], 1 + PACKED_LEN, |r| {
+                unpack(&r[1..], spread(r[0] as usize))
+            })
+        },
+    }
+}
+
+/// Split the given data into fixed-size records and parse each into a frame.
+fn parse_records<F>(data: &[u8], len: usize, parse: F)
+    -> Result<Vec<ReceivedFrame>, ParseError>
+    where F: Fn(&[u8]) -> ReceivedFrame
+{
+    let rem = data.len() % len;
+
+    if rem != 0 {
+        return Err(ParseError::Truncated(rem));
+    }
+
+    Ok(data.chunks(len).map(parse).collect())
+}
+
+/// Parse frames from lines of hex text.
+fn parse_hex(data: &[u8]) -> Result<Vec<ReceivedFrame>, ParseError> {
+    let text = String::from_utf8_lossy(data);
+    let mut frames = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        let digits = line.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_digit(16))
+            .collect::<Option<Vec<u32>>>();
+
+        let digits = match digits {
+            Some(d) => d,
+            None => return Err(ParseError::BadLine(n + 1)),
+        };
+
+        if digits.is_empty() {
+            continue;
+        }
+
+        if digits.len() != PACKED_LEN * 2 {
+            return Err(ParseError::BadLine(n + 1));
+        }
+
+        let bytes = digits.chunks(2).map(|d| (d[0] << 4 | d[1]) as u8)
+            .collect::<Vec<u8>>();
+
+        frames.push(unpack(&bytes[..], [0; 7]));
+    }
+
+    Ok(frames)
+}
+
+/// Spread the given total error count over the chunks, since `.imb` files record only
+/// the total.
+///
+/// Errors are dealt out one chunk at a time, as evenly as independent bit errors would
+/// land, so large totals reach ϵ<sub>0</sub> and can cause repeats. Each chunk is
+/// credited at most what its code can correct, so any excess over 15 is dropped.
+fn spread(mut total: usize) -> Errors {
+    let mut errors: Errors = [0; 7];
+
+    while total > 0 {
+        let mut dealt = false;
+
+        for (i, e) in errors.iter_mut().enumerate() {
+            let max = if i < 4 { MAX_GOLAY_ERRORS } else { MAX_HAMMING_ERRORS };
+
+            if total > 0 && *e < max {
+                *e += 1;
+                total -= 1;
+                dealt = true;
+            }
+        }
+
+        if !dealt {
+            break;
+        }
+    }
+
+    errors
+}
+
+/// Unpack an 11-byte packed frame with the given error counts.
+fn unpack(bytes: &[u8], errors: Errors) -> ReceivedFrame {
+    let mut bits = BitReader::new(bytes);
+    let mut chunks: Chunks = [0; 8];
+
+    for (c, &w) in chunks.iter_mut().zip(CHUNK_BITS.iter()) {
+        *c = bits.take(w);
+    }
+
+    ReceivedFrame::new(chunks, errors)
+}
+
+/// Error correct and demodulate an 18-byte coded frame.
+fn decode_coded(bytes: &[u8], golay: &Golay) -> ReceivedFrame {
+    let mut bits = BitReader::new(bytes);
+    let mut words = [0; 8];
+
+    for (c, &w) in words.iter_mut().zip(CODED_BITS.iter()) {
+        *c = bits.take(w);
+    }
+
+    let mut chunks: Chunks = [0; 8];
+    let mut errors: Errors = [0; 7];
+
+    // The first codeword isn't modulated and seeds the sequence for the rest.
+    let (u0, e0) = golay.decode(words[0]);
+    chunks[0] = u0;
+    errors[0] = e0;
+
+    let masks = fec::pn_masks(u0);
+
+    for i in 1..4 {
+        let (u, e) = golay.decode(words[i] ^ masks[i]);
+        chunks[i] = u;
+        errors[i] = e;
+    }
+
+    for i in 4..7 {
+        let (u, e) = fec::hamming_decode(words[i] ^ masks[i]);
+        chunks[i] = u;
+        errors[i] = e;
+    }
+
+    chunks[7] = words[7];
+
+    ReceivedFrame::new(chunks, errors)
+}
+
+/// Reads MSB-first bit fields from a byte slice.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader {
+            bytes: bytes,
+            pos: 0,
+        }
+    }
+
+    /// Take the next field of the given width.
+    fn take(&mut self, width: u32) -> u32 {
+        (0..width).fold(0, |word, _| {
+            let bit = self.bytes[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            self.pos += 1;
+            word << 1 | bit as u32
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fec::{golay_encode, hamming_encode, pn_masks};
+
+    const CHUNKS: Chunks = [
+        0b001000010010,
+        0b110011001100,
+        0b111000111000,
+        0b111111111111,
+        0b10100110101,
+        0b00101111010,
+        0b01110111011,
+        0b00001000,
+    ];
+
+    /// Pack the given fields MSB first.
+    fn pack(fields: &[u32], widths: &[u32]) -> Vec<u8> {
+        let mut bits = vec![];
+
+        for (&f, &w) in fields.iter().zip(widths.iter()) {
+            bits.extend((0..w).rev().map(|i| (f >> i & 1) as u8));
+        }
+
+        bits.chunks(8).map(|b| b.iter().fold(0, |s, &x| s << 1 | x)).collect()
+    }
+
+    #[test]
+    fn test_packed() {
+        let bytes = pack(&CHUNKS[..], &CHUNK_BITS[..]);
+        assert_eq!(bytes.len(), 11);
+
+        let mut data = bytes.clone();
+        data.extend(bytes.iter());
+
+        let frames = parse(&data[..], Format::Packed).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].chunks, CHUNKS);
+        assert_eq!(frames[1].errors, [0; 7]);
+
+        assert!(parse(&data[1..], Format::Packed).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        let bytes = pack(&CHUNKS[..], &CHUNK_BITS[..]);
+        let hex = bytes.iter().map(|b| format!("{:02x} ", b)).collect::<String>();
+        let text = format!("# capture\n\n{}\n{} # again\n", hex, hex.replace(" ", ""));
+
+        let frames = parse(text.as_bytes(), Format::Hex).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].chunks, CHUNKS);
+        assert_eq!(frames[1].chunks, CHUNKS);
+
+        assert!(parse(b"0123", Format::Hex).is_err());
+        assert!(parse(b"zz", Format::Hex).is_err());
+    }
+
+    #[test]
+    fn test_imb() {
+        let mut data = b".imb".to_vec();
+        data.push(3);
+        data.extend(pack(&CHUNKS[..], &CHUNK_BITS[..]));
+
+        let frames = parse(&data[..], Format::Imb).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].chunks, CHUNKS);
+        assert_eq!(frames[0].errors, [1, 1, 1, 0, 0, 0, 0]);
+
+        assert!(parse(&data[4..], Format::Imb).is_err());
+
+        // Larger counts reach u0 and u4.
+        data[4] = 10;
+        let frames = parse(&data[..], Format::Imb).unwrap();
+        assert_eq!(frames[0].errors, [2, 2, 2, 1, 1, 1, 1]);
+
+        // Counts beyond what the chunks can hold are clamped.
+        data[4] = 20;
+        let frames = parse(&data[..], Format::Imb).unwrap();
+        assert_eq!(frames[0].errors, [3, 3, 3, 3, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_coded() {
+        let masks = pn_masks(CHUNKS[0]);
+        let mut words = [0; 8];
+
+        for i in 0..4 {
+            words[i] = golay_encode(CHUNKS[i]) ^ masks[i];
+        }
+
+        for i in 4..7 {
+            words[i] = hamming_encode(CHUNKS[i]) ^ masks[i];
+        }
+
+        words[7] = CHUNKS[7];
+
+        // Flip some bits.
+        words[0] ^= 1 << 5;
+        words[2] ^= 0b11;
+        words[5] ^= 1 << 14;
+
+        let bytes = pack(&words[..], &CODED_BITS[..]);
+        assert_eq!(bytes.len(), 18);
+
+        let frames = parse(&bytes[..], Format::Coded).unwrap();
+        assert_eq!(frames[0].chunks, CHUNKS);
+        assert_eq!(frames[0].errors, [1, 0, 2, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::from_path("a/b.imb"), Some(Format::Imb));
+        assert_eq!(Format::from_path("x.txt"), Some(Format::Hex));
+        assert_eq!(Format::from_path("x"), None);
+        assert_eq!(Format::from_name("coded"), Some(Format::Coded));
+        assert_eq!(Format::from_name("wav"), None);
+    }
+}
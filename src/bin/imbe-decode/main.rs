@@ -0,0 +1,262 @@
+//! Decode captured IMBE frames into audio.
+
+extern crate imbe;
+
+mod fec;
+mod input;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use imbe::consts::{SAMPLE_RATE, SAMPLES_PER_FRAME};
+use imbe::resample::{self, Resampler};
+use imbe::sink::{self, PcmSink};
+use imbe::{DecodeStatus, ImbeDecoder, Sink, StreamDecoder, Threading};
+
+use input::Format;
+
+const USAGE: &str = "\
+Usage: imbe-decode [OPTIONS] [INPUT]
+
+Decode IMBE voice frames from INPUT (or stdin) into 16-bit mono audio.
+
+Options:
+    -f, --format FMT   input format: hex, packed, coded, imb
+                       (default: guessed from INPUT extension, else packed)
+                       imb error totals are spread evenly over the chunks,
+                       and totals above 15 are clamped
+    -o, --output FILE  write audio to FILE instead of stdout
+    -t, --type TYPE    output type: wav, raw (default: wav)
+    -r, --rate HZ      output sample rate (default: 8000)
+    -g, --gain DB      output gain in dB (default: 0)
+    -q, --quiet        don't print a summary of decoded frames
+    -h, --help         print this message
+";
+
+/// Output container type.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OutputType {
+    Wav,
+    Raw,
+}
+
+/// Command line options.
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    format: Option<Format>,
+    kind: OutputType,
+    rate: usize,
+    gain: f32,
+    quiet: bool,
+}
+
+impl Options {
+    /// Parse options from the given arguments.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut opts = Options {
+            input: None,
+            output: None,
+            format: None,
+            kind: OutputType::Wav,
+            rate: SAMPLE_RATE,
+            gain: 0.0,
+            quiet: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next().ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match &arg[..] {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                },
+                "-q" | "--quiet" => opts.quiet = true,
+                "-f" | "--format" => {
+                    let v = value(&arg)?;
+                    opts.format = Some(Format::from_name(&v)
+                        .ok_or_else(|| format!("unknown input format '{}'", v))?);
+                },
+                "-o" | "--output" => opts.output = Some(value(&arg)?),
+                "-t" | "--type" => {
+                    opts.kind = match &value(&arg)?[..] {
+                        "wav" => OutputType::Wav,
+                        "raw" => OutputType::Raw,
+                        v => return Err(format!("unknown output type '{}'", v)),
+                    };
+                },
+                "-r" | "--rate" => {
+                    let v = value(&arg)?;
+                    opts.rate = match v.parse() {
+                        Ok(r) if r > 0 => r,
+                        _ => return Err(format!("invalid rate '{}'", v)),
+                    };
+                },
+                "-g" | "--gain" => {
+                    let v = value(&arg)?;
+                    opts.gain = v.parse().map_err(|_| format!("invalid gain '{}'", v))?;
+                },
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option '{}'", arg));
+                },
+                _ if opts.input.is_none() => opts.input = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Scales samples by a fixed gain before passing them on.
+struct Gain<S: Sink> {
+    sink: S,
+    gain: f32,
+    buf: Vec<f32>,
+}
+
+impl<S: Sink> Sink for Gain<S> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let gain = self.gain;
+
+        self.buf.clear();
+        self.buf.extend(samples.iter().map(|&s| s * gain));
+        self.sink.write(&self.buf[..])
+    }
+
+    fn finish(&mut self) -> io::Result<()> { self.sink.finish() }
+}
+
+/// Counts how frames were handled by the decoder.
+#[derive(Default)]
+struct Summary {
+    voice: usize,
+    repeat: usize,
+    mute: usize,
+    silence: usize,
+    invalid: usize,
+}
+
+impl Summary {
+    /// Record the given handling of a frame.
+    fn tally(&mut self, status: &DecodeStatus) {
+        match *status {
+            DecodeStatus::Voice(_) => self.voice += 1,
+            DecodeStatus::InvalidPeriod | DecodeStatus::Repeat(_) => self.repeat += 1,
+            DecodeStatus::Mute(_) => self.mute += 1,
+            DecodeStatus::Silence => self.silence += 1,
+        }
+    }
+}
+
+/// Compute the WAV data length in bytes of the given number of samples, or `None` if the
+/// lengths don't fit in the header, so the streaming placeholder lengths are used instead.
+fn wav_data_len(samples: usize) -> Option<u32> {
+    // The RIFF length also covers the 36 bytes of header after it.
+    samples.checked_mul(2)
+        .filter(|&d| d <= (u32::MAX - 36) as usize)
+        .map(|d| d as u32)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("imbe-decode: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let opts = Options::parse(env::args().skip(1))?;
+
+    let input = opts.input.as_ref().filter(|p| *p != "-");
+
+    let mut data = vec![];
+
+    match input {
+        Some(path) => File::open(path).and_then(|mut f| f.read_to_end(&mut data)),
+        None => io::stdin().read_to_end(&mut data),
+    }.map_err(|e| format!("unable to read input: {}", e))?;
+
+    let format = opts.format
+        .or_else(|| input.and_then(|p| Format::from_path(p)))
+        .unwrap_or(Format::Packed);
+
+    let frames = input::parse(&data[..], format).map_err(|e| e.to_string())?;
+
+    let out: Box<dyn Write> = match opts.output.as_ref().filter(|p| *p != "-") {
+        Some(path) => Box::new(File::create(path)
+            .map_err(|e| format!("unable to create {}: {}", path, e))?),
+        None => Box::new(io::stdout()),
+    };
+
+    let mut out = BufWriter::new(out);
+
+    if opts.kind == OutputType::Wav {
+        let input = frames.len() * SAMPLES_PER_FRAME;
+
+        // The stream decoder drains the resampler at the end, if it resamples.
+        let samples = if opts.rate == SAMPLE_RATE {
+            input
+        } else {
+            Resampler::new(opts.rate).map_err(|e| e.to_string())?
+                .output_len(input + resample::DRAIN_LEN)
+        };
+
+        sink::write_wav_header(&mut out, opts.rate, wav_data_len(samples))
+            .map_err(|e| e.to_string())?;
+    }
+
+    let sink = Gain {
+        sink: PcmSink::new(out),
+        gain: 10.0f32.powf(opts.gain / 20.0),
+        buf: vec![],
+    };
+
+    let decoder = ImbeDecoder::with_threading(Threading::Single);
+    let mut dec = StreamDecoder::with_decoder(decoder, sink, opts.rate)
+        .map_err(|e| e.to_string())?;
+    let mut summary = Summary::default();
+
+    for frame in frames {
+        match dec.push(frame) {
+            Ok(status) => summary.tally(&status),
+            // Conceal a frame that can't be decoded so the output keeps its length.
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                dec.push_lost().map_err(|e| format!("unable to write output: {}", e))?;
+                summary.invalid += 1;
+            },
+            Err(e) => return Err(format!("unable to write output: {}", e)),
+        }
+    }
+
+    dec.finish().map_err(|e| format!("unable to write output: {}", e))?;
+
+    if !opts.quiet {
+        eprintln!("{} frames: {} voice, {} repeated, {} muted, {} silence, {} invalid",
+                  summary.voice + summary.repeat + summary.mute + summary.silence +
+                      summary.invalid,
+                  summary.voice, summary.repeat, summary.mute, summary.silence,
+                  summary.invalid);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wav_data_len() {
+        assert_eq!(wav_data_len(0), Some(0));
+        assert_eq!(wav_data_len(1000), Some(2000));
+        assert_eq!(wav_data_len((u32::MAX as usize - 36) / 2), Some(u32::MAX - 37));
+        assert_eq!(wav_data_len((u32::MAX as usize - 36) / 2 + 1), None);
+        assert_eq!(wav_data_len(usize::MAX), None);
+    }
+}
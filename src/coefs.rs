@@ -1,6 +1,6 @@
 //! Higher order DCT coefficients.
 
-use std::f32::consts::PI;
+use core::f32::consts::PI;
 
 use arrayvec::ArrayVec;
 
@@ -8,6 +8,7 @@ use allocs::allocs;
 use consts::{MIN_HARMONICS, MAX_HARMONICS};
 use descramble::QuantizedAmplitudes;
 use gain::Gains;
+use math;
 use params::BaseParams;
 
 /// Higher order DCT coefficients vector T<sub>l</sub>, 1 ≤ l ≤ L.
@@ -93,9 +94,9 @@ impl CoefBlock {
         assert!(j >= 1 && j <= self.len());
 
         self.0[0] + 2.0 * (2..=self.len()).map(|k| {
-            self.0[k - 1] * (
+            self.0[k - 1] * math::cos(
                 PI * (k as f32 - 1.0) * (j as f32 - 0.5) / self.len() as f32
-            ).cos()
+            )
         }).fold(0.0, |s, x| s + x)
     }
 }
@@ -103,7 +104,7 @@ impl CoefBlock {
 /// Each AMPS_USED[l] gives J<sub>1</sub> - 1,..,,J<sub>6</sub> - 1 for harmonics
 /// parameter l = L - 9. Each J<sub>i</sub> - 1 represents the number of quantized
 /// amplitudes used in coefficient block i.
-static AMPS_USED: [[usize; 6]; 48] = [
+pub static AMPS_USED: [[usize; 6]; 48] = [
     [0, 0, 0, 1, 1, 1],
     [0, 0, 1, 1, 1, 1],
     [0, 1, 1, 1, 1, 1],
@@ -156,7 +157,7 @@ static AMPS_USED: [[usize; 6]; 48] = [
 
 /// Each DCT_STEP_SIZE[b] is the "uniform quantizer step size" [p31] for the bit
 /// allocation b = B<sub>m</sub> - 1.
-const DCT_STEP_SIZE: [f32; 10] = [
+pub const DCT_STEP_SIZE: [f32; 10] = [
     1.2,
     0.85,
     0.65,
@@ -171,7 +172,7 @@ const DCT_STEP_SIZE: [f32; 10] = [
 
 /// Each DCT_STD_DEV[j] is the DCT standard deviation [p32] for the coefficient
 /// C<sub>i,j+2</sub>.
-const DCT_STD_DEV: [f32; 9] = [
+pub const DCT_STD_DEV: [f32; 9] = [
     0.307,
     0.241,
     0.207,
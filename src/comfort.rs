@@ -0,0 +1,307 @@
+//! Comfort noise for silent and muted frames.
+//!
+//! Rather than exact zeros, silent and muted frames can be filled with low-level noise
+//! shaped like the background noise of the call, so listeners can tell the call is still
+//! connected. The background spectral envelope is estimated from the unvoiced spectrum
+//! U<sub>w</sub>(m) of decoded frames by following the quietest recent power in each
+//! band, and the noise is synthesized with the same weighted overlap-add as unvoiced
+//! speech.
+
+use rand::Rng;
+
+use complex::Complex32;
+use consts::SAMPLES_PER_FRAME;
+use frame::AudioBuf;
+use math;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
+use unvoiced::{self, IdftMethod, Unvoiced, UnvoicedDft, DFT_HALF};
+
+/// Number of bands in the background spectral envelope.
+const BANDS: usize = 16;
+/// Number of DFT points in each band.
+const BAND_SIZE: usize = DFT_HALF / BANDS;
+/// Background power of each DFT point before any unvoiced content has been seen, which
+/// gives noise roughly 70dB below full scale.
+const INITIAL_POWER: f32 = 2.7e4;
+/// Weight of a band power that's below the current estimate.
+const FALL: f32 = 0.5;
+/// Greatest factor the estimate can grow by in each frame, so unvoiced speech raises it
+/// only slowly, by at most about 4dB per second.
+const RISE: f32 = 1.02;
+/// Number of samples over which noise ramps in after speech.
+const RAMP_IN: usize = 2 * SAMPLES_PER_FRAME;
+
+/// Generates comfort noise from an estimate of the background spectral envelope.
+#[derive(Clone)]
+pub struct ComfortNoise {
+    /// Linear gain applied to the estimated background.
+    gain: f32,
+    /// Estimated background power of each DFT point in each band.
+    envelope: [f32; BANDS],
+    /// Spectrum of the previous noise frame, for overlap-add.
+    prev: UnvoicedDft,
+    /// Ramp level at the end of the previous frame, from 0 (no noise) to 1.
+    ramp: f32,
+}
+
+impl ComfortNoise {
+    /// Create a new `ComfortNoise` that generates noise at the given level, in dB
+    /// relative to the estimated background noise.
+    pub fn new(level: f32) -> ComfortNoise {
+        ComfortNoise {
+            gain: gain(level),
+            envelope: [INITIAL_POWER; BANDS],
+            prev: UnvoicedDft::default(),
+            ramp: 0.0,
+        }
+    }
+
+    /// Set the level of generated noise, in dB relative to the estimated background
+    /// noise.
+    pub fn set_level(&mut self, level: f32) {
+        self.gain = gain(level);
+    }
+
+    /// Update the background envelope from the given unvoiced spectrum of a decoded
+    /// frame.
+    pub fn track(&mut self, udft: &UnvoicedDft) {
+        for (env, band) in self.envelope.iter_mut().zip(udft.dft().chunks(BAND_SIZE)) {
+            // Voiced harmonics leave their DFT points at zero, so only unvoiced points
+            // are measured.
+            let (sum, count) = band.iter()
+                .map(|x| x.norm_sqr())
+                .filter(|&p| p > 0.0)
+                .fold((0.0, 0), |(s, c), p| (s + p, c + 1));
+
+            if count == 0 {
+                continue;
+            }
+
+            let power = sum / count as f32;
+
+            *env = if power < *env {
+                *env + FALL * (power - *env)
+            } else {
+                power.min(RISE * *env)
+            };
+        }
+    }
+
+    /// Add comfort noise to the given frame, ramping in if the previous frame was
+    /// speech.
+    pub fn fill<R: Rng>(&mut self, rng: &mut R, buf: &mut AudioBuf) {
+        let start = self.ramp;
+        self.synthesize(rng, buf, |n| (start + (n + 1) as f32 / RAMP_IN as f32).min(1.0));
+    }
+
+    /// Add the tail of any comfort noise from previous frames to the given frame of
+    /// speech, ramping out over the frame.
+    pub fn fade_out<R: Rng>(&mut self, rng: &mut R, buf: &mut AudioBuf) {
+        if self.ramp == 0.0 {
+            return;
+        }
+
+        let start = self.ramp;
+        self.synthesize(rng, buf, |n| {
+            start * (1.0 - (n + 1) as f32 / SAMPLES_PER_FRAME as f32)
+        });
+
+        // Start from an empty spectrum the next time noise ramps in.
+        self.prev = UnvoicedDft::default();
+    }
+
+    /// Synthesize a frame of noise and add it to the given buffer, scaled at each sample
+    /// n by the ramp level given by the given function.
+    fn synthesize<R, F>(&mut self, rng: &mut R, buf: &mut AudioBuf, ramp: F)
+        where R: Rng, F: Fn(usize) -> f32
+    {
+        let mut dft = [Complex32::zero(); DFT_HALF];
+
+        for (m, x) in dft.iter_mut().enumerate() {
+            // Each part has half the power of the point.
+            let std_dev = self.gain * math::sqrt(self.envelope[m / BAND_SIZE] / 2.0);
+            *x = unvoiced::gaussian(rng, std_dev);
+        }
+
+        let cur = UnvoicedDft::from_dft(dft);
+
+        {
+            let noise = Unvoiced::with_method(&cur, &self.prev, IdftMethod::Fft);
+
+            for (n, x) in buf.iter_mut().enumerate() {
+                *x += ramp(n) * noise.get(n);
+            }
+        }
+
+        self.prev = cur;
+        self.ramp = ramp(SAMPLES_PER_FRAME - 1);
+    }
+}
+
+impl Persist for ComfortNoise {
+    fn save(&self, w: &mut Writer) {
+        w.f32(self.gain);
+        w.f32s(&self.envelope[..]);
+        self.prev.save(w);
+        w.f32(self.ramp);
+    }
+
+    fn load(r: &mut Reader) -> Result<ComfortNoise, SnapshotError> {
+        let gain = r.f32()?;
+        let mut envelope = [0.0; BANDS];
+        r.f32s(&mut envelope[..])?;
+
+        let c = ComfortNoise {
+            gain: gain,
+            envelope: envelope,
+            prev: UnvoicedDft::load(r)?,
+            ramp: r.f32()?,
+        };
+
+        // The gain and envelope set the deviation of the noise, and the ramp is a level.
+        let valid = |x: f32| x.is_finite() && x >= 0.0;
+
+        if !valid(c.gain) || !c.envelope.iter().all(|&e| valid(e)) ||
+            !(0.0..=1.0).contains(&c.ramp)
+        {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(c)
+    }
+}
+
+/// Convert the given level in dB to a linear amplitude gain.
+fn gain(level: f32) -> f32 {
+    math::powf(10.0, level / 20.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    /// Compute the RMS level of the given samples.
+    fn rms(x: &[f32]) -> f32 {
+        (x.iter().fold(0.0, |s, &x| s + x * x) / x.len() as f32).sqrt()
+    }
+
+    /// Create a spectrum with the given power in the points of the first half of bands.
+    fn spectrum(power: f32) -> UnvoicedDft {
+        let mut dft = [Complex32::zero(); DFT_HALF];
+
+        for x in dft[..DFT_HALF / 2].iter_mut() {
+            *x = Complex32::new(power.sqrt(), 0.0);
+        }
+
+        UnvoicedDft::from_dft(dft)
+    }
+
+    #[test]
+    fn test_track() {
+        let mut c = ComfortNoise::new(0.0);
+
+        // Quieter content pulls the estimate down quickly.
+        for _ in 0..20 {
+            c.track(&spectrum(100.0));
+        }
+
+        assert!(c.envelope[..BANDS / 2].iter().all(|&e| (e - 100.0).abs() < 1.0));
+        assert!(c.envelope[BANDS / 2..].iter().all(|&e| e == INITIAL_POWER));
+
+        // Louder content raises it slowly.
+        c.track(&spectrum(1e6));
+        assert!(c.envelope[0] > 100.0 && c.envelope[0] < 110.0);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut c = ComfortNoise::new(0.0);
+        let mut bufs = vec![];
+
+        for _ in 0..10 {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            c.fill(&mut rng, &mut buf);
+            bufs.push(buf);
+        }
+
+        // Noise ramps in over the first frames, then settles to about 70dB below full
+        // scale.
+        assert!(rms(&bufs[0][..40]) < rms(&bufs[1][..]));
+
+        let level = rms(&bufs[2..].iter().flat_map(|b| b.iter().cloned())
+                            .collect::<Vec<_>>()[..]);
+
+        assert!(level > 5.0 && level < 20.0);
+
+        // A lower level scales the noise.
+        let mut quiet = ComfortNoise::new(-20.0);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        for _ in 0..4 {
+            buf = [0.0; SAMPLES_PER_FRAME];
+            quiet.fill(&mut rng, &mut buf);
+        }
+
+        assert!(rms(&buf[..]) < 0.3 * level);
+    }
+
+    #[test]
+    fn test_fade_out() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut c = ComfortNoise::new(0.0);
+
+        // Nothing is added without preceding noise.
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        c.fade_out(&mut rng, &mut buf);
+        assert!(buf.iter().all(|&x| x == 0.0));
+
+        for _ in 0..4 {
+            c.fill(&mut rng, &mut buf);
+        }
+
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        c.fade_out(&mut rng, &mut buf);
+
+        assert!(buf[..40].iter().any(|&x| x != 0.0));
+        assert_eq!(buf[SAMPLES_PER_FRAME - 1], 0.0);
+        assert!(rms(&buf[120..]) < rms(&buf[..40]));
+
+        // The noise has ended.
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        c.fade_out(&mut rng, &mut buf);
+        assert!(buf.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_persist() {
+        let load = |c: &ComfortNoise| {
+            let mut buf = [0; 2048];
+
+            let len = {
+                let mut w = Writer::new(&mut buf);
+                c.save(&mut w);
+                w.finish().unwrap()
+            };
+
+            let mut r = Reader::new(&buf[..len]).unwrap();
+            ComfortNoise::load(&mut r).map(|c| (c.gain, c.envelope, c.ramp))
+        };
+
+        let mut c = ComfortNoise::new(-6.0);
+        c.track(&spectrum(100.0));
+        assert_eq!(load(&c), Ok((c.gain, c.envelope, c.ramp)));
+
+        // Negative or non-finite envelopes would give an invalid noise deviation.
+        c.envelope[3] = -1.0;
+        assert_eq!(load(&c).err(), Some(SnapshotError::InvalidValue));
+
+        c.envelope[3] = f32::INFINITY;
+        assert_eq!(load(&c).err(), Some(SnapshotError::InvalidValue));
+
+        c.envelope[3] = 1.0;
+        c.ramp = 2.0;
+        assert_eq!(load(&c).err(), Some(SnapshotError::InvalidValue));
+    }
+}
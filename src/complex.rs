@@ -0,0 +1,85 @@
+//! Complex numbers.
+
+use core::ops::{Add, Mul, Sub};
+
+/// Complex number with `f32` parts, laid out as the real part followed by the imaginary
+/// part so slices can be read as interleaved pairs.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[repr(C)]
+pub struct Complex32 {
+    /// Real part.
+    pub re: f32,
+    /// Imaginary part.
+    pub im: f32,
+}
+
+impl Complex32 {
+    /// Create a new `Complex32` with the given real and imaginary parts.
+    pub fn new(re: f32, im: f32) -> Complex32 {
+        Complex32 {
+            re: re,
+            im: im,
+        }
+    }
+
+    /// Create a new `Complex32` equal to zero.
+    pub fn zero() -> Complex32 {
+        Complex32::new(0.0, 0.0)
+    }
+
+    /// Compute the squared magnitude |z|<sup>2</sup>.
+    pub fn norm_sqr(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Add for Complex32 {
+    type Output = Complex32;
+
+    fn add(self, other: Complex32) -> Complex32 {
+        Complex32::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex32 {
+    type Output = Complex32;
+
+    fn sub(self, other: Complex32) -> Complex32 {
+        Complex32::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex32 {
+    type Output = Complex32;
+
+    fn mul(self, other: Complex32) -> Complex32 {
+        Complex32::new(self.re * other.re - self.im * other.im,
+                       self.re * other.im + self.im * other.re)
+    }
+}
+
+impl Mul<Complex32> for f32 {
+    type Output = Complex32;
+
+    fn mul(self, other: Complex32) -> Complex32 {
+        Complex32::new(self * other.re, self * other.im)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_arith() {
+        let a = Complex32::new(1.0, 2.0);
+        let b = Complex32::new(-3.0, 0.5);
+
+        assert_eq!(a + b, Complex32::new(-2.0, 2.5));
+        assert_eq!(a - b, Complex32::new(4.0, 1.5));
+        assert_eq!(a * b, Complex32::new(-4.0, -5.5));
+        assert_eq!(2.0 * a, Complex32::new(2.0, 4.0));
+        assert_eq!(a.norm_sqr(), 5.0);
+        assert_eq!(Complex32::zero(), Complex32::default());
+    }
+}
@@ -1,153 +1,1818 @@
 //! Decode IMBE frames into an audio signal.
 
-use std::sync::Arc;
+#[cfg(feature = "std")]
+use core::cmp::max;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
-use collect_slice::CollectSlice;
+#[cfg(feature = "std")]
 use crossbeam;
+#[cfg(feature = "std")]
 use rand;
+use arrayvec::ArrayVec;
+use rand::{Rng, XorShiftRng};
 
 use coefs::Coefficients;
+use comfort::ComfortNoise;
 use consts::SAMPLES_PER_FRAME;
-use descramble::{descramble, Bootstrap};
+use descramble::{try_descramble, Bootstrap};
 use enhance::{self, EnhancedSpectrals, FrameEnergy, EnhanceErrors};
-use frame::{AudioBuf, ReceivedFrame};
+use frame::{AudioBuf, FrameError, ReceivedFrame};
 use gain::Gains;
+use kernel::BATCH_LANES;
 use params::BaseParams;
+use pcm::{self, Dither, PcmBuf};
+use policy::{ErrorPolicy, Standard};
+#[cfg(feature = "std")]
+use pool::{Parts, WorkerPool};
 use prev::PrevFrame;
+#[cfg(feature = "std")]
+use quality::Governor;
+use quality::Quality;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 use spectral::Spectrals;
-use unvoiced::{UnvoicedDft, Unvoiced};
-use voiced::{Phase, PhaseBase, Voiced};
+use stats::Stats;
+use unvoiced::{IdftMethod, UnvoicedDft, Unvoiced};
+use voiced::{Phase, PhaseBase, Voiced, VoicedMethod};
 
+/// Default number of consecutive lost frames over which concealment fades out before
+/// muting.
+const LOSS_MUTE: u32 = 6;
 /// Number of threads to spin up per frame.
+#[cfg(feature = "std")]
 const THREADS: usize = 4;
 /// Number of samples to process in each thread.
+#[cfg(feature = "std")]
 const SAMPLES_PER_THREAD: usize = SAMPLES_PER_FRAME / THREADS;
 
+/// Determines how the synthesis of each frame is distributed across threads.
+///
+/// All modes produce identical output. Without the `std` feature, only `Single` is
+/// available.
+#[derive(Clone)]
+pub enum Threading {
+    /// Synthesize on the calling thread.
+    Single,
+    /// Spawn `THREADS` scoped threads for each frame.
+    #[cfg(feature = "std")]
+    Scoped,
+    /// Split synthesis of each frame into `THREADS` jobs on the given long-lived pool,
+    /// which can be shared among many decoders.
+    ///
+    /// Each decoder reuses the same storage for its jobs, so no allocation happens per
+    /// frame, but the calling thread blocks until the pool gets through the jobs queued
+    /// ahead of them. Where latency must be bounded, prefer `Single`.
+    #[cfg(feature = "std")]
+    Pool(WorkerPool),
+}
+
+impl Default for Threading {
+    /// Spawn scoped threads for each frame.
+    #[cfg(feature = "std")]
+    fn default() -> Threading { Threading::Scoped }
+
+    /// Synthesize on the calling thread, the only mode available without `std`.
+    #[cfg(not(feature = "std"))]
+    fn default() -> Threading { Threading::Single }
+}
+
 /// Decodes a stream of IMBE frames.
-pub struct ImbeDecoder {
+///
+/// The random terms of unvoiced synthesis and voiced phase are drawn from the owned
+/// generator `R`, so decoders created with identically seeded generators produce
+/// identical audio for identical frames. Frames with errors are concealed according to
+/// the policy `P`, which follows the standard by default.
+pub struct ImbeDecoder<R: Rng = XorShiftRng, P: ErrorPolicy = Standard> {
     /// Tracks saved parameters across frames.
     prev: PrevFrame,
+    /// Threading mode used for synthesis.
+    threading: Threading,
+    /// Storage shared with pool threads, in `Threading::Pool` mode.
+    #[cfg(feature = "std")]
+    slot: Option<Arc<SynthSlot>>,
+    /// Method used to evaluate the unvoiced IDFT.
+    idft: IdftMethod,
+    /// Method used to synthesize harmonics voiced in consecutive frames.
+    voiced: VoicedMethod,
+    /// Best quality level to decode at.
+    quality: Quality,
+    /// Lowers the quality level when decoding exceeds a time budget, if one is set.
+    #[cfg(feature = "std")]
+    governor: Option<Governor>,
+    /// Fills silent and muted frames with noise, if enabled.
+    comfort: Option<ComfortNoise>,
+    /// Whether the saved parameters are the quiet state of a silent or muted frame.
+    quiet: bool,
+    /// Number of consecutive lost frames, reset when a frame is received.
+    lost: u32,
+    /// Number of consecutive lost frames after which output is muted.
+    loss_mute: u32,
+    /// Collects statistics of decoded frames, if enabled.
+    stats: Option<Stats>,
+    /// Decides how frames with errors are concealed.
+    policy: P,
+    /// Source of random terms in synthesis.
+    rng: R,
 }
 
+#[cfg(feature = "std")]
 impl ImbeDecoder {
-    /// Create a new `ImbeDecoder` in the default state.
+    /// Create a new `ImbeDecoder` in the default state, which spawns scoped threads for
+    /// the synthesis of each frame and draws random terms from a generator seeded by the
+    /// OS.
     pub fn new() -> ImbeDecoder {
+        Self::with_threading(Threading::Scoped)
+    }
+
+    /// Create a new `ImbeDecoder` in the default state that synthesizes frames with the
+    /// given threading mode and draws random terms from a generator seeded by the OS.
+    pub fn with_threading(threading: Threading) -> ImbeDecoder {
+        ImbeDecoder::with_rng_threading(rand::weak_rng(), threading)
+    }
+}
+
+impl<R: Rng> ImbeDecoder<R> {
+    /// Create a new `ImbeDecoder` in the default state that draws random terms from the
+    /// given generator and synthesizes frames with the default threading mode.
+    pub fn with_rng(rng: R) -> ImbeDecoder<R> {
+        Self::with_rng_threading(rng, Threading::default())
+    }
+
+    /// Create a new `ImbeDecoder` in the default state that draws random terms from the
+    /// given generator and synthesizes frames with the given threading mode.
+    pub fn with_rng_threading(rng: R, threading: Threading) -> ImbeDecoder<R> {
+        #[cfg(feature = "std")]
+        let slot = match threading {
+            Threading::Pool(_) => Some(Arc::new(SynthSlot::new())),
+            _ => None,
+        };
+
         ImbeDecoder {
             prev: PrevFrame::default(),
+            threading: threading,
+            #[cfg(feature = "std")]
+            slot: slot,
+            idft: IdftMethod::Direct,
+            voiced: VoicedMethod::OverlapAdd,
+            quality: Quality::Full,
+            #[cfg(feature = "std")]
+            governor: None,
+            comfort: None,
+            quiet: true,
+            lost: 0,
+            loss_mute: LOSS_MUTE,
+            stats: None,
+            policy: Standard,
+            rng: rng,
+        }
+    }
+}
+
+impl<R: Rng, P: ErrorPolicy> ImbeDecoder<R, P> {
+    /// Convert into a decoder in the same state that conceals frames with errors
+    /// according to the given policy.
+    pub fn with_policy<Q: ErrorPolicy>(self, policy: Q) -> ImbeDecoder<R, Q> {
+        ImbeDecoder {
+            prev: self.prev,
+            threading: self.threading,
+            #[cfg(feature = "std")]
+            slot: self.slot,
+            idft: self.idft,
+            voiced: self.voiced,
+            quality: self.quality,
+            #[cfg(feature = "std")]
+            governor: self.governor,
+            comfort: self.comfort,
+            quiet: self.quiet,
+            lost: self.lost,
+            loss_mute: self.loss_mute,
+            stats: self.stats,
+            policy: policy,
+            rng: self.rng,
+        }
+    }
+
+    /// Set the method used to evaluate the unvoiced IDFT in subsequent frames.
+    ///
+    /// `IdftMethod::Fft` is considerably cheaper and matches the default
+    /// `IdftMethod::Direct` to within floating-point roundoff.
+    pub fn set_idft_method(&mut self, method: IdftMethod) {
+        self.idft = method;
+    }
+
+    /// Set the method used to synthesize harmonics that are voiced in both the previous
+    /// and current frames in subsequent frames.
+    ///
+    /// `VoicedMethod::Interpolate` avoids the phasiness and mid-frame amplitude dips of
+    /// the default `VoicedMethod::OverlapAdd` when the pitch is steady.
+    pub fn set_voiced_method(&mut self, method: VoicedMethod) {
+        self.voiced = method;
+    }
+
+    /// Set the best quality level used to decode subsequent frames.
+    ///
+    /// With a time budget set, the decoder may still drop to cheaper levels.
+    pub fn set_quality(&mut self, quality: Quality) {
+        self.quality = quality;
+    }
+
+    /// Retrieve the quality level the next frame will be decoded at.
+    #[cfg(feature = "std")]
+    pub fn quality(&self) -> Quality {
+        match self.governor {
+            Some(ref g) => max(self.quality, g.level()),
+            None => self.quality,
+        }
+    }
+
+    /// Retrieve the quality level the next frame will be decoded at.
+    #[cfg(not(feature = "std"))]
+    pub fn quality(&self) -> Quality { self.quality }
+
+    /// Set the time budget for decoding each frame, or remove it with `None`.
+    ///
+    /// With a budget, each call to `decode` is timed, and the decoder steps down to
+    /// cheaper quality levels while the average time spent on recent frames exceeds the
+    /// budget, then steps back up once it's comfortably within it.
+    #[cfg(feature = "std")]
+    pub fn set_budget(&mut self, budget: Option<Duration>) {
+        self.governor = budget.map(Governor::new);
+    }
+
+    /// Set the level of comfort noise, in dB relative to the estimated background noise,
+    /// or disable it with `None`.
+    ///
+    /// With comfort noise, silent and muted frames are filled with low-level noise shaped
+    /// like the background of recent unvoiced content rather than exact zeros, ramping in
+    /// after speech and back out when speech resumes.
+    pub fn set_comfort_noise(&mut self, level: Option<f32>) {
+        // Keep the tracked background when only the level changes.
+        if let (Some(level), Some(c)) = (level, self.comfort.as_mut()) {
+            c.set_level(level);
+            return;
+        }
+
+        self.comfort = level.map(ComfortNoise::new);
+    }
+
+    /// Set the number of consecutive lost frames over which `decode_lost` fades out
+    /// before muting.
+    pub fn set_loss_mute(&mut self, frames: u32) {
+        self.loss_mute = frames;
+    }
+
+    /// Enable or disable the collection of statistics of decoded frames.
+    ///
+    /// Enabling collection that's already enabled keeps the statistics collected so far.
+    pub fn set_stats(&mut self, enabled: bool) {
+        if !enabled {
+            self.stats = None;
+        } else if self.stats.is_none() {
+            self.stats = Some(Stats::new());
+        }
+    }
+
+    /// Retrieve the statistics collected so far, if enabled.
+    ///
+    /// These can be cloned to keep a snapshot while decoding continues.
+    pub fn stats(&self) -> Option<&Stats> { self.stats.as_ref() }
+
+    /// Clear the statistics collected so far, if enabled, such as at the start of a new
+    /// call.
+    pub fn reset_stats(&mut self) {
+        if let Some(ref mut s) = self.stats {
+            *s = Stats::new();
+        }
+    }
+
+    /// Save the state carried between frames as a snapshot in the given buffer,
+    /// returning the length of the snapshot.
+    ///
+    /// The snapshot covers the parameters saved from the previous frame, the quiet and
+    /// loss state, and any comfort noise background and statistics, in at most
+    /// `snapshot::MAX_SNAPSHOT_LEN` bytes. The threading mode, synthesis methods,
+    /// quality, budget, loss muting, policy, and generator aren't included, so these
+    /// should be configured the same way on the decoder that restores it.
+    pub fn snapshot(&self, buf: &mut [u8]) -> Result<usize, SnapshotError> {
+        let mut w = Writer::new(buf);
+
+        self.prev.save(&mut w);
+        w.bool(self.quiet);
+        w.u32(self.lost);
+        self.comfort.save(&mut w);
+        self.stats.save(&mut w);
+
+        w.finish()
+    }
+
+    /// Restore the state from the given snapshot, so decoding continues as it would
+    /// have on the decoder that saved it.
+    ///
+    /// On error, the decoder state is unchanged.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = Reader::new(snapshot)?;
+
+        let prev = PrevFrame::load(&mut r)?;
+        let quiet = r.bool()?;
+        let lost = r.u32()?;
+        let comfort = Option::load(&mut r)?;
+        let stats = Option::load(&mut r)?;
+
+        r.finish()?;
+
+        self.prev = prev;
+        self.quiet = quiet;
+        self.lost = lost;
+        self.comfort = comfort;
+        self.stats = stats;
+
+        Ok(())
+    }
+
+    /// Decode the given frame into the given audio sample buffer, returning how the
+    /// frame was handled.
+    ///
+    /// Samples are written on the 16-bit PCM scale described in the `pcm` module. The
+    /// error counts ϵ<sub>i</sub> are trusted as given, even beyond what each chunk's
+    /// code can correct, and only raise the error rate and the chance of a repeat or
+    /// mute. Panics if the frame can't be descrambled, as described in `try_decode`.
+    pub fn decode(&mut self, frame: ReceivedFrame, buf: &mut AudioBuf) -> DecodeStatus {
+        match self.decode_frame(frame, buf) {
+            Ok(status) => status,
+            Err(e) => panic!("unable to decode frame: {}", e),
+        }
+    }
+
+    /// Decode the given frame into the given audio sample buffer as in `decode`, first
+    /// checking the frame as in `ReceivedFrame::check`, so unlike `decode` it rejects
+    /// error counts beyond what each chunk's code can correct.
+    ///
+    /// On error, the buffer and decoder state are unchanged, so decoding can continue
+    /// with the next frame or with `decode_lost` in place of this one.
+    pub fn try_decode(&mut self, frame: ReceivedFrame, buf: &mut AudioBuf)
+        -> Result<DecodeStatus, FrameError>
+    {
+        frame.check()?;
+        self.decode_frame(frame, buf)
+    }
+
+    /// Decode the given frame into the given audio sample buffer, failing if it can't be
+    /// descrambled.
+    fn decode_frame(&mut self, frame: ReceivedFrame, buf: &mut AudioBuf)
+        -> Result<DecodeStatus, FrameError>
+    {
+        #[cfg(feature = "std")]
+        let start = self.governor.as_ref().map(|_| Instant::now());
+
+        let (status, a) = self.analyze(&frame)?;
+
+        if let Some(ref mut s) = self.stats {
+            s.record(&frame, &status, &a.cur);
+        }
+
+        if self.synthesizes(&a) {
+            self.synthesize(&a, buf);
+        } else {
+            *buf = [0.0; SAMPLES_PER_FRAME];
+        }
+
+        self.resume(&a, buf);
+        self.finish(a, buf);
+
+        #[cfg(feature = "std")]
+        self.track(start);
+
+        Ok(status)
+    }
+
+    /// Conceal a frame that was lost entirely, such as from packet or sync loss, into
+    /// the given audio buffer.
+    ///
+    /// The previous received frame is repeated with a level that fades out over
+    /// consecutive losses, and after the count set by `set_loss_mute` the output is
+    /// muted. When frames are received again, the first is faded back in from the level
+    /// the losses ended at.
+    pub fn decode_lost(&mut self, buf: &mut AudioBuf) {
+        let start = self.loss_level(self.lost);
+        self.lost = self.lost.saturating_add(1);
+
+        // Once muted, the concealment has already faded out, so the quiet state is
+        // entered without synthesizing the tail of the previous frame.
+        let a = if start == 0.0 {
+            *buf = [0.0; SAMPLES_PER_FRAME];
+            self.analyze_quiet(self.prev.err_rate)
+        } else {
+            let a = self.analyze_repeat();
+
+            if self.synthesizes(&a) {
+                self.synthesize(&a, buf);
+                fade(buf, start, self.loss_level(self.lost));
+            } else {
+                *buf = [0.0; SAMPLES_PER_FRAME];
+            }
+
+            a
+        };
+
+        if let Some(ref mut s) = self.stats {
+            s.record_lost(a.cur.err_rate);
+        }
+
+        self.finish(a, buf);
+    }
+
+    /// Compute the level of concealment after the given number of consecutive lost
+    /// frames.
+    fn loss_level(&self, lost: u32) -> f32 {
+        if lost >= self.loss_mute {
+            0.0
+        } else {
+            1.0 - lost as f32 / self.loss_mute as f32
         }
     }
 
-    /// Decode the given frame into the given audio sample buffer.
-    pub fn decode(&mut self, frame: ReceivedFrame, buf: &mut AudioBuf) {
+    /// Fade the given frame, synthesized from a received frame, back in from the level
+    /// of any preceding lost frames.
+    ///
+    /// A quiet frame holds that level instead, so the tail of the concealment continues
+    /// to decay from where it left off.
+    fn resume(&mut self, a: &Analysis, buf: &mut AudioBuf) {
+        if self.lost > 0 {
+            let start = self.loss_level(self.lost);
+            fade(buf, start, if a.quiet { start } else { 1.0 });
+            self.lost = 0;
+        }
+    }
+
+    /// Check if the given analyzed frame needs to be synthesized, which is the case
+    /// unless both it and the previous frame are quiet.
+    fn synthesizes(&self, a: &Analysis) -> bool {
+        !(a.quiet && self.quiet)
+    }
+
+    /// Record the time spent decoding a frame that started at the given time, if a budget
+    /// is set.
+    #[cfg(feature = "std")]
+    fn track(&mut self, start: Option<Instant>) {
+        if let (Some(g), Some(start)) = (self.governor.as_mut(), start) {
+            g.update(start.elapsed());
+        }
+    }
+
+    /// Decode each of the given frames with the corresponding decoder into the
+    /// corresponding audio sample buffer, writing the result of each frame into the
+    /// corresponding entry of `results`.
+    ///
+    /// The output is identical to calling `try_decode` with each decoder in turn, so a
+    /// frame that fails leaves its buffer and decoder unchanged, but up to `BATCH_LANES`
+    /// streams are decoded at a time. The analysis stages, from descrambling to spectral
+    /// enhancement, run for each stream in turn, as they're branching code over a
+    /// handful of values per frame. Voiced and unvoiced synthesis are laid out with one
+    /// stream in each vector lane and run with the cross-stream kernels in `kernel`.
+    /// Synthesis runs on the calling thread, regardless of the threading mode of each
+    /// decoder.
+    ///
+    /// With a time budget set, each decoded frame is charged an equal share of the time
+    /// spent on the lanes it was batched with.
+    pub fn decode_batch(decoders: &mut [ImbeDecoder<R, P>], frames: &[ReceivedFrame],
+                        bufs: &mut [AudioBuf],
+                        results: &mut [Result<DecodeStatus, FrameError>])
+    {
+        assert!(frames.len() == decoders.len() && bufs.len() == decoders.len() &&
+                results.len() == decoders.len());
+
+        for (((decs, frames), bufs), results) in decoders.chunks_mut(BATCH_LANES)
+            .zip(frames.chunks(BATCH_LANES))
+            .zip(bufs.chunks_mut(BATCH_LANES))
+            .zip(results.chunks_mut(BATCH_LANES))
+        {
+            decode_lanes(decs, frames, bufs, results);
+        }
+    }
+
+    /// Run the analysis stages of decoding the given frame, producing how the frame is
+    /// handled and the parameters to synthesize.
+    ///
+    /// The decoder state is unchanged if the frame can't be descrambled.
+    fn analyze(&mut self, frame: &ReceivedFrame)
+        -> Result<(DecodeStatus, Analysis), FrameError>
+    {
         let period = match Bootstrap::new(&frame.chunks) {
             Bootstrap::Period(p) => p,
+            // Repeat previous frame on invalid period [p46].
             Bootstrap::Invalid => {
-                // Repeat previous frame on invalid period [p46].
-                self.repeat(buf);
-                return;
+                return Ok((DecodeStatus::InvalidPeriod, self.analyze_repeat()))
             },
             Bootstrap::Silence => {
-                self.silence(buf);
-                return;
+                let a = self.analyze_quiet(self.prev.err_rate);
+                return Ok((DecodeStatus::Silence, a));
             },
         };
 
         let errors = EnhanceErrors::new(&frame.errors, self.prev.err_rate);
 
-        if enhance::should_repeat(&errors) {
-            self.repeat(buf);
-            return;
+        if self.policy.should_repeat(&errors) {
+            return Ok((DecodeStatus::Repeat(errors), self.analyze_repeat()));
         }
 
-        if enhance::should_mute(&errors) {
-            self.silence(buf);
-            return;
+        if self.policy.should_mute(&errors) {
+            return Ok((DecodeStatus::Mute(errors), self.analyze_quiet(errors.rate)));
         }
 
         let params = BaseParams::new(period);
-        let (amps, mut voice, gain_idx) = descramble(&frame.chunks, &params);
+        let (amps, mut voice, gain_idx) = try_descramble(&frame.chunks, &params)?;
         let gains = Gains::new(gain_idx, &amps, &params);
         let coefs = Coefficients::new(&gains, &amps, &params);
         let spectrals = Spectrals::new(&coefs, &params, &self.prev);
         let energy = FrameEnergy::new(&spectrals, &self.prev.energy, &params);
 
+        let quality = self.quality();
+
         let mut enhanced = EnhancedSpectrals::new(&spectrals, &energy, &params);
-        let amp_thresh = enhance::amp_thresh(&errors, self.prev.amp_thresh);
-        enhance::smooth(&mut enhanced, &mut voice, &errors, &energy, amp_thresh);
+        let amp_thresh = self.policy.amp_thresh(&errors, self.prev.amp_thresh);
+
+        if quality.smooth() {
+            let thresh = self.policy.voice_thresh(&errors, &energy);
+            enhance::smooth(&mut enhanced, &mut voice, thresh, amp_thresh);
+        }
 
-        let udft = UnvoicedDft::new(&params, &voice, &enhanced, rand::weak_rng());
+        let udft = UnvoicedDft::new(&params, &voice, &enhanced, &mut self.rng);
         let vbase = PhaseBase::new(&params, &self.prev);
-        let vphase = Phase::new(&vbase, &params, &self.prev, &voice, rand::weak_rng());
+        let vphase = Phase::new(&vbase, &params, &self.prev, &voice, &mut self.rng);
 
-        crossbeam::scope(|scope| {
-            let unvoiced = Arc::new(Unvoiced::new(&udft, &self.prev.unvoiced));
-            let voiced = Arc::new(Voiced::new(&params, &self.prev, &vphase, &enhanced, &voice));
+        Ok((DecodeStatus::Voice(errors), Analysis {
+            cur: PrevFrame {
+                params: params,
+                spectrals: spectrals,
+                enhanced: enhanced,
+                voice: voice,
+                err_rate: errors.rate,
+                energy: energy,
+                amp_thresh: amp_thresh,
+                unvoiced: udft,
+                phase_base: vbase,
+                phase: vphase,
+            },
+            quiet: false,
+            quality: quality,
+        }))
+    }
 
-            for (i, chunk) in buf.chunks_mut(SAMPLES_PER_THREAD).enumerate() {
-                let u = unvoiced.clone();
-                let v = voiced.clone();
+    /// Complete the given frame synthesized into the given buffer, adding any comfort
+    /// noise and saving the current parameters for the next frame.
+    fn finish(&mut self, a: Analysis, buf: &mut AudioBuf) {
+        if let Some(ref mut c) = self.comfort {
+            if a.quiet {
+                c.fill(&mut self.rng, buf);
+            } else {
+                c.fade_out(&mut self.rng, buf);
+                c.track(&a.cur.unvoiced);
+            }
+        }
 
-                let start = i * SAMPLES_PER_THREAD;
-                let stop = start + SAMPLES_PER_THREAD;
+        self.quiet = a.quiet;
+        self.prev = a.cur;
+    }
 
-                // Compute Eq 142 for this chunk.
-                scope.spawn(move || {
-                    (start..stop)
-                        .map(|n| u.get(n) + v.get(n))
-                        .collect_slice_checked(&mut chunk[..]);
-                });
-            }
-        });
-
-        // Save current parameters.
-        self.prev = PrevFrame {
-            params: params,
-            spectrals: spectrals,
-            enhanced: enhanced,
-            voice: voice,
-            err_rate: errors.rate,
-            energy: energy,
-            amp_thresh: amp_thresh,
-            unvoiced: udft,
-            phase_base: vbase,
-            phase: vphase,
-        };
+    /// Decode the given frame into the given 16-bit PCM sample buffer.
+    ///
+    /// Samples are mapped according to the full-scale definition in the `pcm` module, and
+    /// out-of-range samples are saturated.
+    pub fn decode_i16(&mut self, frame: ReceivedFrame, out: &mut PcmBuf) -> DecodeStatus {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        let status = self.decode(frame, &mut buf);
+        pcm::quantize(&buf, out);
+        status
+    }
+
+    /// Decode the given frame into the given 16-bit PCM sample buffer as in
+    /// `decode_i16`, checking the frame as in `try_decode`.
+    pub fn try_decode_i16(&mut self, frame: ReceivedFrame, out: &mut PcmBuf)
+        -> Result<DecodeStatus, FrameError>
+    {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        let status = self.try_decode(frame, &mut buf)?;
+        pcm::quantize(&buf, out);
+        Ok(status)
+    }
+
+    /// Decode the given frame into the given 16-bit PCM sample buffer, applying TPDF
+    /// dither from the given generator before quantization.
+    pub fn decode_i16_dithered<D: Rng>(&mut self, frame: ReceivedFrame,
+                                       dither: &mut Dither<D>, out: &mut PcmBuf)
+        -> DecodeStatus
+    {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        let status = self.decode(frame, &mut buf);
+        pcm::quantize_dithered(&buf, dither, out);
+        status
     }
 
-    /// Fill the given audio buffer with silence.
-    fn silence(&self, buf: &mut AudioBuf) {
-        (0..SAMPLES_PER_FRAME).map(|_| 0.0).collect_slice_checked(&mut buf[..]);
+    /// Decode the given frame into the given audio sample buffer, with samples normalized
+    /// to the range [-1, 1].
+    pub fn decode_normalized(&mut self, frame: ReceivedFrame, buf: &mut AudioBuf)
+        -> DecodeStatus
+    {
+        let status = self.decode(frame, buf);
+        pcm::normalize(buf);
+        status
+    }
+
+    /// Produce the parameters of a silent or muted frame with the given error rate.
+    ///
+    /// These move the decoder to a quiet state, in which all amplitudes are zero and the
+    /// phase and noise terms are reset as in the initial state, so the previous frame
+    /// fades out through the synthesis window and the next voice frame fades in through
+    /// it. The spectral amplitude predictor and the energy and amplitude trackers are
+    /// kept, so the next voice frame is decoded as if the quiet frames weren't there.
+    fn analyze_quiet(&self, err_rate: f32) -> Analysis {
+        let mut voice = self.prev.voice;
+        voice.clear();
+
+        Analysis {
+            cur: PrevFrame {
+                params: self.prev.params,
+                spectrals: self.prev.spectrals.clone(),
+                enhanced: EnhancedSpectrals::default(),
+                voice: voice,
+                err_rate: err_rate,
+                energy: self.prev.energy.clone(),
+                amp_thresh: self.prev.amp_thresh,
+                unvoiced: UnvoicedDft::default(),
+                phase_base: PhaseBase::default(),
+                phase: Phase::default(),
+            },
+            quiet: true,
+            quality: self.quality(),
+        }
     }
 
     /// Repeat the previous frame into the given audio buffer.
-    fn repeat(&self, buf: &mut AudioBuf) {
+    ///
+    /// This is done internally for frames that fail error checks. To conceal a frame that
+    /// was lost entirely, use `decode_lost`, which also fades out over longer losses.
+    pub fn repeat(&mut self, buf: &mut AudioBuf) {
+        let a = self.analyze_repeat();
+        self.synthesize(&a, buf);
+        self.finish(a, buf);
+    }
+
+    /// Produce the parameters to synthesize a repeat of the previous frame.
+    ///
+    /// The repeat's parameters are saved like those of any other frame, so the voiced
+    /// phases and unvoiced overlap continue across a run of repeats. A repeat of a quiet
+    /// frame is itself quiet, so any comfort noise continues through it.
+    fn analyze_repeat(&mut self) -> Analysis {
         // Apply Eqs 99 through 104.
         let params = self.prev.params.clone();
         let voice = self.prev.voice.clone();
         let enhanced = self.prev.enhanced.clone();
 
-        let udft = UnvoicedDft::new(&params, &voice, &enhanced, rand::weak_rng());
+        let udft = UnvoicedDft::new(&params, &voice, &enhanced, &mut self.rng);
         let vbase = PhaseBase::new(&params, &self.prev);
-        let vphase = Phase::new(&vbase, &params, &self.prev, &voice, rand::weak_rng());
-
-        let unvoiced = Unvoiced::new(&udft, &self.prev.unvoiced);
-        let voiced = Voiced::new(&params, &self.prev, &vphase, &enhanced, &voice);
+        let vphase = Phase::new(&vbase, &params, &self.prev, &voice, &mut self.rng);
 
         // Repeat frame using previous parameters [p47].
-        (0..SAMPLES_PER_FRAME)
-            .map(|n| unvoiced.get(n) + voiced.get(n))
-            .collect_slice_checked(&mut buf[..]);
+        Analysis {
+            cur: PrevFrame {
+                params: params,
+                spectrals: self.prev.spectrals.clone(),
+                enhanced: enhanced,
+                voice: voice,
+                err_rate: self.prev.err_rate,
+                energy: self.prev.energy.clone(),
+                amp_thresh: self.prev.amp_thresh,
+                unvoiced: udft,
+                phase_base: vbase,
+                phase: vphase,
+            },
+            quiet: self.quiet,
+            quality: self.quality(),
+        }
+    }
+
+    /// Synthesize the given analyzed frame into the given audio buffer, using the
+    /// configured threading mode.
+    fn synthesize(&self, a: &Analysis, buf: &mut AudioBuf) {
+        let cur = &a.cur;
+        let idft = a.quality.idft(self.idft);
+        let limit = a.quality.max_harmonics();
+
+        match self.threading {
+            Threading::Single => {
+                let unvoiced = Unvoiced::with_method(&cur.unvoiced, &self.prev.unvoiced,
+                                                     idft);
+                let voiced = Voiced::with_options(&cur.params, &self.prev, &cur.phase,
+                                                  &cur.enhanced, &cur.voice, self.voiced,
+                                                  limit);
+
+                synth_range(&unvoiced, &voiced, 0, &mut buf[..]);
+            },
+            #[cfg(feature = "std")]
+            Threading::Scoped => crossbeam::scope(|scope| {
+                let unvoiced = Arc::new(Unvoiced::with_method(&cur.unvoiced,
+                                                              &self.prev.unvoiced,
+                                                              idft));
+                let voiced = Arc::new(Voiced::with_options(&cur.params, &self.prev,
+                                                           &cur.phase, &cur.enhanced,
+                                                           &cur.voice, self.voiced,
+                                                           limit));
+
+                for (i, chunk) in buf.chunks_mut(SAMPLES_PER_THREAD).enumerate() {
+                    let u = unvoiced.clone();
+                    let v = voiced.clone();
+
+                    scope.spawn(move || {
+                        synth_range(&u, &v, i * SAMPLES_PER_THREAD, chunk);
+                    });
+                }
+            }),
+            #[cfg(feature = "std")]
+            Threading::Pool(ref pool) => {
+                let slot = self.slot.as_ref().expect("pool mode without a slot");
+
+                {
+                    let mut job = slot.job.write().unwrap();
+
+                    job.cur = cur.clone();
+                    job.prev = self.prev.clone();
+                    job.idft = idft;
+                    job.voiced = self.voiced;
+                    job.limit = limit;
+                }
+
+                {
+                    let mut out = slot.out.lock().unwrap();
+
+                    out.pending = THREADS;
+                    out.failed = false;
+                }
+
+                for i in 0..THREADS {
+                    pool.execute_part(slot.clone(), i);
+                }
+
+                let mut out = slot.out.lock().unwrap();
+
+                while out.pending > 0 {
+                    out = slot.done.wait(out).unwrap();
+                }
+
+                assert!(!out.failed, "synthesis job failed");
+                buf.copy_from_slice(&out.buf[..]);
+            },
+        }
+    }
+}
+
+/// How `ImbeDecoder::decode` handled a frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DecodeStatus {
+    /// Decoded as voice, with the given error characteristics.
+    Voice(EnhanceErrors),
+    /// Replaced with a repeat of the previous frame because the period b<sub>0</sub> was
+    /// invalid [p46].
+    InvalidPeriod,
+    /// Replaced with a repeat of the previous frame because of the given error
+    /// characteristics.
+    Repeat(EnhanceErrors),
+    /// Muted because of the given error characteristics.
+    Mute(EnhanceErrors),
+    /// Decoded as a silence frame.
+    Silence,
+}
+
+impl DecodeStatus {
+    /// Retrieve the error characteristics computed for the frame, if any.
+    ///
+    /// These aren't computed for silence frames or frames with an invalid period.
+    pub fn errors(&self) -> Option<&EnhanceErrors> {
+        match *self {
+            DecodeStatus::Voice(ref e) |
+            DecodeStatus::Repeat(ref e) |
+            DecodeStatus::Mute(ref e) => Some(e),
+            DecodeStatus::InvalidPeriod | DecodeStatus::Silence => None,
+        }
+    }
+}
+
+/// Parameters of a frame to synthesize, produced by the analysis stages of decoding.
+struct Analysis {
+    /// Current frame parameters, in the form saved for the next frame.
+    cur: PrevFrame,
+    /// Whether this is a silent or muted frame, whose parameters are the quiet state.
+    quiet: bool,
+    /// Quality level to synthesize at.
+    quality: Quality,
+}
+
+/// Decode each of the given frames, at most `BATCH_LANES`, with the corresponding decoder
+/// into the corresponding buffer and result.
+fn decode_lanes<R, P>(decoders: &mut [ImbeDecoder<R, P>], frames: &[ReceivedFrame],
+                      bufs: &mut [AudioBuf],
+                      results: &mut [Result<DecodeStatus, FrameError>])
+    where R: Rng, P: ErrorPolicy
+{
+    #[cfg(feature = "std")]
+    let start = if decoders.iter().any(|d| d.governor.is_some()) {
+        Some(Instant::now())
+    } else {
+        None
+    };
+
+    // Lanes whose frames fail are skipped in every later stage.
+    let analyses = decoders.iter_mut().zip(frames.iter()).zip(results.iter_mut())
+        .map(|((d, f), result)| match f.check().and_then(|_| d.analyze(f)) {
+            Ok((status, a)) => {
+                if let Some(ref mut s) = d.stats {
+                    s.record(f, &status, &a.cur);
+                }
+
+                *result = Ok(status);
+                Some(a)
+            },
+            Err(e) => {
+                *result = Err(e);
+                None
+            },
+        })
+        .collect::<ArrayVec<[_; BATCH_LANES]>>();
+
+    // Synthesize the lanes that produce sound together, with one lane per column.
+    {
+        let mut voiced = ArrayVec::<[_; BATCH_LANES]>::new();
+        let mut unvoiced = ArrayVec::<[_; BATCH_LANES]>::new();
+        let mut out = ArrayVec::<[_; BATCH_LANES]>::new();
+
+        for ((d, a), buf) in decoders.iter().zip(analyses.iter()).zip(bufs.iter_mut()) {
+            let a = match *a {
+                Some(ref a) => a,
+                None => continue,
+            };
+
+            if !d.synthesizes(a) {
+                *buf = [0.0; SAMPLES_PER_FRAME];
+                continue;
+            }
+
+            voiced.push(Voiced::with_options(&a.cur.params, &d.prev, &a.cur.phase,
+                                             &a.cur.enhanced, &a.cur.voice, d.voiced,
+                                             a.quality.max_harmonics()));
+            unvoiced.push(Unvoiced::with_method(&a.cur.unvoiced, &d.prev.unvoiced,
+                                                a.quality.idft(d.idft)));
+            out.push(buf);
+        }
+
+        // Compute Eq 142 as in `synth_range`.
+        Voiced::fill_batch(&voiced[..], &mut out[..]);
+        Unvoiced::add_batch(&unvoiced[..], &mut out[..]);
+    }
+
+    for ((d, a), buf) in decoders.iter_mut().zip(analyses).zip(bufs.iter_mut()) {
+        if let Some(a) = a {
+            d.resume(&a, buf);
+            d.finish(a, buf);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    {
+        let decoded = results.iter().filter(|r| r.is_ok()).count() as u32;
+
+        if let Some(start) = start.filter(|_| decoded > 0) {
+            let share = start.elapsed() / decoded;
+
+            for (d, r) in decoders.iter_mut().zip(results.iter()) {
+                if let (Some(g), true) = (d.governor.as_mut(), r.is_ok()) {
+                    g.update(share);
+                }
+            }
+        }
+    }
+}
+
+/// Scale the given frame by a level that moves linearly from the given start level to
+/// the given end level over the frame.
+fn fade(buf: &mut AudioBuf, start: f32, end: f32) {
+    let step = (end - start) / SAMPLES_PER_FRAME as f32;
+
+    for (n, x) in buf.iter_mut().enumerate() {
+        *x *= start + step * (n + 1) as f32;
+    }
+}
+
+/// Per-decoder storage for synthesizing frames on pool threads, allocated once and
+/// reused for every frame.
+#[cfg(feature = "std")]
+struct SynthSlot {
+    /// Parameters of the frame being synthesized.
+    job: RwLock<SynthJob>,
+    /// Output of the frame being synthesized.
+    out: Mutex<SynthOut>,
+    /// Signalled when the last job of a frame finishes.
+    done: Condvar,
+}
+
+#[cfg(feature = "std")]
+impl SynthSlot {
+    /// Create a new `SynthSlot` with placeholder parameters.
+    fn new() -> SynthSlot {
+        SynthSlot {
+            job: RwLock::new(SynthJob {
+                cur: PrevFrame::default(),
+                prev: PrevFrame::default(),
+                idft: IdftMethod::Direct,
+                voiced: VoicedMethod::OverlapAdd,
+                limit: 0,
+            }),
+            out: Mutex::new(SynthOut {
+                buf: [0.0; SAMPLES_PER_FRAME],
+                pending: 0,
+                failed: false,
+            }),
+            done: Condvar::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Parts for SynthSlot {
+    /// Synthesize the given range of `SAMPLES_PER_THREAD` samples.
+    fn run(&self, part: usize) {
+        let start = part * SAMPLES_PER_THREAD;
+        let mut chunk = [0.0; SAMPLES_PER_THREAD];
+
+        // Count the job as finished even if it panics, so the decoder isn't left waiting.
+        let ok = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.job.read().unwrap().run(start, &mut chunk);
+        })).is_ok();
+
+        let mut out = self.out.lock().unwrap();
+
+        out.buf[start..start + SAMPLES_PER_THREAD].copy_from_slice(&chunk[..]);
+        out.failed |= !ok;
+        out.pending -= 1;
+
+        if out.pending == 0 {
+            self.done.notify_one();
+        }
+    }
+}
+
+/// Samples synthesized by pool threads.
+#[cfg(feature = "std")]
+struct SynthOut {
+    /// Synthesized samples.
+    buf: AudioBuf,
+    /// Number of jobs of the current frame yet to finish.
+    pending: usize,
+    /// Whether any job of the current frame panicked.
+    failed: bool,
+}
+
+/// Copy of the parameters needed to synthesize a frame on a pool thread.
+#[cfg(feature = "std")]
+struct SynthJob {
+    cur: PrevFrame,
+    prev: PrevFrame,
+    idft: IdftMethod,
+    voiced: VoicedMethod,
+    limit: usize,
+}
+
+#[cfg(feature = "std")]
+impl SynthJob {
+    /// Synthesize the samples starting at the given sample into the given buffer.
+    fn run(&self, start: usize, out: &mut [f32]) {
+        let unvoiced = Unvoiced::with_method(&self.cur.unvoiced, &self.prev.unvoiced,
+                                             self.idft);
+        let voiced = Voiced::with_options(&self.cur.params, &self.prev, &self.cur.phase,
+                                          &self.cur.enhanced, &self.cur.voice,
+                                          self.voiced, self.limit);
+
+        synth_range(&unvoiced, &voiced, start, out);
+    }
+}
+
+/// Synthesize the samples starting at the given sample into the given buffer.
+fn synth_range(unvoiced: &Unvoiced, voiced: &Voiced, start: usize, out: &mut [f32]) {
+    voiced.fill(start, out);
+
+    // Compute Eq 142 for this range.
+    for (n, x) in (start..).zip(out.iter_mut()) {
+        *x += unvoiced.get(n);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::THREADS;
+    use super::*;
     use consts::SAMPLES_PER_FRAME;
+    use descramble::VoiceDecisions;
+    use frame::{Errors, ReceivedFrame};
+    #[cfg(feature = "std")]
+    use pool::WorkerPool;
+    use policy::{Lenient, Strict};
+    use rand::{SeedableRng, XorShiftRng};
+    use snapshot::MAX_SNAPSHOT_LEN;
+    #[cfg(feature = "std")]
+    use voiced::RESYNC;
 
     #[test]
+    #[cfg(feature = "std")]
     fn verify_threads() {
         // Verify samples are split cleanly over threads.
         assert!(SAMPLES_PER_FRAME % THREADS == 0);
+        // Verify threads produce the same voiced samples as a single block.
+        assert_eq!(SAMPLES_PER_THREAD % RESYNC, 0);
+    }
+
+    /// First chunks of a run of fully-voiced frames.
+    const VOICED: [u32; 3] = [0b001000010010, 0b010000110010, 0b001100010110];
+
+    /// Create a fully-voiced frame, which involves no random terms, with the given first
+    /// chunk.
+    fn voiced_frame(u0: u32) -> ReceivedFrame {
+        ReceivedFrame::new([
+            u0,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b11111111111,
+            0b11111111111,
+            0b01110111011,
+            0b00001000,
+        ], [0; 7])
+    }
+
+    /// Create a silent frame.
+    fn silence_frame() -> ReceivedFrame {
+        ReceivedFrame::new([0b110110000000, 0, 0, 0, 0, 0, 0, 0], [0; 7])
+    }
+
+    /// Decode a run of fully-voiced frames with the given threading mode and voiced
+    /// synthesis method.
+    #[cfg(feature = "std")]
+    fn decode_voiced(threading: Threading, method: VoicedMethod) -> Vec<f32> {
+        let mut dec = ImbeDecoder::with_threading(threading);
+        dec.set_voiced_method(method);
+        let mut out = vec![];
+
+        for &u0 in &VOICED {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            dec.decode(voiced_frame(u0), &mut buf);
+            out.extend_from_slice(&buf[..]);
+        }
+
+        out
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_threading() {
+        for &method in &[VoicedMethod::OverlapAdd, VoicedMethod::Interpolate] {
+            let single = decode_voiced(Threading::Single, method);
+            let pool = WorkerPool::new(2);
+
+            assert!(single.iter().any(|&x| x != 0.0));
+            assert_eq!(single, decode_voiced(Threading::Scoped, method));
+            assert_eq!(single, decode_voiced(Threading::Pool(pool), method));
+        }
+    }
+
+    /// Decode a run of frames with unvoiced bands, including a repeat, using the given
+    /// decoder.
+    fn decode_mixed<R: Rng>(mut dec: ImbeDecoder<R>) -> Vec<f32> {
+        let mut out = vec![];
+
+        for _ in 0..3 {
+            let frame = ReceivedFrame::new([
+                0b001000010010,
+                0b110011001100,
+                0b111000111000,
+                0b111111111111,
+                0b10100110101,
+                0b00101111010,
+                0b01110111011,
+                0b00001000,
+            ], [0; 7]);
+
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            dec.decode(frame, &mut buf);
+            out.extend_from_slice(&buf[..]);
+        }
+
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        dec.repeat(&mut buf);
+        out.extend_from_slice(&buf[..]);
+
+        out
+    }
+
+    #[test]
+    fn test_rng() {
+        let seeded = |seed| XorShiftRng::from_seed([seed, 2, 3, 4]);
+
+        let a = decode_mixed(ImbeDecoder::with_rng(seeded(1)));
+        let b = decode_mixed(ImbeDecoder::with_rng_threading(seeded(1),
+                                                             Threading::Single));
+        let c = decode_mixed(ImbeDecoder::with_rng(seeded(5)));
+
+        assert!(a.iter().any(|&x| x != 0.0));
+        assert_eq!(a, b);
+        assert!(a != c);
+    }
+
+    /// Create the given frame of the given stream, covering voiced, mixed, silent, and
+    /// invalid frames.
+    fn batch_frame(stream: usize, frame: usize) -> ReceivedFrame {
+        let u0 = [0b001000010010, 0b010000110010, 0b001100010110, 0b000110101000];
+
+        match (stream + frame) % 7 {
+            0 => ReceivedFrame::new([0b110110000000, 0, 0, 0, 0, 0, 0, 0], [0; 7]),
+            1 => ReceivedFrame::new([0b111111111111, 0, 0, 0, 0, 0, 0, 0], [0; 7]),
+            x => ReceivedFrame::new([
+                u0[(stream * 3 + frame) % 4],
+                0b110011001100,
+                0b111000111000,
+                0b111111111111,
+                if x % 2 == 0 { 0b10100110101 } else { 0b11111111111 },
+                if x % 2 == 0 { 0b00101111010 } else { 0b11111111111 },
+                0b01110111011,
+                0b00001000,
+            ], [0; 7]),
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        const STREAMS: usize = 11;
+
+        let decoders = || (0..STREAMS).map(|s| {
+            let mut dec = ImbeDecoder::with_rng_threading(
+                XorShiftRng::from_seed([s as u32 + 1, 2, 3, 4]), Threading::Single);
+
+            if s % 3 == 0 {
+                dec.set_idft_method(IdftMethod::Fft);
+            }
+
+            if s % 4 == 1 {
+                dec.set_voiced_method(VoicedMethod::Interpolate);
+            }
+
+            if s % 5 == 2 {
+                dec.set_comfort_noise(Some(0.0));
+            }
+
+            if s % 2 == 0 {
+                dec.set_stats(true);
+            }
+
+            dec
+        }).collect::<Vec<_>>();
+
+        let mut single = decoders();
+        let mut batch = decoders();
+        let mut invalid = 0;
+
+        for f in 0..8 {
+            // A malformed frame in one lane doesn't affect the others.
+            let bad = (f * 3) % STREAMS;
+            let frame = |s| {
+                let mut frame = batch_frame(s, f);
+
+                if s == bad {
+                    frame.chunks[7] = 1 << 8;
+                }
+
+                frame
+            };
+
+            let frames = (0..STREAMS).map(&frame).collect::<Vec<_>>();
+            let mut out = vec![[1.0; SAMPLES_PER_FRAME]; STREAMS];
+            let mut results = vec![Ok(DecodeStatus::Silence); STREAMS];
+
+            ImbeDecoder::decode_batch(&mut batch[..], &frames[..], &mut out[..],
+                                      &mut results[..]);
+
+            for (s, dec) in single.iter_mut().enumerate() {
+                let mut buf = [1.0; SAMPLES_PER_FRAME];
+                let result = dec.try_decode(frame(s), &mut buf);
+
+                assert_eq!(&out[s][..], &buf[..]);
+                assert_eq!(results[s], result);
+            }
+
+            assert_eq!(results[bad], Err(FrameError::OversizedChunk(7)));
+            assert!(out[bad].iter().all(|&x| x == 1.0));
+            assert!(out.iter().any(|b| b.iter().any(|&x| x != 0.0)));
+
+            invalid += results.iter().filter(|&r| *r == Ok(DecodeStatus::InvalidPeriod))
+                .count();
+        }
+
+        assert!(invalid > 0);
+
+        // Statistics are collected the same way.
+        for (a, b) in single.iter().zip(batch.iter()) {
+            assert_eq!(a.stats().map(|s| format!("{}", s.summary())),
+                       b.stats().map(|s| format!("{}", s.summary())));
+        }
+
+        assert_eq!(batch[0].stats().unwrap().frames(), 7);
+    }
+
+    #[test]
+    fn test_quality() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+        let decode = |quality| {
+            let mut dec = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+            dec.set_quality(quality);
+            assert_eq!(dec.quality(), quality);
+
+            (0..8).flat_map(|f| {
+                let mut buf = [0.0; SAMPLES_PER_FRAME];
+                dec.decode(batch_frame(2, f), &mut buf);
+                buf.to_vec()
+            }).collect::<Vec<_>>()
+        };
+
+        let full = decode(Quality::Full);
+        let reduced = decode(Quality::Reduced);
+        let minimal = decode(Quality::Minimal);
+
+        assert!(minimal.iter().any(|&x| x != 0.0));
+        assert!(full != reduced && reduced != minimal);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_budget() {
+        let mut dec = ImbeDecoder::with_threading(Threading::Single);
+        dec.set_budget(Some(Duration::new(0, 1)));
+
+        for f in 0..32 {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            dec.decode(batch_frame(0, f), &mut buf);
+        }
+
+        // Every frame exceeds the budget.
+        assert_eq!(dec.quality(), Quality::Minimal);
+
+        dec.set_budget(None);
+        assert_eq!(dec.quality(), Quality::Full);
+
+        // Batches are timed too.
+        let mut decs = (0..3).map(|_| {
+            let mut dec = ImbeDecoder::with_threading(Threading::Single);
+            dec.set_budget(Some(Duration::new(0, 1)));
+            dec
+        }).collect::<Vec<_>>();
+
+        for f in 0..32 {
+            let frames = (0..3).map(|s| batch_frame(s, f)).collect::<Vec<_>>();
+            let mut out = [[0.0; SAMPLES_PER_FRAME]; 3];
+            let mut results = [Ok(DecodeStatus::Silence); 3];
+
+            ImbeDecoder::decode_batch(&mut decs[..], &frames[..], &mut out[..],
+                                      &mut results[..]);
+        }
+
+        assert!(decs.iter().all(|d| d.quality() == Quality::Minimal));
+    }
+
+    #[test]
+    fn test_comfort_noise() {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut buf = [1.0; SAMPLES_PER_FRAME];
+
+        dec.decode(silence_frame(), &mut buf);
+        assert!(buf.iter().all(|&x| x == 0.0));
+
+        dec.set_comfort_noise(Some(0.0));
+
+        for f in 0..4 {
+            dec.decode(batch_frame(2, f), &mut buf);
+        }
+
+        // The first silent frame carries the tail of the speech.
+        for _ in 0..2 {
+            dec.decode(silence_frame(), &mut buf);
+        }
+
+        assert!(buf.iter().any(|&x| x != 0.0));
+        assert!(buf.iter().all(|&x| x.abs() < 1000.0));
+
+        // Lost frames and invalid periods during silence continue the noise.
+        dec.decode_lost(&mut buf);
+        assert!(dec.quiet);
+        assert!(buf.iter().any(|&x| x != 0.0));
+
+        let mut invalid = voiced_frame(VOICED[0]);
+        invalid.chunks[0] = 0b111111111111;
+        assert_eq!(dec.decode(invalid, &mut buf), DecodeStatus::InvalidPeriod);
+        assert!(dec.quiet);
+        assert!(buf.iter().any(|&x| x != 0.0));
+        assert!(buf.iter().all(|&x| x.abs() < 1000.0));
+
+        // Disabling it restores exact silence.
+        dec.set_comfort_noise(None);
+        dec.decode(silence_frame(), &mut buf);
+        assert!(buf.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_silence_transitions() {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut out = vec![];
+
+        let frames = vec![
+            silence_frame(),
+            voiced_frame(VOICED[0]),
+            voiced_frame(VOICED[1]),
+            silence_frame(),
+            silence_frame(),
+        ];
+
+        for frame in frames {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            dec.decode(frame, &mut buf);
+            out.push(buf);
+        }
+
+        let peak = out.iter().flat_map(|b| b.iter())
+            .fold(0.0, |m, &x| f32::max(m, x.abs()));
+
+        // Speech fades in through the synthesis window rather than starting abruptly.
+        assert!(out[1][..50].iter().all(|&x| x.abs() < 1e-3 * peak));
+        assert!(out[1][50..].iter().any(|&x| x != 0.0));
+        assert!(out[2][100..].iter().any(|&x| x.abs() > 0.1 * peak));
+
+        // The first silent frame carries the speech out through the window, then the
+        // decoder is quiet.
+        assert!(out[3][..20].iter().any(|&x| x.abs() > 0.1 * peak));
+        assert!(out[3][120..].iter().all(|&x| x.abs() < 1e-3 * peak));
+        assert!(out[4].iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_quiet_state() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut reference = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        dec.decode(voiced_frame(VOICED[0]), &mut buf);
+        reference.decode(voiced_frame(VOICED[0]), &mut buf);
+
+        // Quiet frames clear the synthesis state but keep the predictor and trackers.
+        dec.decode(silence_frame(), &mut buf);
+        assert!(dec.prev.enhanced.is_empty());
+        assert_eq!(dec.prev.voice.unvoiced_count(), dec.prev.params.harmonics);
+        assert_eq!(&dec.prev.spectrals[..], &reference.prev.spectrals[..]);
+
+        dec.decode(silence_frame(), &mut buf);
+        dec.decode(voiced_frame(VOICED[1]), &mut buf);
+        reference.decode(voiced_frame(VOICED[1]), &mut buf);
+
+        // The next voice frame is decoded as if the quiet frames weren't there.
+        assert_eq!(&dec.prev.spectrals[..], &reference.prev.spectrals[..]);
+        assert_eq!(&dec.prev.enhanced[..], &reference.prev.enhanced[..]);
+        assert_eq!(dec.prev.energy.tracking, reference.prev.energy.tracking);
+        assert_eq!(dec.prev.amp_thresh, reference.prev.amp_thresh);
+    }
+
+    #[test]
+    fn test_lost() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut reference = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        let mut expected = [0.0; SAMPLES_PER_FRAME];
+
+        dec.set_loss_mute(4);
+
+        for &u0 in &VOICED[..2] {
+            dec.decode(voiced_frame(u0), &mut buf);
+            reference.decode(voiced_frame(u0), &mut expected);
+        }
+
+        let energy = |x: &[f32]| x.iter().fold(0.0, |s, &x| s + x * x);
+
+        let levels = (0..6).map(|_| {
+            dec.decode_lost(&mut buf);
+            energy(&buf[..])
+        }).collect::<Vec<_>>();
+
+        // Concealed frames advance the state like repeats, and muted frames move to the
+        // quiet state like silence.
+        for _ in 0..4 {
+            reference.repeat(&mut expected);
+        }
+
+        reference.decode(silence_frame(), &mut expected);
+
+        // Concealment fades out, then mutes.
+        assert!(levels[0] > levels[1] && levels[1] > levels[2] && levels[2] > levels[3]);
+        assert!(levels[3] > 0.0);
+        assert_eq!(&levels[4..], &[0.0, 0.0]);
+
+        // The first received frame fades back in from silence.
+        dec.decode(voiced_frame(VOICED[2]), &mut buf);
+        reference.decode(voiced_frame(VOICED[2]), &mut expected);
+
+        for (n, (&x, &y)) in buf.iter().zip(expected.iter()).enumerate() {
+            let level = (n + 1) as f32 / SAMPLES_PER_FRAME as f32;
+            assert!((x - level * y).abs() < 1e-2);
+        }
+
+        // Decoding then continues as if the frames were repeated.
+        dec.decode(voiced_frame(VOICED[0]), &mut buf);
+        reference.decode(voiced_frame(VOICED[0]), &mut expected);
+        assert!(buf.iter().any(|&x| x != 0.0));
+        assert_eq!(&buf[..], &expected[..]);
+    }
+
+    #[test]
+    fn test_repeat_continuity() {
+        // A fully-voiced frame with b_0 = 0, so ω_0 = 4π / 39.5 and the voiced signal
+        // repeats every 79 samples while the parameters are steady.
+        const U0: u32 = 0b000000010010;
+        const LAG: usize = 79;
+        // Number of samples on each side of a frame boundary where only one frame
+        // contributes to the voiced signal.
+        const PURE: usize = 55;
+
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut out = vec![];
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        for f in 0..6 {
+            match f {
+                2..=4 => dec.repeat(&mut buf),
+                _ => { dec.decode(voiced_frame(U0), &mut buf); },
+            }
+
+            out.extend_from_slice(&buf[..]);
+        }
+
+        let peak = out.iter().fold(0.0, |m, &x| f32::max(m, x.abs()));
+
+        // Across each voice→repeat, repeat→repeat, and repeat→voice boundary, the signal
+        // continues with the same phases rather than restarting.
+        for f in 2..6 {
+            let b = f * SAMPLES_PER_FRAME;
+
+            for n in b - PURE + LAG..b + PURE {
+                assert!((out[n] - out[n - LAG]).abs() < 1e-3 * peak);
+            }
+        }
+    }
+
+    /// Decode a run of frames with the given valid error counts under the given policy,
+    /// and return the index of the first frame muted, if any.
+    fn first_mute<P: ErrorPolicy>(policy: P, errors: Errors) -> Option<usize> {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single)
+            .with_policy(policy);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        (0..100).position(|i| {
+            let mut frame = voiced_frame(VOICED[i % VOICED.len()]);
+            frame.errors = errors;
+
+            matches!(dec.try_decode(frame, &mut buf).unwrap(), DecodeStatus::Mute(_))
+        })
+    }
+
+    #[test]
+    fn test_policy() {
+        // Without errors in u0, no policy repeats these frames, so ϵR rises toward
+        // 0.073, past only the strict muting threshold.
+        let errors = [0, 3, 3, 3, 1, 0, 0];
+        assert_eq!(first_mute(Strict, errors), Some(22));
+        assert_eq!(first_mute(Standard, errors), None);
+        assert_eq!(first_mute(Lenient, errors), None);
+
+        // With an error in u0, ϵR rises toward 0.095, past the standard threshold but
+        // not the lenient one. The strict policy repeats these frames instead, which
+        // leaves ϵR unchanged.
+        let errors = [1, 3, 3, 3, 1, 1, 1];
+        assert_eq!(first_mute(Standard, errors), Some(49));
+        assert_eq!(first_mute(Lenient, errors), None);
+        assert_eq!(first_mute(Strict, errors), None);
+    }
+
+    #[test]
+    fn test_status() {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        match dec.decode(voiced_frame(VOICED[0]), &mut buf) {
+            DecodeStatus::Voice(e) => assert_eq!(e.total, 0),
+            s => panic!("unexpected status {:?}", s),
+        }
+
+        // Errors in u0 and the most the codes correct elsewhere cause a repeat (Eq 97),
+        // which leaves ϵR as it was.
+        let mut frame = voiced_frame(VOICED[1]);
+        frame.errors = [3, 3, 3, 3, 1, 1, 1];
+
+        match dec.decode(frame, &mut buf) {
+            DecodeStatus::Repeat(e) => {
+                assert_eq!(e.total, 15);
+                assert_eq!(e.golay_init, 3);
+                assert_eq!(e.hamming_init, 1);
+                assert!((e.rate - 0.000365 * 15.0).abs() < 1e-7);
+            },
+            s => panic!("unexpected status {:?}", s),
+        }
+
+        // With a single error in u0, frames are decoded while ϵR rises, until it passes
+        // the muting threshold.
+        let mut rate = 0.0;
+
+        for i in 0..50 {
+            let mut frame = voiced_frame(VOICED[i % VOICED.len()]);
+            frame.errors = [1, 3, 3, 3, 1, 1, 1];
+
+            rate = 0.95 * rate + 0.000365 * 13.0;
+            let status = dec.decode(frame, &mut buf);
+
+            let e = status.errors().unwrap();
+            assert_eq!(e.total, 13);
+            assert_eq!(e.golay_init, 1);
+            assert!((e.rate - rate).abs() < 1e-5);
+
+            match status {
+                DecodeStatus::Voice(_) if i < 49 => {},
+                DecodeStatus::Mute(e) if i == 49 => assert!(e.rate > 0.0875),
+                s => panic!("unexpected status {:?} at frame {}", s, i),
+            }
+        }
+
+        let mut invalid = voiced_frame(VOICED[0]);
+        invalid.chunks[0] = 0b111111111111;
+
+        assert_eq!(dec.decode(invalid, &mut buf), DecodeStatus::InvalidPeriod);
+        assert_eq!(dec.decode(silence_frame(), &mut buf), DecodeStatus::Silence);
+        assert_eq!(DecodeStatus::Silence.errors(), None);
+    }
+
+    #[test]
+    fn test_try_decode() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        assert!(dec.try_decode(voiced_frame(VOICED[0]), &mut buf).is_ok());
+
+        // Frames modified after construction are checked again.
+        let mut frame = voiced_frame(VOICED[1]);
+        frame.chunks[5] = 1 << 11;
+
+        let mut out = [1.0; SAMPLES_PER_FRAME];
+        assert_eq!(dec.try_decode(frame, &mut out), Err(FrameError::OversizedChunk(5)));
+        assert!(out.iter().all(|&x| x == 1.0));
+
+        let mut frame = voiced_frame(VOICED[1]);
+        frame.errors = [0, 0, 0, 0, 3, 0, 0];
+        assert_eq!(dec.try_decode(frame, &mut out), Err(FrameError::InvalidErrors(4)));
+
+        // Rejected frames leave the decoder state unchanged.
+        let mut expected = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        expected.decode(voiced_frame(VOICED[0]), &mut buf);
+        expected.decode(voiced_frame(VOICED[1]), &mut buf);
+
+        assert!(dec.try_decode(voiced_frame(VOICED[1]), &mut out).is_ok());
+        assert_eq!(&out[..], &buf[..]);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let seeded = |s| XorShiftRng::from_seed([1, 2, 3, s]);
+
+        let mut dec = ImbeDecoder::with_rng_threading(seeded(4), Threading::Single);
+        dec.set_comfort_noise(Some(0.0));
+        dec.set_stats(true);
+
+        // Decode a variety of frames, ending in a loss.
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        for f in 0..10 {
+            dec.decode(batch_frame(0, f), &mut buf);
+        }
+
+        dec.decode_lost(&mut buf);
+
+        let mut snapshot = [0; MAX_SNAPSHOT_LEN];
+        let len = dec.snapshot(&mut snapshot).unwrap();
+
+        assert_eq!(&snapshot[..6], b"IMBS\x01\x00");
+        assert_eq!(dec.snapshot(&mut [0; 64]), Err(SnapshotError::BufferTooSmall));
+
+        // A new decoder restored from the snapshot continues exactly as the original
+        // when both are seeded identically at the hand-off.
+        let mut restored = ImbeDecoder::with_rng_threading(seeded(5), Threading::Single);
+        restored.restore(&snapshot[..len]).unwrap();
+        dec.rng = seeded(5);
+
+        for f in 10..24 {
+            let mut a = [0.0; SAMPLES_PER_FRAME];
+            let mut b = [0.0; SAMPLES_PER_FRAME];
+
+            if f % 9 == 0 {
+                dec.decode_lost(&mut a);
+                restored.decode_lost(&mut b);
+            } else {
+                dec.decode(batch_frame(0, f), &mut a);
+                restored.decode(batch_frame(0, f), &mut b);
+            }
+
+            assert_eq!(&a[..], &b[..]);
+        }
+
+        assert_eq!(format!("{}", dec.stats().unwrap().summary()),
+                   format!("{}", restored.stats().unwrap().summary()));
+
+        // A decoder that isn't restored diverges.
+        let mut fresh = ImbeDecoder::with_rng_threading(seeded(5), Threading::Single);
+        fresh.set_comfort_noise(Some(0.0));
+        let mut a = [0.0; SAMPLES_PER_FRAME];
+        fresh.decode(batch_frame(0, 24), &mut a);
+        dec.decode(batch_frame(0, 24), &mut buf);
+        assert!(a[..] != buf[..]);
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        dec.decode(voiced_frame(VOICED[0]), &mut buf);
+
+        let mut snapshot = [0; MAX_SNAPSHOT_LEN];
+        let len = dec.snapshot(&mut snapshot).unwrap();
+
+        let mut other = ImbeDecoder::with_rng_threading(XorShiftRng::new_unseeded(),
+                                                        Threading::Single);
+
+        assert_eq!(other.restore(&snapshot[..len - 1]), Err(SnapshotError::Truncated));
+        assert_eq!(other.restore(&snapshot[..len + 1]), Err(SnapshotError::TrailingData));
+
+        // Out-of-range harmonics are rejected, since they would index past tables.
+        let mut bad = snapshot;
+        bad[10] = 200;
+        assert_eq!(other.restore(&bad[..len]), Err(SnapshotError::InvalidValue));
+
+        // As are fundamentals that don't match the harmonics, or are out of range.
+        let mut bad = snapshot;
+        bad[6..10].copy_from_slice(&[0x00, 0x00, 0x80, 0x3f]);
+        assert_eq!(other.restore(&bad[..len]), Err(SnapshotError::InvalidValue));
+
+        let fundamental = BaseParams::new(150).fundamental.to_bits();
+        let mut bad = snapshot;
+        bad[6..10].copy_from_slice(&fundamental.to_le_bytes());
+        assert_eq!(other.restore(&bad[..len]), Err(SnapshotError::InvalidValue));
+
+        // And voicing decisions made for other parameters.
+        dec.prev.voice = VoiceDecisions::new(0, &BaseParams::new(150));
+        let mut bad = [0; MAX_SNAPSHOT_LEN];
+        dec.snapshot(&mut bad).unwrap();
+        assert_eq!(other.restore(&bad[..len]), Err(SnapshotError::InvalidValue));
+
+        let mut bad = snapshot;
+        bad[4] = 2;
+        assert_eq!(other.restore(&bad[..len]),
+                   Err(SnapshotError::UnsupportedVersion(2)));
+
+        // Failed restores leave the initial state.
+        assert!(other.quiet);
+        assert_eq!(other.prev.params.harmonics, PrevFrame::default().params.harmonics);
+    }
+
+    #[test]
+    fn test_restore_corrupt() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        dec.set_comfort_noise(Some(0.0));
+        dec.set_stats(true);
+
+        for f in 0..6 {
+            dec.decode(batch_frame(0, f), &mut buf);
+        }
+
+        let mut snapshot = [0; MAX_SNAPSHOT_LEN];
+        let len = dec.snapshot(&mut snapshot).unwrap();
+
+        // Any restored snapshot can be decoded from, whatever byte is corrupted.
+        for i in 6..len {
+            for &x in &[0x7f, 0xff] {
+                let mut bad = snapshot;
+                bad[i] = x;
+
+                let mut other = ImbeDecoder::with_rng_threading(seeded(),
+                                                                Threading::Single);
+                other.set_comfort_noise(Some(0.0));
+
+                if other.restore(&bad[..len]).is_err() {
+                    continue;
+                }
+
+                other.decode_lost(&mut buf);
+                other.decode(batch_frame(0, 6), &mut buf);
+                other.decode(silence_frame(), &mut buf);
+                other.decode(batch_frame(0, 1), &mut buf);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut dec = ImbeDecoder::with_rng_threading(seeded, Threading::Single);
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        // Nothing is collected until enabled.
+        dec.decode(voiced_frame(VOICED[0]), &mut buf);
+        assert!(dec.stats().is_none());
+
+        dec.set_stats(true);
+
+        for &u0 in &VOICED {
+            dec.decode(voiced_frame(u0), &mut buf);
+        }
+
+        let mut frame = voiced_frame(VOICED[0]);
+        frame.errors = [1, 0, 0, 0, 0, 0, 1];
+        dec.decode(frame, &mut buf);
+
+        let mut frame = voiced_frame(VOICED[0]);
+        frame.errors = [3, 3, 3, 3, 1, 1, 1];
+        dec.decode(frame, &mut buf);
+
+        dec.decode(silence_frame(), &mut buf);
+        dec.decode_lost(&mut buf);
+
+        let snapshot = dec.stats().unwrap().clone();
+
+        assert_eq!(snapshot.voice(), 4);
+        assert_eq!(snapshot.repeat(), 1);
+        assert_eq!(snapshot.mute(), 0);
+        assert_eq!(snapshot.silence(), 1);
+        assert_eq!(snapshot.lost(), 1);
+        assert_eq!(snapshot.frames(), 7);
+
+        assert_eq!(snapshot.errors()[0], 3);
+        assert_eq!(snapshot.errors()[2], 1);
+        assert_eq!(snapshot.errors()[15], 1);
+        assert_eq!(snapshot.rates().len(), 7);
+        assert!(snapshot.rates()[3] > snapshot.rates()[2]);
+        assert!(snapshot.mean_tracking().unwrap() > 0.0);
+
+        let period = |u0| match Bootstrap::new(&voiced_frame(u0).chunks) {
+            Bootstrap::Period(p) => p as usize,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(snapshot.periods()[period(VOICED[0])], 2);
+        assert_eq!(snapshot.periods().iter().sum::<u32>(), 4);
+
+        // Enabling again keeps the collected statistics, and resetting clears them
+        // without affecting the snapshot.
+        dec.set_stats(true);
+        assert_eq!(dec.stats().unwrap().frames(), 7);
+
+        dec.reset_stats();
+        assert_eq!(dec.stats().unwrap().frames(), 0);
+        assert_eq!(snapshot.frames(), 7);
+
+        dec.set_stats(false);
+        dec.decode(voiced_frame(VOICED[0]), &mut buf);
+        assert!(dec.stats().is_none());
+    }
+
+    #[test]
+    fn test_idft_method() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        let direct = decode_mixed(ImbeDecoder::with_rng(seeded()));
+
+        let mut dec = ImbeDecoder::with_rng(seeded());
+        dec.set_idft_method(IdftMethod::Fft);
+        let fft = decode_mixed(dec);
+
+        // Samples are on the 16-bit scale, so this is well within a single LSB.
+        assert!(direct.iter().zip(fft.iter()).all(|(a, b)| (a - b).abs() < 1e-2));
     }
 }
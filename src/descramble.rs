@@ -3,25 +3,39 @@
 use arrayvec::ArrayVec;
 
 use allocs::allocs;
-use frame::Chunks;
+use frame::{Chunks, FrameError};
 use params::BaseParams;
 use scan::{ScanSep, ScanBits, ScanChunks};
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 
 /// Descramble the given prioritized chunks u<sub>i</sub> into the underlying quantized
 /// amplitudes b<sub>m</sub>, voiced/unvoiced decisions v<sub>l</sub>, and initial gain
 /// index b<sub>2</sub>.
+///
+/// Panics if the scan is inconsistent, as described in `try_descramble`.
 pub fn descramble(chunks: &Chunks, params: &BaseParams) ->
     (QuantizedAmplitudes, VoiceDecisions, usize)
+{
+    match try_descramble(chunks, params) {
+        Ok(x) => x,
+        Err(e) => panic!("unable to descramble: {}", e),
+    }
+}
+
+/// Descramble the given prioritized chunks as in `descramble`, checking that the
+/// prioritized scan holds exactly the bits allocated to the quantized amplitudes.
+pub fn try_descramble(chunks: &Chunks, params: &BaseParams) ->
+    Result<(QuantizedAmplitudes, VoiceDecisions, usize), FrameError>
 {
     // Extract the data in between the two scans.
     let parts = ScanSep::new(chunks, params);
 
-    (
+    Ok((
         QuantizedAmplitudes::new(ScanBits::new(
-            ScanChunks::new(chunks, parts.scanned, &params)), params),
+            ScanChunks::new(chunks, parts.scanned, &params)), params)?,
         VoiceDecisions::new(parts.voiced, params),
         gain_idx(chunks, parts.idx_part),
-    )
+    ))
 }
 
 /// Decodes the bootstrap value b<sub>0</sub>.
@@ -74,7 +88,9 @@ pub struct QuantizedAmplitudes(ArrayVec<[u32; 64]>);
 
 impl QuantizedAmplitudes {
     /// Reconstruct quantized amplitudes from the given bit scan.
-    fn new(mut scan: ScanBits, params: &BaseParams) -> QuantizedAmplitudes {
+    fn new(mut scan: ScanBits, params: &BaseParams)
+        -> Result<QuantizedAmplitudes, FrameError>
+    {
         // Since 3 ≤ m ≤ L + 1, let i = m - 3. Then 0 ≤ i ≤ L + 1 - 3 = L - 2.
         //
         // The underlying array has a maximum length of 64 because that's the closest impl
@@ -94,13 +110,15 @@ impl QuantizedAmplitudes {
 
                 // Shift the next scanned bit onto the LSB.
                 amps[i] <<= 1;
-                amps[i] |= scan.next().unwrap();
+                amps[i] |= scan.next().ok_or(FrameError::InconsistentScan)?;
             }
         }
 
-        assert!(scan.next().is_none());
+        if scan.next().is_some() {
+            return Err(FrameError::InconsistentScan);
+        }
 
-        QuantizedAmplitudes(amps)
+        Ok(QuantizedAmplitudes(amps))
     }
 
     /// Retrieve the quantized amplitude b<sub>m</sub>, 3 ≤ m ≤ L + 1.
@@ -137,6 +155,14 @@ impl VoiceDecisions {
         self.voiced |= self.mask(l);
     }
 
+    /// Mark every harmonic as unvoiced.
+    pub fn clear(&mut self) {
+        self.voiced = 0;
+    }
+
+    /// Retrieve the parameters of the frame the decisions were made for.
+    pub fn params(&self) -> &BaseParams { &self.params }
+
     /// Compute the number of unvoiced spectral amplitudes/harmonics, L<sub>uv</sub>.
     pub fn unvoiced_count(&self) -> u32 {
         self.params.harmonics - self.voiced.count_ones()
@@ -165,6 +191,27 @@ impl Default for VoiceDecisions {
     }
 }
 
+impl Persist for VoiceDecisions {
+    fn save(&self, w: &mut Writer) {
+        self.params.save(w);
+        w.u64(self.voiced);
+    }
+
+    fn load(r: &mut Reader) -> Result<VoiceDecisions, SnapshotError> {
+        let v = VoiceDecisions {
+            params: BaseParams::load(r)?,
+            voiced: r.u64()?,
+        };
+
+        // Only the bits of harmonics 1 ≤ l ≤ L can be set.
+        if v.voiced >> v.params.harmonics != 0 {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(v)
+    }
+}
+
 /// Create a bitmap for harmonic voiced/unvoiced decisions from the given voiced/unvoiced
 /// band bitmap b<sub>1</sub> and frame parameters.
 ///
@@ -480,4 +527,23 @@ mod tests {
 
         assert_eq!(period(&chunks), 0b01010101);
     }
+
+    #[test]
+    fn test_try_descramble() {
+        let chunks = [
+            0b111111111111,
+            0b111111111111,
+            0b111111111111,
+            0b111111111111,
+            0b11111111111,
+            0b11111111111,
+            0b11111111111,
+            0b1111111,
+        ];
+
+        // The scan holds exactly the allocated bits for every valid period.
+        for p in 0..=207 {
+            assert!(try_descramble(&chunks, &BaseParams::new(p)).is_ok());
+        }
+    }
 }
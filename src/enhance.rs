@@ -1,18 +1,20 @@
 //! Frame repeat/muting, spectral amplitude enhancement, and adaptive smoothing.
 
-use std;
-use std::f32::consts::PI;
+use core;
+use core::f32::consts::PI;
 
 use arrayvec::ArrayVec;
-use slice_mip::MapInPlace;
 
 use consts::MAX_HARMONICS;
 use frame::Errors;
 use descramble::VoiceDecisions;
+use math;
 use params::BaseParams;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 use spectral::Spectrals;
 
 /// Values derived from error correction decoding.
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct EnhanceErrors {
     /// Total number of errors corrected in the current frame, ϵ<sub>T</sub> [p45].
     pub total: usize,
@@ -41,6 +43,7 @@ impl EnhanceErrors {
 }
 
 /// Energy-related parameters for a voice frame.
+#[derive(Clone)]
 pub struct FrameEnergy {
     /// Spectral amplitude energy, R<sub>M0</sub>.
     pub energy: f32,
@@ -58,12 +61,14 @@ impl FrameEnergy {
     {
         // Compute energy of spectral amplitudes according to Eq 105.
         let energy = spectrals.iter()
-            .map(|&m| m.powi(2))
+            .map(|&m| math::powi(m, 2))
             .fold(0.0, |s, x| s + x);
 
         // Compute scaled energies according to Eq 106.
         let scaled = spectrals.iter().enumerate()
-            .map(|(l, &m)| m.powi(2) * (params.fundamental * (l + 1) as f32).cos())
+            .map(|(l, &m)| {
+                math::powi(m, 2) * math::cos(params.fundamental * (l + 1) as f32)
+            })
             .fold(0.0, |s, x| s + x);
 
         FrameEnergy {
@@ -88,6 +93,22 @@ impl Default for FrameEnergy {
     }
 }
 
+impl Persist for FrameEnergy {
+    fn save(&self, w: &mut Writer) {
+        w.f32(self.energy);
+        w.f32(self.scaled);
+        w.f32(self.tracking);
+    }
+
+    fn load(r: &mut Reader) -> Result<FrameEnergy, SnapshotError> {
+        Ok(FrameEnergy {
+            energy: r.f32()?,
+            scaled: r.f32()?,
+            tracking: r.f32()?,
+        })
+    }
+}
+
 /// Enhanced spectral amplitudes, "overbar" M<sub>l</sub>, are derived from the decoded
 /// spectral amplitudes, "tilde" M<sub>l</sub>.
 #[derive(Clone)]
@@ -100,9 +121,9 @@ impl EnhancedSpectrals {
         -> EnhancedSpectrals
     {
         // Compute R_M0^2.
-        let energy_sqr = fen.energy.powi(2);
+        let energy_sqr = math::powi(fen.energy, 2);
         // Compute R_M1^2.
-        let scaled_sqr = fen.scaled.powi(2);
+        let scaled_sqr = math::powi(fen.scaled, 2);
         // Compute denominator term of Eq 107.
         let denom = params.fundamental * fen.energy * (energy_sqr - scaled_sqr);
 
@@ -115,24 +136,27 @@ impl EnhancedSpectrals {
             }
 
             // Compute Eq 107.
-            let weight = m.sqrt() * (
+            let weight = math::sqrt(m) * math::powf(
                 0.96 * PI * (
                     energy_sqr + scaled_sqr - 2.0 * fen.energy * fen.scaled *
-                        (params.fundamental * l as f32).cos()
-                ) / denom
-            ).powf(0.25);
+                        math::cos(params.fundamental * l as f32)
+                ) / denom,
+                0.25
+            );
 
             // Scale current spectral amplitude according to Eq 108.
             m * weight.max(0.5).min(1.2)
         }).collect::<ArrayVec<[f32; MAX_HARMONICS]>>();
 
         // Compute root ratio of energies according to Eq 109.
-        let scale = (
-            fen.energy / enhanced.iter().fold(0.0, |s, &m| s + m.powi(2))
-        ).sqrt();
+        let scale = math::sqrt(
+            fen.energy / enhanced.iter().fold(0.0, |s, &m| s + math::powi(m, 2))
+        );
 
         // Perform second scaling pass according to Eq 110.
-        enhanced.map_in_place(|&m| m * scale);
+        for m in enhanced.iter_mut() {
+            *m *= scale;
+        }
 
         EnhancedSpectrals(enhanced)
     }
@@ -149,12 +173,12 @@ impl EnhancedSpectrals {
     }
 }
 
-impl std::ops::Deref for EnhancedSpectrals {
+impl core::ops::Deref for EnhancedSpectrals {
     type Target = ArrayVec<[f32; MAX_HARMONICS]>;
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
-impl std::ops::DerefMut for EnhancedSpectrals {
+impl core::ops::DerefMut for EnhancedSpectrals {
     fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
 }
 
@@ -166,6 +190,14 @@ impl Default for EnhancedSpectrals {
     }
 }
 
+impl Persist for EnhancedSpectrals {
+    fn save(&self, w: &mut Writer) { w.f32_list(&self.0[..]) }
+
+    fn load(r: &mut Reader) -> Result<EnhancedSpectrals, SnapshotError> {
+        Ok(EnhancedSpectrals(r.f32_list()?))
+    }
+}
+
 /// Compute the spectral amplitude threshold τ<sub>M</sub> used in adaptive smoothing from
 /// the given error characteristics and previous amplitude threshold.
 pub fn amp_thresh(errors: &EnhanceErrors, prev: f32) -> f32 {
@@ -177,21 +209,26 @@ pub fn amp_thresh(errors: &EnhanceErrors, prev: f32) -> f32 {
     }
 }
 
-/// Smooth the given enhanced spectral amplitudes M<sub>l</sub> and voiced/unvoiced
-/// decisions v<sub>l</sub> based on the given error characteristics, current frame
-/// energy, and spectral amplitude threshold τ<sub>M</sub> for the current frame.
-pub fn smooth(enhanced: &mut EnhancedSpectrals, voiced: &mut VoiceDecisions,
-              errors: &EnhanceErrors, fen: &FrameEnergy, amp_thresh: f32)
-{
+/// Compute the adaptive threshold V<sub>M</sub> above which enhanced spectral amplitudes
+/// are forced voiced in adaptive smoothing from the given error characteristics and
+/// current frame energy.
+pub fn voice_thresh(errors: &EnhanceErrors, fen: &FrameEnergy) -> f32 {
     // Compute Eq 112.
-    let thresh = if errors.rate <= 0.005 && errors.total <= 4 {
-        std::f32::MAX
+    if errors.rate <= 0.005 && errors.total <= 4 {
+        core::f32::MAX
     } else if errors.rate <= 0.0125 && errors.hamming_init == 0 {
-        45.255 * fen.tracking.powf(0.375) / (277.26 * errors.rate).exp()
+        45.255 * math::powf(fen.tracking, 0.375) / math::exp(277.26 * errors.rate)
     } else {
-        1.414 * fen.tracking.powf(0.375)
-    };
+        1.414 * math::powf(fen.tracking, 0.375)
+    }
+}
 
+/// Smooth the given enhanced spectral amplitudes M<sub>l</sub> and voiced/unvoiced
+/// decisions v<sub>l</sub> based on the given adaptive threshold V<sub>M</sub> and
+/// spectral amplitude threshold τ<sub>M</sub> for the current frame.
+pub fn smooth(enhanced: &mut EnhancedSpectrals, voiced: &mut VoiceDecisions, thresh: f32,
+              amp_thresh: f32)
+{
     // Update voiced/unvoiced decisions according to Eq 113.
     for (l, &m) in enhanced.iter().enumerate() {
         if m > thresh {
@@ -205,7 +242,9 @@ pub fn smooth(enhanced: &mut EnhancedSpectrals, voiced: &mut VoiceDecisions,
     let scale = (amp_thresh / amp).min(1.0);
 
     // Scale each enhanced M_l [p50].
-    enhanced.map_in_place(|&m| m * scale);
+    for m in enhanced.iter_mut() {
+        *m *= scale;
+    }
 }
 
 /// Check whether the current frame should be discarded and the previous repeated based on
@@ -403,7 +442,7 @@ mod tests {
         assert!(!voice.is_voiced(15));
         assert!(voice.is_voiced(16));
 
-        smooth(&mut amps, &mut voice, &err, &fe, 42.0);
+        smooth(&mut amps, &mut voice, voice_thresh(&err, &fe), 42.0);
 
         assert!(voice.is_voiced(1));
         assert!(voice.is_voiced(2));
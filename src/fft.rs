@@ -0,0 +1,93 @@
+//! Radix-2 fast Fourier transform.
+
+use core::f32::consts::PI;
+
+use complex::Complex32;
+use math;
+
+/// Compute the inverse DFT
+///
+/// > x(n) = X(0) + X(1) exp(*j* 2π(1)n/N) + ··· + X(N-1) exp(*j* 2π(N-1)n/N)
+///
+/// of the given N points in place, without the 1/N normalization. N must be a power of
+/// two.
+pub fn inverse(buf: &mut [Complex32]) {
+    let size = buf.len();
+    assert!(size.is_power_of_two());
+
+    if size < 2 {
+        return;
+    }
+
+    // Shift to discard the unused upper bits after reversing an index.
+    let shift = usize::BITS - size.trailing_zeros();
+
+    // Put the points into bit-reversed order so the butterflies can work in place.
+    for i in 0..size {
+        let j = i.reverse_bits() >> shift;
+
+        if j > i {
+            buf.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+
+    while len <= size {
+        let half = len / 2;
+        let stride = size / len;
+
+        for k in 0..half {
+            // Compute the twiddle factor exp(j 2πk/len).
+            let (sin, cos) = math::sin_cos(2.0 * PI * (k * stride) as f32 / size as f32);
+            let twiddle = Complex32::new(cos, sin);
+
+            for start in (0..size).step_by(len) {
+                let a = buf[start + k];
+                let b = buf[start + k + half] * twiddle;
+
+                buf[start + k] = a + b;
+                buf[start + k + half] = a - b;
+            }
+        }
+
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inverse() {
+        let points = (0..16).map(|i| {
+            Complex32::new((i * 7 % 5) as f32 - 2.0, (i * 3 % 4) as f32 - 1.5)
+        }).collect::<Vec<_>>();
+
+        let mut buf = points.clone();
+        inverse(&mut buf[..]);
+
+        for (n, &x) in buf.iter().enumerate() {
+            let expected = points.iter().enumerate().map(|(m, &p)| {
+                let (sin, cos) = (2.0 * PI * (m * n) as f32 / 16.0).sin_cos();
+                p * Complex32::new(cos, sin)
+            }).fold(Complex32::new(0.0, 0.0), |s, x| s + x);
+
+            assert!((x - expected).norm_sqr() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_impulse() {
+        let mut buf = [Complex32::new(0.0, 0.0); 8];
+        buf[1] = Complex32::new(1.0, 0.0);
+        inverse(&mut buf[..]);
+
+        for (n, x) in buf.iter().enumerate() {
+            let (sin, cos) = (2.0 * PI * n as f32 / 8.0).sin_cos();
+            assert!((x.re - cos).abs() < 1e-6);
+            assert!((x.im - sin).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Decode IMBE frames into 16-bit PCM with integer arithmetic.
+
+#[cfg(feature = "std")]
+use rand;
+use rand::{Rng, XorShiftRng};
+
+use descramble::{try_descramble, Bootstrap, VoiceDecisions};
+use frame::{FrameError, ReceivedFrame};
+use params::BaseParams;
+use pcm::PcmBuf;
+
+use super::enhance::{self, EnhancedSpectrals, EnhanceErrors, FrameEnergy};
+use super::params::{self, DEFAULT_FUNDAMENTAL};
+use super::spectral::{self, Spectrals};
+use super::synth::{self, Excitation, Phase, PhaseBase, UnvoicedDft};
+
+/// Parameters saved from the previous frame, as in `prev::PrevFrame`.
+#[derive(Clone)]
+struct PrevFrame {
+    /// Fundamental frequency ω<sub>0</sub> in radians per sample, as computed by
+    /// `BaseParams::new`.
+    fundamental: f32,
+    /// Fundamental frequency ω<sub>0</sub> as a binary angle per sample.
+    step: u32,
+    /// Number of harmonics L.
+    harmonics: u32,
+    spectrals: Spectrals,
+    enhanced: EnhancedSpectrals,
+    voice: VoiceDecisions,
+    err_rate: i32,
+    energy: FrameEnergy,
+    amp_thresh: i32,
+    unvoiced: UnvoicedDft,
+    phase_base: PhaseBase,
+    phase: Phase,
+}
+
+impl Default for PrevFrame {
+    fn default() -> PrevFrame {
+        let params = BaseParams::default();
+
+        PrevFrame {
+            fundamental: params.fundamental,
+            step: DEFAULT_FUNDAMENTAL,
+            harmonics: params.harmonics,
+            spectrals: Spectrals::default(),
+            enhanced: EnhancedSpectrals::default(),
+            voice: VoiceDecisions::default(),
+            err_rate: 0,
+            energy: FrameEnergy::default(),
+            amp_thresh: 0,
+            unvoiced: UnvoicedDft::default(),
+            phase_base: PhaseBase::default(),
+            phase: Phase::default(),
+        }
+    }
+}
+
+/// Decodes a stream of IMBE frames using only integer arithmetic in the signal path.
+///
+/// This follows the same steps as `ImbeDecoder`, and its output deviates from that of
+/// `ImbeDecoder::decode_i16` by the bounds documented in the `fixed` module.
+pub struct FixedDecoder<R: Rng = XorShiftRng> {
+    /// Tracks saved parameters across frames.
+    prev: PrevFrame,
+    /// Source of random terms in synthesis.
+    rng: R,
+}
+
+#[cfg(feature = "std")]
+impl Default for FixedDecoder {
+    fn default() -> FixedDecoder { FixedDecoder::new() }
+}
+
+#[cfg(feature = "std")]
+impl FixedDecoder {
+    /// Create a new `FixedDecoder` in the default state that draws random terms from a
+    /// generator seeded by the OS.
+    pub fn new() -> FixedDecoder {
+        FixedDecoder::with_rng(rand::weak_rng())
+    }
+}
+
+impl<R: Rng> FixedDecoder<R> {
+    /// Create a new `FixedDecoder` in the default state that draws random terms from the
+    /// given generator.
+    pub fn with_rng(rng: R) -> FixedDecoder<R> {
+        FixedDecoder {
+            prev: PrevFrame::default(),
+            rng: rng,
+        }
+    }
+
+    /// Decode the given frame into the given 16-bit PCM sample buffer.
+    ///
+    /// The error counts are trusted as given, as in `ImbeDecoder::decode`. Panics if the
+    /// frame can't be descrambled, as described in `try_decode`.
+    pub fn decode(&mut self, frame: ReceivedFrame, out: &mut PcmBuf) {
+        if let Err(e) = self.decode_frame(frame, out) {
+            panic!("unable to decode frame: {}", e);
+        }
+    }
+
+    /// Decode the given frame into the given 16-bit PCM sample buffer as in `decode`,
+    /// first checking the frame, including its error counts, as in
+    /// `ReceivedFrame::check`.
+    ///
+    /// On error, the buffer and decoder state are unchanged.
+    pub fn try_decode(&mut self, frame: ReceivedFrame, out: &mut PcmBuf)
+        -> Result<(), FrameError>
+    {
+        frame.check()?;
+        self.decode_frame(frame, out)
+    }
+
+    /// Decode the given frame into the given buffer, failing if it can't be
+    /// descrambled.
+    fn decode_frame(&mut self, frame: ReceivedFrame, out: &mut PcmBuf)
+        -> Result<(), FrameError>
+    {
+        let period = match Bootstrap::new(&frame.chunks) {
+            Bootstrap::Period(p) => p,
+            Bootstrap::Invalid => {
+                // Repeat previous frame on invalid period [p46].
+                self.repeat(out);
+                return Ok(());
+            },
+            Bootstrap::Silence => {
+                let err_rate = self.prev.err_rate;
+                self.silence(err_rate, out);
+                return Ok(());
+            },
+        };
+
+        let errors = EnhanceErrors::new(&frame.errors, self.prev.err_rate);
+
+        if enhance::should_repeat(&errors) {
+            self.repeat(out);
+            return Ok(());
+        }
+
+        if enhance::should_mute(&errors) {
+            self.silence(errors.rate, out);
+            return Ok(());
+        }
+
+        let params = BaseParams::new(period);
+        let step = params::fundamental(period);
+
+        let (amps, mut voice, gain_idx) = try_descramble(&frame.chunks, &params)?;
+        let coefs = spectral::coefficients(gain_idx, &amps, &params);
+        let spectrals = Spectrals::new(&coefs[..], &params, self.prev.harmonics,
+                                       &self.prev.spectrals);
+        let energy = FrameEnergy::new(&enhance::linear(&spectrals)[..], &self.prev.energy,
+                                      step);
+
+        let mut enhanced = EnhancedSpectrals::new(&spectrals, &energy, &params, step);
+        let amp_thresh = enhance::amp_thresh(&errors, self.prev.amp_thresh);
+        enhance::smooth(&mut enhanced, &mut voice, &errors, &energy, amp_thresh);
+
+        let udft = UnvoicedDft::new(step, &voice, &enhanced, &mut self.rng);
+        let vbase = PhaseBase::new(params.fundamental, self.prev.fundamental,
+                                   &self.prev.phase_base);
+        let vphase = Phase::new(&vbase, params.harmonics, self.prev.harmonics, &voice,
+                                &mut self.rng);
+
+        self.synthesize(&Excitation {
+            step: step,
+            voice: &voice,
+            amps: &enhanced,
+            phase: &vphase,
+            unvoiced: &udft,
+        }, params.harmonics, out);
+
+        // Save current parameters.
+        self.prev = PrevFrame {
+            fundamental: params.fundamental,
+            step: step,
+            harmonics: params.harmonics,
+            spectrals: spectrals,
+            enhanced: enhanced,
+            voice: voice,
+            err_rate: errors.rate,
+            energy: energy,
+            amp_thresh: amp_thresh,
+            unvoiced: udft,
+            phase_base: vbase,
+            phase: vphase,
+        };
+
+        Ok(())
+    }
+
+    /// Synthesize a silent or muted frame with the given error rate into the given
+    /// buffer, moving to the quiet state as `ImbeDecoder` does.
+    fn silence(&mut self, err_rate: i32, out: &mut PcmBuf) {
+        let mut voice = self.prev.voice;
+        voice.clear();
+
+        let quiet = PrevFrame {
+            fundamental: self.prev.fundamental,
+            step: self.prev.step,
+            harmonics: self.prev.harmonics,
+            spectrals: self.prev.spectrals.clone(),
+            enhanced: EnhancedSpectrals::default(),
+            voice: voice,
+            err_rate: err_rate,
+            energy: self.prev.energy.clone(),
+            amp_thresh: self.prev.amp_thresh,
+            unvoiced: UnvoicedDft::default(),
+            phase_base: PhaseBase::default(),
+            phase: Phase::default(),
+        };
+
+        self.synthesize(&Excitation {
+            step: quiet.step,
+            voice: &quiet.voice,
+            amps: &quiet.enhanced,
+            phase: &quiet.phase,
+            unvoiced: &quiet.unvoiced,
+        }, quiet.harmonics, out);
+
+        self.prev = quiet;
+    }
+
+    /// Repeat the previous frame into the given buffer.
+    ///
+    /// This is done internally for frames that fail error checks, and can also be used to
+    /// conceal a frame that was lost entirely.
+    pub fn repeat(&mut self, out: &mut PcmBuf) {
+        let (fundamental, step) = (self.prev.fundamental, self.prev.step);
+        let harmonics = self.prev.harmonics;
+        let voice = self.prev.voice;
+        let enhanced = self.prev.enhanced.clone();
+
+        let udft = UnvoicedDft::new(step, &voice, &enhanced, &mut self.rng);
+        let vbase = PhaseBase::new(fundamental, fundamental, &self.prev.phase_base);
+        let vphase = Phase::new(&vbase, harmonics, harmonics, &voice, &mut self.rng);
+
+        // Repeat frame using previous parameters [p47].
+        self.synthesize(&Excitation {
+            step: step,
+            voice: &voice,
+            amps: &enhanced,
+            phase: &vphase,
+            unvoiced: &udft,
+        }, harmonics, out);
+
+        // Save the advanced phase and noise so they continue across repeats.
+        self.prev.unvoiced = udft;
+        self.prev.phase_base = vbase;
+        self.prev.phase = vphase;
+    }
+
+    /// Synthesize the current frame from the given excitation and number of harmonics
+    /// into the given buffer.
+    fn synthesize(&self, cur: &Excitation, harmonics: u32, out: &mut PcmBuf) {
+        let prev = Excitation {
+            step: self.prev.step,
+            voice: &self.prev.voice,
+            amps: &self.prev.enhanced,
+            phase: &self.prev.phase,
+            unvoiced: &self.prev.unvoiced,
+        };
+
+        let end = harmonics.max(self.prev.harmonics) as usize;
+
+        synth::synthesize(&prev, cur, end, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixed::MAX_VOICED_DEVIATION;
+    use consts::SAMPLES_PER_FRAME;
+    use decode::{ImbeDecoder, Threading};
+    use descramble::descramble;
+    use frame::ReceivedFrame;
+    use rand::{SeedableRng, XorShiftRng};
+
+    /// Create a frame with the given first chunk, which mostly determines the period, and
+    /// fixed remaining chunks.
+    fn voiced_frame(u0: u32) -> ReceivedFrame {
+        ReceivedFrame::new([
+            u0,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b11111111111,
+            0b11111111111,
+            0b01110111011,
+            0b00001000,
+        ], [0; 7])
+    }
+
+    /// Check if the given frame has a valid period and no unvoiced bands.
+    fn fully_voiced(frame: &ReceivedFrame) -> bool {
+        match Bootstrap::new(&frame.chunks) {
+            Bootstrap::Period(p) => {
+                let (_, voice, _) = descramble(&frame.chunks, &BaseParams::new(p));
+                voice.unvoiced_count() == 0
+            },
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_voiced_deviation() {
+        let frames = (0..4096).filter(|&u0| fully_voiced(&voiced_frame(u0)))
+            .collect::<Vec<_>>();
+
+        let mut max = 0;
+        let mut exact = 0;
+        let mut total = 0;
+
+        // Decode pairs of frames from the initial state, covering the range of periods.
+        for (i, &u0) in frames.iter().enumerate() {
+            let mut fixed = FixedDecoder::with_rng(XorShiftRng::new_unseeded());
+            let mut float = ImbeDecoder::with_rng_threading(XorShiftRng::new_unseeded(),
+                                                            Threading::Single);
+
+            for &u0 in &[u0, frames[(i + 131) % frames.len()]] {
+                let mut out = [0; SAMPLES_PER_FRAME];
+                let mut expected = [0; SAMPLES_PER_FRAME];
+
+                fixed.decode(voiced_frame(u0), &mut out);
+                float.decode_i16(voiced_frame(u0), &mut expected);
+
+                // Skip samples where the float decoder saturated.
+                for (&a, &b) in out.iter().zip(expected.iter()).filter(|&(_, &b)| {
+                    b > i16::MIN && b < i16::MAX
+                }) {
+                    let diff = (a as i32 - b as i32).abs();
+
+                    max = max.max(diff);
+                    exact += (diff == 0) as usize;
+                    total += 1;
+                }
+            }
+        }
+
+        assert!(max <= MAX_VOICED_DEVIATION);
+        assert!(exact as f32 / total as f32 > 0.95);
+    }
+
+    #[test]
+    fn test_voiced_drift() {
+        let frames = (0..4096).filter(|&u0| fully_voiced(&voiced_frame(u0)))
+            .collect::<Vec<_>>();
+
+        let mut fixed = FixedDecoder::with_rng(XorShiftRng::new_unseeded());
+        let mut float = ImbeDecoder::with_rng_threading(XorShiftRng::new_unseeded(),
+                                                        Threading::Single);
+
+        // Decode a long stream so the phase base grows over many frames.
+        for i in 0..1000 {
+            let u0 = frames[(i * 131) % frames.len()];
+
+            let mut out = [0; SAMPLES_PER_FRAME];
+            let mut expected = [0; SAMPLES_PER_FRAME];
+
+            fixed.decode(voiced_frame(u0), &mut out);
+            float.decode_i16(voiced_frame(u0), &mut expected);
+
+            assert!(out.iter().zip(expected.iter()).filter(|&(_, &b)| {
+                b > i16::MIN && b < i16::MAX
+            }).all(|(&a, &b)| {
+                (a as i32 - b as i32).abs() <= MAX_VOICED_DEVIATION + i as i32 + 1
+            }));
+        }
+    }
+
+    /// Decode a run of frames with unvoiced bands using the given decoders, and return
+    /// the output of each.
+    fn decode_mixed<R: Rng>(fixed: &mut FixedDecoder<R>, float: &mut ImbeDecoder<R>)
+        -> (Vec<i16>, Vec<i16>)
+    {
+        let (mut a, mut b) = (vec![], vec![]);
+
+        for _ in 0..8 {
+            let frame = || ReceivedFrame::new([
+                0b001000010010,
+                0b110011001100,
+                0b111000111000,
+                0b111111111111,
+                0b10100110101,
+                0b00101111010,
+                0b01110111011,
+                0b00001000,
+            ], [0; 7]);
+
+            let mut out = [0; SAMPLES_PER_FRAME];
+            fixed.decode(frame(), &mut out);
+            a.extend_from_slice(&out[..]);
+
+            float.decode_i16(frame(), &mut out);
+            b.extend_from_slice(&out[..]);
+        }
+
+        (a, b)
+    }
+
+    #[test]
+    fn test_unvoiced_energy() {
+        let seeded = || XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        let mut fixed = FixedDecoder::with_rng(seeded());
+        let mut float = ImbeDecoder::with_rng_threading(seeded(), Threading::Single);
+        let (a, b) = decode_mixed(&mut fixed, &mut float);
+
+        let energy = |x: &[i16]| x.iter().fold(0.0, |s, &x| s + (x as f64).powi(2));
+
+        assert!(a.iter().any(|&x| x != 0));
+        assert!((energy(&a[..]) / energy(&b[..]) - 1.0).abs() < 0.2);
+
+        // Identically seeded decoders produce identical output.
+        let mut other = FixedDecoder::with_rng(seeded());
+        let (c, _) = decode_mixed(&mut other, &mut float);
+        assert_eq!(a, c);
+    }
+
+    #[test]
+    fn test_mute() {
+        let mut dec = FixedDecoder::with_rng(XorShiftRng::new_unseeded());
+        let mut out = [1; SAMPLES_PER_FRAME];
+
+        // A run of frames with valid errors pushes the error rate over the muting
+        // threshold on the 50th frame, and the frame after that has faded out.
+        for i in 0..51 {
+            let mut frame = voiced_frame(0b001000010010);
+            frame.errors = [1, 3, 3, 3, 1, 1, 1];
+
+            dec.try_decode(frame, &mut out).unwrap();
+            assert_eq!(out.iter().all(|&x| x == 0), i == 50);
+        }
+
+        dec.decode(voiced_frame(0b001000010010), &mut out);
+        assert!(out.iter().any(|&x| x != 0));
+    }
+
+    #[test]
+    fn test_quiet_state() {
+        let mut dec = FixedDecoder::with_rng(XorShiftRng::new_unseeded());
+        let mut reference = FixedDecoder::with_rng(XorShiftRng::new_unseeded());
+        let mut out = [0; SAMPLES_PER_FRAME];
+
+        dec.decode(voiced_frame(0b001000010010), &mut out);
+        reference.decode(voiced_frame(0b001000010010), &mut out);
+
+        // Silence clears the synthesis state but keeps the predictor and trackers.
+        let silence = ReceivedFrame::new([0b110110000000, 0, 0, 0, 0, 0, 0, 0], [0; 7]);
+        dec.decode(silence, &mut out);
+        assert!(dec.prev.enhanced.is_empty());
+        assert_eq!(&dec.prev.spectrals[..], &reference.prev.spectrals[..]);
+
+        dec.decode(voiced_frame(0b010000110010), &mut out);
+        reference.decode(voiced_frame(0b010000110010), &mut out);
+
+        assert_eq!(&dec.prev.spectrals[..], &reference.prev.spectrals[..]);
+        assert_eq!(&dec.prev.enhanced[..], &reference.prev.enhanced[..]);
+        assert_eq!(dec.prev.amp_thresh, reference.prev.amp_thresh);
+    }
+}
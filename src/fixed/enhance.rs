@@ -0,0 +1,368 @@
+//! Fixed-point spectral amplitude enhancement and adaptive smoothing.
+//!
+//! This mirrors the float `enhance` module. The error rate ϵ<sub>R</sub> is Q24,
+//! energies are Q16, and the enhancement weights of Eq 107 are computed in the log domain
+//! after normalizing R<sub>M1</sub> by R<sub>M0</sub>, so the powers of the frame
+//! energy never need to be formed.
+
+use core;
+
+use arrayvec::ArrayVec;
+
+use consts::MAX_HARMONICS;
+use descramble::VoiceDecisions;
+use frame::Errors;
+use params::BaseParams;
+
+use super::math::{self, LOG_BITS, LOG_ONE, TRIG_BITS};
+use super::spectral::Spectrals;
+
+/// Number of fractional bits in the error rate ϵ<sub>R</sub>.
+pub const RATE_BITS: u32 = 24;
+/// Number of fractional bits in linear amplitudes.
+pub const AMP_BITS: u32 = 12;
+/// Number of fractional bits in energies.
+pub const ENERGY_BITS: u32 = 16;
+
+/// Number of fractional bits in the normalized correlation r = R<sub>M1</sub> /
+/// R<sub>M0</sub>.
+const CORR_BITS: u32 = 24;
+
+/// log<sub>2</sub>(0.96π), the scale of Eq 107.
+const LOG_WEIGHT_SCALE: i32 = math::to_fixed(1.5926024, LOG_BITS);
+/// log<sub>2</sub>(1.2), the maximum weight of Eq 107.
+const LOG_MAX_WEIGHT: i32 = math::to_fixed(0.2630344, LOG_BITS);
+/// log<sub>2</sub>(2π), used to convert binary angles to radians.
+const LOG_TURN: i32 = math::to_fixed(2.6514961, LOG_BITS);
+
+/// Convert the given error rate to Q24.
+const fn rate(x: f32) -> i64 {
+    math::to_fixed(x, RATE_BITS) as i64
+}
+
+/// Error characteristics of the current frame, as in `enhance::EnhanceErrors`.
+pub struct EnhanceErrors {
+    /// Total errors detected in the current frame, ϵ<sub>T</sub> [p45].
+    pub total: usize,
+    /// Error rate tracking term, ϵ<sub>R</sub> [p45], in Q24.
+    pub rate: i32,
+    /// Errors corrected in the first Golay-coded chunk, ϵ<sub>0</sub>.
+    pub golay_init: usize,
+    /// Errors corrected in the first Hamming-coded chunk, ϵ<sub>4</sub>.
+    pub hamming_init: usize,
+}
+
+impl EnhanceErrors {
+    /// Create a new `EnhanceErrors` from the given error counts and previous error rate.
+    pub fn new(errors: &Errors, prev_rate: i32) -> EnhanceErrors {
+        let total = errors.iter().sum::<usize>();
+
+        EnhanceErrors {
+            total: total,
+            // Compute Eq 96.
+            rate: ((19 * prev_rate as i64 + 10) / 20 + rate(0.000365) * total as i64)
+                as i32,
+            golay_init: errors[0],
+            hamming_init: errors[4],
+        }
+    }
+}
+
+/// Frame energy terms, as in `enhance::FrameEnergy`.
+#[derive(Clone)]
+pub struct FrameEnergy {
+    /// R<sub>M0</sub> in Q16.
+    pub energy: u64,
+    /// R<sub>M1</sub> in Q16.
+    pub scaled: i64,
+    /// S<sub>E</sub> in Q16.
+    pub tracking: u64,
+}
+
+impl FrameEnergy {
+    /// Compute the energy terms (Eqs 105, 106, and 111) for the given linear spectral
+    /// amplitudes, previous energy, and fundamental frequency as a binary angle.
+    pub fn new(amps: &[i32], prev: &FrameEnergy, step: u32) -> FrameEnergy {
+        let powers = amps.iter().map(|&m| (m as i64 * m as i64) >> (2 * AMP_BITS -
+                                                                      ENERGY_BITS));
+
+        let energy = powers.clone().sum::<i64>();
+
+        // Powers can use up to 48 bits, so the products with the cosines need 64.
+        let scaled = powers.enumerate().map(|(l, x)| {
+            let cos = math::cos(step.wrapping_mul(l as u32 + 1));
+
+            ((x as i128 * cos as i128) >> TRIG_BITS) as i64
+        }).sum::<i64>();
+
+        FrameEnergy {
+            energy: energy as u64,
+            scaled: scaled,
+            tracking: ((19 * prev.tracking + energy as u64) / 20)
+                .max(10000 << ENERGY_BITS),
+        }
+    }
+}
+
+impl Default for FrameEnergy {
+    fn default() -> FrameEnergy {
+        FrameEnergy {
+            energy: 0,
+            scaled: 0,
+            tracking: 75000 << ENERGY_BITS,
+        }
+    }
+}
+
+/// Compute the linear amplitudes M<sub>l</sub> in Q12 for the given log-domain
+/// amplitudes.
+pub fn linear(spectrals: &Spectrals) -> ArrayVec<[i32; MAX_HARMONICS]> {
+    spectrals.iter().map(|&m| math::exp2(m, AMP_BITS)).collect()
+}
+
+/// Enhanced spectral amplitudes M̄<sub>l</sub>, 1 ≤ l ≤ L, linear in Q12.
+#[derive(Clone)]
+pub struct EnhancedSpectrals(ArrayVec<[i32; MAX_HARMONICS]>);
+
+impl EnhancedSpectrals {
+    /// Compute the enhanced amplitudes (Eqs 107 through 110) from the given spectral
+    /// amplitudes and energy terms.
+    pub fn new(spectrals: &Spectrals, fen: &FrameEnergy, params: &BaseParams,
+               step: u32)
+        -> EnhancedSpectrals
+    {
+        // Normalize both energies so the correlation can be formed without overflow.
+        let shift = (64 - fen.energy.leading_zeros()).saturating_sub(38);
+        let energy = (fen.energy >> shift) as i64;
+        let scaled = fen.scaled >> shift;
+
+        let one = 1i64 << CORR_BITS;
+
+        // The correlation r = R_M1 / R_M0, which lies in [-1, 1].
+        let corr = if energy == 0 {
+            0
+        } else {
+            (scaled << CORR_BITS) / energy
+        };
+
+        let corr_sqr = (corr * corr) >> CORR_BITS;
+
+        // Dividing the numerator and denominator of Eq 107 by R_M0^3 leaves
+        // (1 + r^2 - 2r cos(ω0 l)) / (ω0 R_M0 (1 - r^2)), so the log of the common terms
+        // 0.96π / (ω0 R_M0 (1 - r^2)) is shared by all harmonics.
+        let common = if energy == 0 || corr_sqr >= one {
+            None
+        } else {
+            Some(
+                LOG_WEIGHT_SCALE -
+                    log_fundamental(step) -
+                    (math::log2(fen.energy) - ((ENERGY_BITS as i32) << LOG_BITS)) -
+                    (math::log2((one - corr_sqr) as u64) -
+                        ((CORR_BITS as i32) << LOG_BITS))
+            )
+        };
+
+
+        let logs = spectrals.iter().enumerate().map(|(l, &m)| {
+            let l = l + 1;
+
+            if 8 * l as u32 <= params.harmonics {
+                return m;
+            }
+
+            let weight = match common {
+                Some(common) => {
+                    let cos = math::cos(step.wrapping_mul(l as u32)) as i64;
+                    let cross = (corr * cos) >> (TRIG_BITS - 1);
+                    let numer = (one + corr_sqr - cross).max(1);
+
+                    m / 2 + (common + math::log2(numer as u64) -
+                             ((CORR_BITS as i32) << LOG_BITS)) / 4
+                },
+                // The denominator vanishes, so the weight takes its maximum.
+                None => LOG_MAX_WEIGHT,
+            };
+
+            m + weight.clamp(-LOG_ONE, LOG_MAX_WEIGHT)
+        }).collect::<ArrayVec<[i32; MAX_HARMONICS]>>();
+
+        let amps = logs.iter().map(|&m| math::exp2(m, AMP_BITS))
+            .collect::<ArrayVec<[i32; MAX_HARMONICS]>>();
+
+        let enhanced_energy = amps.iter()
+            .map(|&m| ((m as i64 * m as i64) >> (2 * AMP_BITS - ENERGY_BITS)) as u64)
+            .sum::<u64>();
+
+        if fen.energy == 0 || enhanced_energy == 0 {
+            return EnhancedSpectrals(amps);
+        }
+
+        // Compute Eq 109 in the log domain and apply Eq 110.
+        let scale = (math::log2(fen.energy) - math::log2(enhanced_energy)) / 2;
+
+        EnhancedSpectrals(logs.iter().map(|&m| math::exp2(m + scale, AMP_BITS)).collect())
+    }
+
+    /// Retrieve the enhanced spectral amplitude M̄<sub>l</sub>, 1 ≤ l ≤ L, or 0 for l >
+    /// L.
+    pub fn get(&self, l: usize) -> i32 {
+        assert!(l >= 1);
+
+        match self.0.get(l - 1) {
+            Some(&s) => s,
+            None => 0,
+        }
+    }
+}
+
+impl core::ops::Deref for EnhancedSpectrals {
+    type Target = ArrayVec<[i32; MAX_HARMONICS]>;
+    fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl core::ops::DerefMut for EnhancedSpectrals {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
+}
+
+impl Default for EnhancedSpectrals {
+    fn default() -> EnhancedSpectrals {
+        EnhancedSpectrals(ArrayVec::new())
+    }
+}
+
+/// Compute log<sub>2</sub>ω<sub>0</sub> for the given fundamental frequency as a binary
+/// angle.
+fn log_fundamental(step: u32) -> i32 {
+    LOG_TURN + math::log2(step as u64) - (32 << LOG_BITS)
+}
+
+/// Compute the amplitude threshold τ<sub>M</sub> (Eq 115).
+pub fn amp_thresh(errors: &EnhanceErrors, prev: i32) -> i32 {
+    if errors.rate as i64 <= rate(0.005) && errors.total <= 6 {
+        20480
+    } else {
+        (6000 - 300 * errors.total as i32).saturating_add(prev)
+    }
+}
+
+/// Perform adaptive smoothing (Eqs 112 through 116) on the given amplitudes and voicing
+/// decisions.
+pub fn smooth(enhanced: &mut EnhancedSpectrals, voiced: &mut VoiceDecisions,
+              errors: &EnhanceErrors, fen: &FrameEnergy, amp_thresh: i32)
+{
+    let tracking = math::log2(fen.tracking) - ((ENERGY_BITS as i32) << LOG_BITS);
+
+    // Compute Eq 112 in the log domain, using 45.255 = 2^5.5 and exp(277.26 x) =
+    // 2^(400 x).
+    let thresh = if errors.rate as i64 <= rate(0.005) && errors.total <= 4 {
+        None
+    } else if errors.rate as i64 <= rate(0.0125) && errors.hamming_init == 0 {
+        Some(math::to_fixed(5.5, LOG_BITS) + 3 * tracking / 8 -
+             ((400 * errors.rate as i64) >> (RATE_BITS - LOG_BITS)) as i32)
+    } else {
+        // 1.414 = 2^0.4997821.
+        Some(math::to_fixed(0.4997821, LOG_BITS) + 3 * tracking / 8)
+    };
+
+    if let Some(thresh) = thresh {
+        let thresh = math::exp2(thresh, AMP_BITS);
+
+        for (l, &m) in enhanced.iter().enumerate() {
+            if m > thresh {
+                voiced.force_voiced(l + 1);
+            }
+        }
+    }
+
+    let amp = enhanced.iter().fold(0, |s, &m| s + m as i64);
+    let thresh = (amp_thresh as i64) << AMP_BITS;
+
+    if amp > thresh {
+        for m in enhanced.iter_mut() {
+            *m = (*m as i128 * thresh as i128 / amp as i128) as i32;
+        }
+    }
+}
+
+/// Check if the current frame should be repeated (Eq 97).
+pub fn should_repeat(errors: &EnhanceErrors) -> bool {
+    errors.golay_init >= 2 &&
+        (errors.total as i64) << RATE_BITS >= (10 << RATE_BITS) + 40 * errors.rate as i64
+}
+
+/// Check if the current frame should be muted (Eq 98).
+pub fn should_mute(errors: &EnhanceErrors) -> bool {
+    errors.rate as i64 > rate(0.0875)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use descramble::{descramble, Bootstrap};
+    use coefs::Coefficients;
+    use enhance;
+    use fixed::params;
+    use fixed::spectral::{self, Spectrals};
+    use gain::Gains;
+    use params::BaseParams;
+    use prev::PrevFrame;
+
+    #[test]
+    fn test_errors() {
+        let prev = math::to_fixed(0.5, RATE_BITS);
+        let e = EnhanceErrors::new(&[1, 2, 3, 4, 5, 6, 7], prev);
+
+        assert_eq!(e.total, 28);
+        assert!((e.rate as f32 / 16777216.0 - 0.48522).abs() < 0.00001);
+        assert_eq!(e.golay_init, 1);
+        assert_eq!(e.hamming_init, 5);
+
+        let e = EnhanceErrors::new(&[3, 3, 3, 3, 0, 0, 0], 0);
+        assert!(should_repeat(&e));
+        assert!(!should_mute(&e));
+
+        let prev = math::to_fixed(0.1, RATE_BITS);
+        let e = EnhanceErrors::new(&[1, 0, 0, 0, 0, 0, 0], prev);
+        assert!(!should_repeat(&e));
+        assert!(should_mute(&e));
+    }
+
+    #[test]
+    fn test_enhanced() {
+        let chunks = [
+            0b001000010010,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b10100110101,
+            0b00101111010,
+            0b01110111011,
+            0b00001000,
+        ];
+
+        let period = Bootstrap::new(&chunks).unwrap_period();
+        let p = BaseParams::new(period);
+        let step = params::fundamental(period);
+        let (amps, _, gain_idx) = descramble(&chunks, &p);
+
+        let coefs = spectral::coefficients(gain_idx, &amps, &p);
+        let s = Spectrals::new(&coefs[..], &p, 30, &Spectrals::default());
+        let fe = FrameEnergy::new(&linear(&s)[..], &FrameEnergy::default(), step);
+        let e = EnhancedSpectrals::new(&s, &fe, &p, step);
+
+        let g = Gains::new(gain_idx, &amps, &p);
+        let c = Coefficients::new(&g, &amps, &p);
+        let fs = ::spectral::Spectrals::new(&c, &p, &PrevFrame::default());
+        let ffe = enhance::FrameEnergy::new(&fs, &Default::default(), &p);
+        let expected = enhance::EnhancedSpectrals::new(&fs, &ffe, &p);
+
+        assert!((fe.energy as f32 / 65536.0 / ffe.energy - 1.0).abs() < 1e-3);
+        assert!((fe.scaled as f32 / 65536.0 / ffe.scaled - 1.0).abs() < 1e-3);
+        assert!((fe.tracking as f32 / 65536.0 / ffe.tracking - 1.0).abs() < 1e-4);
+
+        for l in 1..=p.harmonics as usize {
+            let x = e.get(l) as f32 / 4096.0;
+            assert!((x - expected.get(l)).abs() < 1e-4 * expected.get(l) + 5e-4);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Integer radix-2 fast Fourier transform.
+
+use super::math::{self, TRIG_BITS};
+
+/// Compute the inverse DFT
+///
+/// > x(n) = X(0) + X(1) exp(*j* 2π(1)n/N) + ··· + X(N-1) exp(*j* 2π(N-1)n/N)
+///
+/// of the given N points in place, without the 1/N normalization, where each point is
+/// a (real, imaginary) pair. N must be a power of two, and twiddle factors are applied
+/// in Q15.
+pub fn inverse(buf: &mut [(i64, i64)]) {
+    let size = buf.len();
+    assert!(size.is_power_of_two());
+
+    if size < 2 {
+        return;
+    }
+
+    // Shift to discard the unused upper bits after reversing an index.
+    let shift = usize::BITS - size.trailing_zeros();
+
+    // Put the points into bit-reversed order so the butterflies can work in place.
+    for i in 0..size {
+        let j = i.reverse_bits() >> shift;
+
+        if j > i {
+            buf.swap(i, j);
+        }
+    }
+
+    let round = 1 << (TRIG_BITS - 1);
+    let mut len = 2;
+
+    while len <= size {
+        let half = len / 2;
+
+        for k in 0..half {
+            // Twiddle factor exp(j 2πk/len).
+            let angle = math::turns(k as u64, len as u64);
+            let (cos, sin) = (math::cos(angle) as i64, math::sin(angle) as i64);
+
+            for start in (0..size).step_by(len) {
+                let (ar, ai) = buf[start + k];
+                let (xr, xi) = buf[start + k + half];
+
+                let br = (xr * cos - xi * sin + round) >> TRIG_BITS;
+                let bi = (xr * sin + xi * cos + round) >> TRIG_BITS;
+
+                buf[start + k] = (ar + br, ai + bi);
+                buf[start + k + half] = (ar - br, ai - bi);
+            }
+        }
+
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_inverse() {
+        let points = (0..64).map(|i| {
+            ((i * 7 % 5) as i64 * 1000 - 2000, (i * 3 % 4) as i64 * 1000 - 1500)
+        }).collect::<Vec<_>>();
+
+        let mut buf = points.clone();
+        inverse(&mut buf[..]);
+
+        for (n, &(re, im)) in buf.iter().enumerate() {
+            let (er, ei) = points.iter().enumerate().map(|(m, &(pr, pi))| {
+                let (sin, cos) = (2.0 * PI * (m * n) as f64 / 64.0).sin_cos();
+                (pr as f64 * cos - pi as f64 * sin, pr as f64 * sin + pi as f64 * cos)
+            }).fold((0.0, 0.0), |(sr, si), (xr, xi)| (sr + xr, si + xi));
+
+            assert!((re as f64 - er).abs() < 20.0);
+            assert!((im as f64 - ei).abs() < 20.0);
+        }
+    }
+
+    #[test]
+    fn test_impulse() {
+        let mut buf = [(0, 0); 8];
+        buf[1] = (32768, 0);
+        inverse(&mut buf[..]);
+
+        for (n, &(re, im)) in buf.iter().enumerate() {
+            let (sin, cos) = (2.0 * PI * n as f64 / 8.0).sin_cos();
+            assert!((re as f64 - 32768.0 * cos).abs() < 2.0);
+            assert!((im as f64 - 32768.0 * sin).abs() < 2.0);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Integer arithmetic primitives for the fixed-point decoder.
+//!
+//! Angles are binary angles, where the full range of a `u32` maps onto one turn, so
+//! phase accumulation wraps exactly. Trigonometric results are Q15, and logarithms and
+//! exponents are base 2 with 16 fractional bits. All functions are table driven with
+//! linear interpolation.
+
+/// Number of fractional bits in log-domain values.
+pub const LOG_BITS: u32 = 16;
+/// Log-domain representation of 1.0.
+pub const LOG_ONE: i32 = 1 << LOG_BITS;
+/// Number of fractional bits in trigonometric values.
+pub const TRIG_BITS: u32 = 15;
+
+/// Binary angle corresponding to a quarter turn.
+const QUARTER: u32 = 1 << 30;
+/// Number of fractional bits in each sine table segment.
+const SIN_SEG_BITS: u32 = 22;
+/// Number of fractional bits in each log/exp table segment.
+const LOG_SEG_BITS: u32 = 6;
+
+/// Compute the binary angle of the given fraction of a turn, num / denom.
+pub fn turns(num: u64, denom: u64) -> u32 {
+    ((num << 32) / denom) as u32
+}
+
+/// Compute sin(θ) in Q15 for the given binary angle θ.
+pub fn sin(angle: u32) -> i32 {
+    let pos = angle & (QUARTER - 1);
+
+    // Mirror the second and fourth quadrants onto the first.
+    let pos = if angle & QUARTER == 0 {
+        pos
+    } else {
+        QUARTER - pos
+    };
+
+    let idx = (pos >> SIN_SEG_BITS) as usize;
+    let frac = (pos & ((1 << SIN_SEG_BITS) - 1)) as i64;
+
+    let lower = SIN_QUARTER[idx] as i64;
+    let upper = SIN_QUARTER[(idx + 1).min(SIN_QUARTER.len() - 1)] as i64;
+
+    let s = lower +
+        (((upper - lower) * frac + (1 << (SIN_SEG_BITS - 1))) >> SIN_SEG_BITS);
+
+    // Negate the second half turn.
+    if angle & (2 * QUARTER) == 0 {
+        s as i32
+    } else {
+        -s as i32
+    }
+}
+
+/// Compute cos(θ) in Q15 for the given binary angle θ.
+pub fn cos(angle: u32) -> i32 {
+    sin(angle.wrapping_add(QUARTER))
+}
+
+/// Compute log<sub>2</sub>(x) in the log domain for the given nonzero integer x.
+pub fn log2(x: u64) -> i32 {
+    assert!(x > 0);
+
+    let int = 63 - x.leading_zeros();
+    // Normalize the mantissa so its leading one is at bit 63.
+    let mant = x << x.leading_zeros();
+
+    let idx = ((mant >> (63 - LOG_SEG_BITS)) & ((1 << LOG_SEG_BITS) - 1)) as usize;
+    let frac = ((mant >> (63 - LOG_SEG_BITS - 16)) & 0xFFFF) as i64;
+
+    let lower = LOG2_MANT[idx] as i64;
+    let upper = LOG2_MANT[idx + 1] as i64;
+
+    let interp = ((upper - lower) * frac + (1 << 15)) >> 16;
+
+    ((int << LOG_BITS) as i64 + lower + interp) as i32
+}
+
+/// Compute 2<sup>y</sup> for the given log-domain y as an integer with the given number
+/// of fractional bits, saturating to `i32::MAX`.
+pub fn exp2(y: i32, bits: u32) -> i32 {
+    let int = (y >> LOG_BITS) + bits as i32;
+    let frac = (y & (LOG_ONE - 1)) as i64;
+
+    let idx = (frac >> (LOG_BITS - LOG_SEG_BITS)) as usize;
+    let rem = frac & ((1 << (LOG_BITS - LOG_SEG_BITS)) - 1);
+
+    let lower = EXP2_MANT[idx] as i64;
+    let upper = EXP2_MANT[idx + 1] as i64;
+
+    // Mantissa in [1, 2) with 30 fractional bits.
+    let mant = lower + (((upper - lower) * rem) >> (LOG_BITS - LOG_SEG_BITS));
+
+    let x = if int >= 30 {
+        if int - 30 > 1 {
+            return i32::MAX;
+        }
+
+        mant << (int - 30)
+    } else if int > 30 - 63 {
+        let shift = 30 - int;
+        (mant + (1 << (shift - 1))) >> shift
+    } else {
+        0
+    };
+
+    x.min(i32::MAX as i64) as i32
+}
+
+/// Compute floor(√x) for the given integer x.
+pub fn isqrt(x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+
+    // Start above the root and descend with Newton's method.
+    let mut r = 1 << (64 - x.leading_zeros()).div_ceil(2);
+
+    loop {
+        let next = (r + x / r) / 2;
+
+        if next >= r {
+            return r;
+        }
+
+        r = next;
+    }
+}
+
+/// Convert the given value to fixed point with the given number of fractional bits,
+/// rounding to nearest.
+pub const fn to_fixed(x: f32, bits: u32) -> i32 {
+    let scaled = x * (1u32 << bits) as f32;
+
+    if scaled < 0.0 {
+        (scaled - 0.5) as i32
+    } else {
+        (scaled + 0.5) as i32
+    }
+}
+
+/// Convert each value in the given table to fixed point with the given number of
+/// fractional bits.
+pub const fn to_fixed_table<const N: usize>(table: &[f32; N], bits: u32) -> [i32; N] {
+    let mut out = [0; N];
+    let mut i = 0;
+
+    while i < N {
+        out[i] = to_fixed(table[i], bits);
+        i += 1;
+    }
+
+    out
+}
+
+/// Quarter wave of sin(θ) in Q15, sampled at θ = π/2 · i/256 for 0 ≤ i ≤ 256.
+static SIN_QUARTER: [u16; 257] = [
+    0, 201, 402, 603, 804, 1005, 1206, 1407, 1608, 1809,
+    2009, 2210, 2411, 2611, 2811, 3012, 3212, 3412, 3612, 3812,
+    4011, 4211, 4410, 4609, 4808, 5007, 5205, 5404, 5602, 5800,
+    5998, 6195, 6393, 6590, 6787, 6983, 7180, 7376, 7571, 7767,
+    7962, 8157, 8351, 8546, 8740, 8933, 9127, 9319, 9512, 9704,
+    9896, 10088, 10279, 10469, 10660, 10850, 11039, 11228, 11417, 11605,
+    11793, 11980, 12167, 12354, 12540, 12725, 12910, 13095, 13279, 13463,
+    13646, 13828, 14010, 14192, 14373, 14553, 14733, 14912, 15091, 15269,
+    15447, 15624, 15800, 15976, 16151, 16326, 16500, 16673, 16846, 17018,
+    17190, 17361, 17531, 17700, 17869, 18037, 18205, 18372, 18538, 18703,
+    18868, 19032, 19195, 19358, 19520, 19681, 19841, 20001, 20160, 20318,
+    20475, 20632, 20788, 20943, 21097, 21251, 21403, 21555, 21706, 21856,
+    22006, 22154, 22302, 22449, 22595, 22740, 22884, 23028, 23170, 23312,
+    23453, 23593, 23732, 23870, 24008, 24144, 24279, 24414, 24548, 24680,
+    24812, 24943, 25073, 25202, 25330, 25457, 25583, 25708, 25833, 25956,
+    26078, 26199, 26320, 26439, 26557, 26674, 26791, 26906, 27020, 27133,
+    27246, 27357, 27467, 27576, 27684, 27791, 27897, 28002, 28106, 28209,
+    28311, 28411, 28511, 28610, 28707, 28803, 28899, 28993, 29086, 29178,
+    29269, 29359, 29448, 29535, 29622, 29707, 29792, 29875, 29957, 30038,
+    30118, 30196, 30274, 30350, 30425, 30499, 30572, 30644, 30715, 30784,
+    30853, 30920, 30986, 31050, 31114, 31177, 31238, 31298, 31357, 31415,
+    31471, 31527, 31581, 31634, 31686, 31737, 31786, 31834, 31881, 31927,
+    31972, 32015, 32058, 32099, 32138, 32177, 32214, 32251, 32286, 32319,
+    32352, 32383, 32413, 32442, 32470, 32496, 32522, 32546, 32568, 32590,
+    32610, 32629, 32647, 32664, 32679, 32693, 32706, 32718, 32729, 32738,
+    32746, 32753, 32758, 32762, 32766, 32767, 32768,
+];
+
+/// Mantissa term log<sub>2</sub>(1 + i/64) in the log domain for 0 ≤ i ≤ 64.
+static LOG2_MANT: [u32; 65] = [
+    0, 1466, 2909, 4331, 5732, 7112, 8473, 9814,
+    11136, 12440, 13727, 14996, 16248, 17484, 18704, 19909,
+    21098, 22272, 23433, 24579, 25711, 26830, 27936, 29029,
+    30109, 31178, 32234, 33279, 34312, 35334, 36346, 37346,
+    38336, 39316, 40286, 41246, 42196, 43137, 44068, 44990,
+    45904, 46809, 47705, 48593, 49472, 50344, 51207, 52063,
+    52911, 53751, 54584, 55410, 56229, 57040, 57845, 58643,
+    59434, 60219, 60997, 61769, 62534, 63294, 64047, 64794,
+    65536,
+];
+
+/// Mantissa 2<sup>i/64</sup> with 30 fractional bits for 0 ≤ i ≤ 64.
+static EXP2_MANT: [u32; 65] = [
+    1073741824, 1085434106, 1097253708, 1109202018, 1121280436, 1133490379,
+    1145833280, 1158310587, 1170923762, 1183674286, 1196563654, 1209593378,
+    1222764986, 1236080024, 1249540052, 1263146652, 1276901417, 1290805962,
+    1304861917, 1319070932, 1333434672, 1347954824, 1362633090, 1377471191,
+    1392470869, 1407633882, 1422962010, 1438457051, 1454120821, 1469955159,
+    1485961921, 1502142985, 1518500250, 1535035634, 1551751076, 1568648537,
+    1585730000, 1602997467, 1620452965, 1638098541, 1655936265, 1673968228,
+    1692196547, 1710623359, 1729250827, 1748081133, 1767116489, 1786359126,
+    1805811301, 1825475297, 1845353420, 1865448001, 1885761398, 1906295993,
+    1927054196, 1948038440, 1969251188, 1990694927, 2012372174, 2034285470,
+    2056437387, 2078830522, 2101467502, 2124350982, 2147483648,
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_trig() {
+        for i in 0..4096u64 {
+            let angle = turns(i * 7919 % 4096, 4096).wrapping_add(i as u32);
+            let theta = angle as f64 / 4294967296.0 * 2.0 * PI;
+
+            assert!((sin(angle) as f64 / 32768.0 - theta.sin()).abs() < 6e-5);
+            assert!((cos(angle) as f64 / 32768.0 - theta.cos()).abs() < 6e-5);
+        }
+
+        assert_eq!(sin(0), 0);
+        assert_eq!(cos(0), 32768);
+        assert_eq!(sin(QUARTER), 32768);
+        assert_eq!(cos(2 * QUARTER), -32768);
+        assert_eq!(sin(3 * QUARTER), -32768);
+    }
+
+    #[test]
+    fn test_log_exp() {
+        assert_eq!(log2(1), 0);
+        assert_eq!(log2(2), LOG_ONE);
+        assert_eq!(log2(1 << 40), 40 * LOG_ONE);
+
+        for &x in [3u64, 10, 1000, 75000, 123456789, 1 << 50].iter() {
+            let expected = (x as f64).log2() * 65536.0;
+            assert!((log2(x) as f64 - expected).abs() < 4.0);
+        }
+
+        assert_eq!(exp2(0, 8), 256);
+        assert_eq!(exp2(-LOG_ONE, 8), 128);
+        assert_eq!(exp2(40 * LOG_ONE, 0), i32::MAX);
+        assert_eq!(exp2(-40 * LOG_ONE, 8), 0);
+
+        for &y in [-5.3f64, -0.7, 0.25, 3.9, 12.01].iter() {
+            let x = exp2((y * 65536.0) as i32, 8) as f64 / 256.0;
+            assert!((x / y.exp2() - 1.0).abs() < 5e-5 + 1.0 / 256.0 / y.exp2());
+        }
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1), 1);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(u64::MAX), 4294967295);
+
+        for x in 1..10000u64 {
+            let r = isqrt(x * 31337);
+            assert!(r * r <= x * 31337 && (r + 1) * (r + 1) > x * 31337);
+        }
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        assert_eq!(to_fixed(1.0, 16), 65536);
+        assert_eq!(to_fixed(-2.842205, 16), -186267);
+        assert_eq!(to_fixed(0.02, 15), 655);
+    }
+}
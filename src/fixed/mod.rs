@@ -0,0 +1,49 @@
+//! Fixed-point decoding for targets without a floating-point unit.
+//!
+//! `FixedDecoder` runs the same steps as `ImbeDecoder`, but every per-harmonic and
+//! per-sample computation uses integer arithmetic:
+//!
+//! - log-domain values (gains, DCT coefficients, log<sub>2</sub>M<sub>l</sub>, and
+//!   enhancement weights) are Q16,
+//! - linear amplitudes are Q12 and energies Q16,
+//! - the error rate ϵ<sub>R</sub> is Q24,
+//! - trigonometric values and window coefficients are Q15, and
+//! - phases and frequencies are binary angles, where 2<sup>32</sup> is one turn.
+//!
+//! Sines, logarithms, and exponents come from lookup tables with linear interpolation,
+//! and the unvoiced IDFT uses an integer FFT. The only floating-point arithmetic left is
+//! the handful of scalar operations per frame in `BaseParams::new`, which is shared with
+//! the float decoder to derive the harmonic and band counts, and the `f32` phase base
+//! Ψ<sub>l</sub> of Eq 139, which is accumulated exactly as the float decoder does and
+//! converted to binary angles once per frame.
+//!
+//! ## Deviation from the Float Decoder
+//!
+//! For fully-voiced frames, which involve no random terms, the 16-bit PCM output of
+//! `FixedDecoder::decode` differs from that of `ImbeDecoder::decode_i16` by at most
+//! [`MAX_VOICED_DEVIATION`](constant.MAX_VOICED_DEVIATION.html) LSBs over the first two
+//! frames decoded from the initial state, across the full range of periods, and over 95%
+//! of samples are identical.
+//!
+//! Both decoders start each frame from the same phase base, but the float decoder
+//! evaluates each harmonic's phase ω<sub>0</sub>ln + Φ<sub>l</sub> in `f32`, which loses
+//! precision as Ψ<sub>l</sub> grows over a stream, while binary angles wrap exactly. The
+//! difference in the nth frame of a stream is therefore bounded by
+//! `MAX_VOICED_DEVIATION + n` LSBs instead.
+//!
+//! Frames with unvoiced bands use a different noise source, so they match the float
+//! decoder in band energy rather than sample by sample.
+
+mod decode;
+mod enhance;
+mod fft;
+mod math;
+mod params;
+mod spectral;
+mod synth;
+
+pub use self::decode::FixedDecoder;
+
+/// Maximum difference, in 16-bit LSBs, between fixed-point and float output for
+/// fully-voiced frames, as described above.
+pub const MAX_VOICED_DEVIATION: i32 = 4;
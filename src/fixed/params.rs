@@ -0,0 +1,31 @@
+//! Fixed-point fundamental frequency.
+
+/// Compute the fundamental frequency ω<sub>0</sub> = 4π / (b<sub>0</sub> + 39.5) (Eq 46)
+/// for the given period b<sub>0</sub> as a binary angle per sample.
+pub fn fundamental(period: u8) -> u32 {
+    // One turn is 2^32, so ω0 = 2 / (b0 + 39.5) turns = 2^34 / (2 b0 + 79).
+    ((1u64 << 34) / (2 * period as u64 + 79)) as u32
+}
+
+/// Default fundamental frequency of the previous frame, matching `BaseParams::default`.
+pub const DEFAULT_FUNDAMENTAL: u32 = 64102387;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use params::BaseParams;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_fundamental() {
+        for period in 0..=207 {
+            let expected = BaseParams::new(period).fundamental;
+            let step = fundamental(period) as f64 / 4294967296.0 * 2.0 * PI as f64;
+
+            assert!((step as f32 - expected).abs() < 1e-6);
+        }
+
+        let step = DEFAULT_FUNDAMENTAL as f64 / 4294967296.0 * 2.0 * PI as f64;
+        assert!((step as f32 - BaseParams::default().fundamental).abs() < 1e-7);
+    }
+}
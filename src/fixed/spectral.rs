@@ -0,0 +1,257 @@
+//! Fixed-point reconstruction of the spectral amplitudes.
+//!
+//! This computes the same gain IDCT, coefficient IDCTs, and spectral amplitude
+//! prediction as the `gain`, `coefs`, and `spectral` modules, but keeps every
+//! intermediate value in the log domain, so the log<sub>2</sub>M<sub>l</sub> saved
+//! for prediction never need to be recovered from linear amplitudes.
+
+use core;
+
+use arrayvec::ArrayVec;
+
+use allocs::allocs;
+use coefs::{AMPS_USED, DCT_STEP_SIZE, DCT_STD_DEV};
+use consts::{MIN_HARMONICS, MAX_HARMONICS};
+use descramble::QuantizedAmplitudes;
+use gain::{GAIN, STEPS};
+use params::BaseParams;
+
+use super::math::{self, LOG_BITS, TRIG_BITS};
+
+/// Number of gain values G<sub>m</sub>.
+const NUM_GAINS: usize = 6;
+/// Number of fractional bits in quantizer step sizes, which are small enough that Q16
+/// would lose precision when scaled by large quantized values.
+const STEP_BITS: u32 = 24;
+
+/// Gain levels G<sub>1</sub> in the log domain.
+static GAIN_LOG: [i32; 64] = math::to_fixed_table(&GAIN, LOG_BITS);
+/// Gain quantizer step sizes in Q24.
+static STEPS_FIXED: [[i32; 5]; 48] = steps_table();
+/// DCT quantizer step sizes in Q24.
+static DCT_STEP_FIXED: [i32; 10] = math::to_fixed_table(&DCT_STEP_SIZE, STEP_BITS);
+/// DCT coefficient standard deviations in Q24.
+static DCT_DEV_FIXED: [i32; 9] = math::to_fixed_table(&DCT_STD_DEV, STEP_BITS);
+
+/// Convert the gain step size table to Q24.
+const fn steps_table() -> [[i32; 5]; 48] {
+    let mut table = [[0; 5]; 48];
+    let mut i = 0;
+
+    while i < table.len() {
+        table[i] = math::to_fixed_table(&STEPS[i], STEP_BITS);
+        i += 1;
+    }
+
+    table
+}
+
+/// Compute the quantizer offset 2(b<sub>m</sub> - 2<sup>B<sub>m</sub>-1</sup> + 0.5) for
+/// the given quantized value and bit allocation.
+fn offset(b: u32, bits: u8) -> i64 {
+    2 * b as i64 - (1 << bits) + 1
+}
+
+/// Compute the IDCT value x<sub>1</sub> + 2Σ x<sub>k</sub> cos(π(k - 1)(j - 0.5) / J) of
+/// the given J coefficients at the given 1 ≤ j ≤ J.
+fn idct(coefs: &[i32], j: usize) -> i32 {
+    let len = coefs.len() as u64;
+
+    let sum = coefs[1..].iter().enumerate().map(|(k, &c)| {
+        let angle = math::turns((k as u64 + 1) * (2 * j as u64 - 1), 4 * len);
+        c as i64 * math::cos(angle) as i64
+    }).sum::<i64>();
+
+    coefs[0] + ((sum + (1 << (TRIG_BITS - 2))) >> (TRIG_BITS - 1)) as i32
+}
+
+/// Compute the gain vector G<sub>1</sub>, ..., G<sub>6</sub>.
+fn gains(gain_idx: usize, amps: &QuantizedAmplitudes, params: &BaseParams)
+    -> [i32; NUM_GAINS]
+{
+    let mut gains = [0; NUM_GAINS];
+
+    let (alloc, _) = allocs(params.harmonics);
+    let steps = &STEPS_FIXED[params.harmonics as usize - MIN_HARMONICS];
+
+    gains[0] = GAIN_LOG[gain_idx];
+
+    for m in 3..=7 {
+        let bits = alloc[m - 3];
+
+        if bits != 0 {
+            let shift = STEP_BITS - LOG_BITS + 1;
+            let gain = steps[m - 3] as i64 * offset(amps.get(m), bits);
+
+            gains[m - 2] = ((gain + (1 << (shift - 1))) >> shift) as i32;
+        }
+    }
+
+    gains
+}
+
+/// Compute the log-domain coefficients T<sub>l</sub> for 1 ≤ l ≤ L.
+pub fn coefficients(gain_idx: usize, amps: &QuantizedAmplitudes, params: &BaseParams)
+    -> ArrayVec<[i32; MAX_HARMONICS]>
+{
+    let gains = gains(gain_idx, amps, params);
+
+    let (alloc, _) = allocs(params.harmonics);
+    let blocks = &AMPS_USED[params.harmonics as usize - MIN_HARMONICS];
+
+    let mut coefs = ArrayVec::new();
+    let mut cur = 8;
+
+    for block in 1..=NUM_GAINS {
+        let mut c = ArrayVec::<[i32; 10]>::new();
+
+        // C_i,1 = R_i.
+        c.push(idct(&gains[..], block));
+
+        c.extend((cur..cur + blocks[block - 1]).enumerate().map(|(k, m)| {
+            let bits = alloc[m - 3];
+
+            if bits == 0 {
+                return 0;
+            }
+
+            let shift = 2 * STEP_BITS - LOG_BITS + 1;
+            let step = DCT_STEP_FIXED[bits as usize - 1] as i64 * DCT_DEV_FIXED[k] as i64;
+
+            ((step * offset(amps.get(m), bits) + (1 << (shift - 1))) >> shift) as i32
+        }));
+
+        coefs.extend((1..=c.len()).map(|j| idct(&c[..], j)));
+        cur += c.len() - 1;
+    }
+
+    coefs
+}
+
+/// Log-domain spectral amplitudes log<sub>2</sub>M<sub>l</sub>, 1 ≤ l ≤ L.
+#[derive(Clone)]
+pub struct Spectrals(ArrayVec<[i32; MAX_HARMONICS]>);
+
+impl Spectrals {
+    /// Predict the spectral amplitudes (Eqs 75 through 79) from the given coefficients
+    /// T<sub>l</sub> and the previous frame's harmonics and spectral amplitudes.
+    pub fn new(coefs: &[i32], params: &BaseParams, prev_harmonics: u32,
+               prev: &Spectrals)
+        -> Spectrals
+    {
+        let harmonics = params.harmonics as u64;
+
+        // Interpolate the previous log amplitudes at k_l = L(-1) l / L.
+        let interp = |l: u32| {
+            let pos = prev_harmonics as u64 * l as u64;
+            let k = (pos / harmonics) as usize;
+            let frac = ((pos % harmonics) << LOG_BITS) as i64 / harmonics as i64;
+
+            ((math::LOG_ONE as i64 - frac) * prev.get(k) as i64 +
+                frac * prev.get(k + 1) as i64) >> LOG_BITS
+        };
+
+        // ρ = clamp(0.03 L - 0.05, 0.4, 0.7).
+        let pred = (((3 * harmonics as i64 - 5) << LOG_BITS) / 100)
+            .max(math::to_fixed(0.4, LOG_BITS) as i64)
+            .min(math::to_fixed(0.7, LOG_BITS) as i64);
+
+        let sum = (1..=params.harmonics).map(interp).sum::<i64>() /
+            harmonics as i64;
+
+        Spectrals((1..=params.harmonics).map(|l| {
+            coefs[l as usize - 1] + ((pred * (interp(l) - sum)) >> LOG_BITS) as i32
+        }).collect())
+    }
+
+    /// Retrieve log<sub>2</sub>M<sub>l</sub>, with the same extension outside 1 ≤ l ≤
+    /// L as the float `Spectrals`.
+    pub fn get(&self, l: usize) -> i32 {
+        if l == 0 {
+            0
+        } else if l > self.0.len() {
+            *self.0.last().unwrap()
+        } else {
+            self.0[l - 1]
+        }
+    }
+}
+
+impl core::ops::Deref for Spectrals {
+    type Target = ArrayVec<[i32; MAX_HARMONICS]>;
+    fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl Default for Spectrals {
+    /// Create the initial spectral amplitudes M<sub>l</sub> = 1.
+    fn default() -> Spectrals {
+        Spectrals((0..MAX_HARMONICS).map(|_| 0).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coefs::Coefficients;
+    use descramble::{descramble, Bootstrap};
+    use gain::Gains;
+    use params::BaseParams;
+    use prev::PrevFrame;
+    use spectral;
+
+    const CHUNKS: [u32; 8] = [
+        0b001000010010,
+        0b110011001100,
+        0b111000111000,
+        0b111111111111,
+        0b10100110101,
+        0b00101111010,
+        0b01110111011,
+        0b00001000,
+    ];
+
+    #[test]
+    fn test_coefficients() {
+        let p = BaseParams::new(Bootstrap::new(&CHUNKS).unwrap_period());
+        let (amps, _, gain_idx) = descramble(&CHUNKS, &p);
+
+        let g = Gains::new(gain_idx, &amps, &p);
+        let expected = Coefficients::new(&g, &amps, &p);
+        let coefs = coefficients(gain_idx, &amps, &p);
+
+        assert_eq!(coefs.len(), p.harmonics as usize);
+
+        for (l, &c) in coefs.iter().enumerate() {
+            assert!((c as f32 / 65536.0 - expected.get(l + 1)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_spectrals() {
+        let p = BaseParams::new(Bootstrap::new(&CHUNKS).unwrap_period());
+        let (amps, _, gain_idx) = descramble(&CHUNKS, &p);
+
+        let g = Gains::new(gain_idx, &amps, &p);
+        let c = Coefficients::new(&g, &amps, &p);
+        let mut prev = PrevFrame::default();
+        let expected = spectral::Spectrals::new(&c, &p, &prev);
+
+        let coefs = coefficients(gain_idx, &amps, &p);
+        let s = Spectrals::new(&coefs[..], &p, 30, &Spectrals::default());
+
+        for l in 1..=p.harmonics as usize {
+            assert!((s.get(l) as f32 / 65536.0 - expected.get(l).log2()).abs() < 1e-4);
+        }
+
+        // Predict a second frame from the first.
+        prev.spectrals = expected;
+        prev.params = p;
+
+        let expected = spectral::Spectrals::new(&c, &p, &prev);
+        let s = Spectrals::new(&coefs[..], &p, p.harmonics, &s);
+
+        for l in 1..=p.harmonics as usize {
+            assert!((s.get(l) as f32 / 65536.0 - expected.get(l).log2()).abs() < 2e-4);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Fixed-point voiced and unvoiced synthesis.
+//!
+//! Phases are binary angles. The phase base Ψ<sub>l</sub> of Eq 139 is the one exception:
+//! it accumulates in `f32` as in the float decoder, so both decoders derive each frame's
+//! phases from the same values, and it's converted to a binary angle once per frame.
+//! Voiced components are evaluated directly through the sine table, and the unvoiced IDFT
+//! is evaluated with the integer FFT.
+
+use rand::Rng;
+
+use consts::{SAMPLES_PER_FRAME, MAX_HARMONICS};
+use descramble::VoiceDecisions;
+use pcm::PcmBuf;
+use window::WINDOW_SYNTHESIS;
+
+use super::enhance::{EnhancedSpectrals, AMP_BITS};
+use super::fft;
+use super::math::{self, TRIG_BITS};
+
+/// Number of fractional bits in window coefficients.
+const WINDOW_BITS: u32 = 15;
+/// Number of fractional bits in unvoiced signal samples.
+const SIGNAL_BITS: u32 = 8;
+
+/// Unvoiced scaling coefficient γ<sub>w</sub> in Q8.
+const SCALING_COEF: i64 = 37541;
+
+/// Number of points in the unvoiced IDFT.
+const IDFT_SIZE: usize = 256;
+/// Number of points in the unvoiced DFT that can be nonzero.
+const DFT_HALF: usize = IDFT_SIZE / 2;
+
+/// Speech synthesis window w<sub>s</sub> in Q15.
+static WINDOW: [i32; 211] = math::to_fixed_table(&WINDOW_SYNTHESIS, WINDOW_BITS);
+
+/// Retrieve the synthesis window coefficient w<sub>s</sub>(n).
+fn window(n: isize) -> i64 {
+    let idx = n + (WINDOW.len() / 2) as isize;
+
+    if idx < 0 {
+        0
+    } else {
+        WINDOW.get(idx as usize).map_or(0, |&w| w as i64)
+    }
+}
+
+/// Phase base Ψ<sub>l</sub> (Eq 139) in radians.
+#[derive(Clone)]
+pub struct PhaseBase([f32; MAX_HARMONICS]);
+
+impl PhaseBase {
+    /// Compute the phase base from the given current and previous fundamentals, as
+    /// computed by `BaseParams::new`, and the previous phase base.
+    ///
+    /// This is the same `f32` arithmetic as the float `PhaseBase`, so the result is
+    /// identical to that of `ImbeDecoder` for the same frames.
+    pub fn new(fundamental: f32, prev_fundamental: f32, prev: &PhaseBase) -> PhaseBase {
+        let mut base = [0.0; MAX_HARMONICS];
+        let scale = (prev_fundamental + fundamental) * SAMPLES_PER_FRAME as f32 / 2.0;
+
+        for (l, x) in (1..=MAX_HARMONICS).zip(base.iter_mut()) {
+            *x = prev.0[l - 1] + scale * l as f32;
+        }
+
+        PhaseBase(base)
+    }
+}
+
+impl Default for PhaseBase {
+    fn default() -> PhaseBase {
+        PhaseBase([0.0; MAX_HARMONICS])
+    }
+}
+
+/// Phase Φ<sub>l</sub> (Eq 140) as binary angles.
+#[derive(Clone)]
+pub struct Phase([u32; MAX_HARMONICS]);
+
+impl Phase {
+    /// Compute the phase from the given phase base, current and previous harmonics,
+    /// and voicing decisions, drawing random terms from the given generator.
+    pub fn new<R: Rng>(base: &PhaseBase, harmonics: u32, prev_harmonics: u32,
+                       voice: &VoiceDecisions, rng: &mut R)
+        -> Phase
+    {
+        let mut phase = [0; MAX_HARMONICS];
+
+        for (x, &b) in phase.iter_mut().zip(base.0.iter()) {
+            *x = to_angle(b);
+        }
+
+        let start = harmonics as usize / 4;
+        let stop = harmonics.max(prev_harmonics) as usize;
+
+        let unvoiced = voice.unvoiced_count() as i64;
+
+        for x in phase[start..stop].iter_mut() {
+            // A signed binary angle is uniform over [-π, π).
+            let noise = rng.next_u32() as i32 as i64;
+            *x = x.wrapping_add((noise * unvoiced / harmonics as i64) as i32 as u32);
+        }
+
+        Phase(phase)
+    }
+
+    /// Retrieve Φ<sub>l</sub>, 1 ≤ l ≤ 56.
+    pub fn get(&self, l: usize) -> u32 { self.0[l - 1] }
+}
+
+impl Default for Phase {
+    fn default() -> Phase {
+        Phase([0; MAX_HARMONICS])
+    }
+}
+
+/// Convert the given angle in radians to a binary angle, wrapped to one turn.
+fn to_angle(x: f32) -> u32 {
+    // An f64 holds the product to well under an LSB for any phase base reached in
+    // practice.
+    let turns = x as f64 * (4294967296.0 / (2.0 * ::core::f64::consts::PI));
+    (turns % 4294967296.0 + 0.5) as i64 as u32
+}
+
+/// Unvoiced signal u<sub>w</sub>(n) (Eq 125) in Q8.
+///
+/// Unlike the float `UnvoicedDft`, only the time-domain signal is kept.
+#[derive(Clone)]
+pub struct UnvoicedDft([i32; IDFT_SIZE]);
+
+impl UnvoicedDft {
+    /// Generate the unvoiced spectrum (Eqs 117 through 124) for the given fundamental,
+    /// voicing decisions, and enhanced amplitudes, drawing noise from the given
+    /// generator, and compute its IDFT.
+    ///
+    /// The noise is the sum of four uniform variables rather than a Gaussian, which has
+    /// no effect on the band energies after normalization.
+    pub fn new<R: Rng>(step: u32, voice: &VoiceDecisions, amps: &EnhancedSpectrals,
+                       rng: &mut R)
+        -> UnvoicedDft
+    {
+        let mut points = [(0, 0); IDFT_SIZE];
+
+        for (l, &amp) in amps.iter().enumerate() {
+            let l = l + 1;
+
+            if voice.is_voiced(l) {
+                continue;
+            }
+
+            let (lower, upper) = edges(l, step);
+            let (lower, upper) = (lower.min(DFT_HALF), upper.min(DFT_HALF));
+
+            if lower == upper {
+                continue;
+            }
+
+            for p in points[lower..upper].iter_mut() {
+                *p = (noise(rng), noise(rng));
+            }
+
+            let energy = points[lower..upper].iter()
+                .map(|&(re, im)| (re * re + im * im) as u64)
+                .sum::<u64>();
+            let rms = math::isqrt(energy / (upper - lower) as u64) as i64;
+
+            if rms == 0 {
+                continue;
+            }
+
+            let scale = (SCALING_COEF * amp as i64) >> AMP_BITS;
+
+            for p in points[lower..upper].iter_mut() {
+                *p = (p.0 * scale / rms, p.1 * scale / rms);
+            }
+        }
+
+        fft::inverse(&mut points[..]);
+
+        let mut signal = [0; IDFT_SIZE];
+        let shift = IDFT_SIZE.trailing_zeros() - 1;
+
+        for (i, s) in signal.iter_mut().enumerate() {
+            let n = (i + IDFT_SIZE - DFT_HALF) % IDFT_SIZE;
+            // Scale by 2 / 256.
+            *s = ((points[n].0 + (1 << (shift - 1))) >> shift) as i32;
+        }
+
+        UnvoicedDft(signal)
+    }
+
+    /// Retrieve u<sub>w</sub>(n) for the given n, which is zero outside -128 ≤ n < 128.
+    pub fn get(&self, n: isize) -> i64 {
+        if n < -(DFT_HALF as isize) || n >= DFT_HALF as isize {
+            return 0;
+        }
+
+        self.0[(n + DFT_HALF as isize) as usize] as i64
+    }
+}
+
+impl Default for UnvoicedDft {
+    fn default() -> UnvoicedDft {
+        UnvoicedDft([0; IDFT_SIZE])
+    }
+}
+
+/// Draw an approximately Gaussian noise sample from the given generator.
+fn noise<R: Rng>(rng: &mut R) -> i64 {
+    let (a, b) = (rng.next_u32(), rng.next_u32());
+
+    [a, a >> 16, b, b >> 16].iter().map(|&x| x as u16 as i16 as i64).sum()
+}
+
+/// Compute the DFT bin edges a<sub>l</sub> and b<sub>l</sub> (Eqs 122 and 123) of the
+/// given harmonic.
+fn edges(l: usize, step: u32) -> (usize, usize) {
+    // 256 / 2π · ω0 (l ± 0.5) = step (2l ± 1) / 2^25 for binary angle steps.
+    let edge = |m: u64| ((step as u64 * m + (1 << 25) - 1) >> 25) as usize;
+    (edge(2 * l as u64 - 1), edge(2 * l as u64 + 1))
+}
+
+/// Excitation parameters of one frame used in synthesis.
+pub struct Excitation<'a> {
+    /// Fundamental frequency ω<sub>0</sub> as a binary angle.
+    pub step: u32,
+    /// Voicing decisions v<sub>l</sub>.
+    pub voice: &'a VoiceDecisions,
+    /// Enhanced spectral amplitudes M̄<sub>l</sub>.
+    pub amps: &'a EnhancedSpectrals,
+    /// Voiced phases Φ<sub>l</sub>.
+    pub phase: &'a Phase,
+    /// Unvoiced signal u<sub>w</sub>(n).
+    pub unvoiced: &'a UnvoicedDft,
+}
+
+impl<'a> Excitation<'a> {
+    /// Compute Σ M̄<sub>l</sub> cos(ω<sub>0</sub> l t + Φ<sub>l</sub>) over the voiced
+    /// harmonics 1 ≤ l ≤ end, in Q12.
+    fn voiced(&self, t: isize, end: usize) -> i64 {
+        (1..=end).filter(|&l| self.voice.is_voiced(l)).map(|l| {
+            let angle = self.step.wrapping_mul(l as u32).wrapping_mul(t as u32)
+                .wrapping_add(self.phase.get(l));
+
+            (self.amps.get(l) as i64 * math::cos(angle) as i64) >> TRIG_BITS
+        }).sum()
+    }
+}
+
+/// Synthesize the frame from the given previous and current excitations (Eqs 126, 127,
+/// and 142) into the given buffer, summing harmonics up to the given end.
+pub fn synthesize(prev: &Excitation, cur: &Excitation, end: usize, out: &mut PcmBuf) {
+    let frame = SAMPLES_PER_FRAME as isize;
+
+    for (n, x) in out.iter_mut().enumerate() {
+        let n = n as isize;
+        let (wp, wc) = (window(n), window(n - frame));
+
+        // Compute Eq 127 in Q12.
+        let mut sum = 0;
+
+        if wp != 0 {
+            sum += wp * prev.voiced(n, end);
+        }
+
+        if wc != 0 {
+            sum += wc * cur.voiced(n - frame, end);
+        }
+
+        let voiced = sum >> (WINDOW_BITS - 1);
+
+        // Compute Eq 126 in Q8.
+        let denom = wp * wp + wc * wc;
+        let unvoiced = if denom == 0 {
+            0
+        } else {
+            let sum = wp * prev.unvoiced.get(n) + wc * cur.unvoiced.get(n - frame);
+            (sum << WINDOW_BITS) / denom
+        };
+
+        let total = voiced + (unvoiced << (AMP_BITS - SIGNAL_BITS));
+        let sample = (total + (1 << (AMP_BITS - 1))) >> AMP_BITS;
+
+        *x = sample.max(i16::MIN as i64).min(i16::MAX as i64) as i16;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use descramble::VoiceDecisions;
+    use enhance;
+    use fixed::params;
+    use params::BaseParams;
+    use prev::PrevFrame;
+    use rand::{SeedableRng, XorShiftRng};
+    use unvoiced;
+    use voiced;
+
+    #[test]
+    fn test_edges() {
+        for period in 0..=207 {
+            let p = BaseParams::new(period);
+            let step = params::fundamental(period);
+
+            for l in 1..=p.harmonics as usize {
+                let common = 256.0 / (2.0 * ::std::f64::consts::PI) *
+                    (step as f64 / 4294967296.0 * 2.0 * ::std::f64::consts::PI);
+                let expected = (
+                    (common * (l as f64 - 0.5)).ceil() as usize,
+                    (common * (l as f64 + 0.5)).ceil() as usize,
+                );
+
+                assert_eq!(edges(l, step), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_phase_base() {
+        let mut prev = PhaseBase::default();
+        let mut fprev = PrevFrame::default();
+
+        // Accumulate over a run of frames, matching the float phase base exactly.
+        for i in 0..100 {
+            let p = BaseParams::new((i * 37 % 208) as u8);
+            let base = PhaseBase::new(p.fundamental, fprev.params.fundamental, &prev);
+            let expected = voiced::PhaseBase::new(&p, &fprev);
+
+            for l in 1..=MAX_HARMONICS {
+                assert_eq!(base.0[l - 1], expected.get(l));
+            }
+
+            prev = base;
+            fprev.params = p;
+            fprev.phase_base = expected;
+        }
+    }
+
+    #[test]
+    fn test_to_angle() {
+        use std::f32::consts::PI;
+
+        // Check the angle is within the given error, in turns, of the expected angle.
+        let close = |x: f32, expected: u32, err: f64| {
+            let diff = to_angle(x).wrapping_sub(expected) as i32;
+            (diff as f64 / 4294967296.0).abs() < err
+        };
+
+        assert_eq!(to_angle(0.0), 0);
+        assert!(close(PI / 2.0, 1 << 30, 1e-7));
+        assert!(close(-PI / 2.0, 3 << 30, 1e-7));
+        assert!(close(2.0 * PI, 0, 1e-7));
+
+        // Large angles wrap to one turn, to within the precision of the f32 input.
+        assert!(close(1000.0 * PI + PI / 4.0, 1 << 29, 1e-4));
+    }
+
+    #[test]
+    fn test_unvoiced_energy() {
+        let p = BaseParams::new(42);
+        let step = params::fundamental(42);
+        let voice = VoiceDecisions::new(0, &p);
+
+        let mut amps = EnhancedSpectrals::default();
+        let mut famps = enhance::EnhancedSpectrals::default();
+
+        for l in 1..=p.harmonics {
+            amps.push(l as i32 * 4096);
+            famps.push(l as f32);
+        }
+
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let dft = UnvoicedDft::new(step, &voice, &amps, &mut rng);
+        let fdft = unvoiced::UnvoicedDft::new(&p, &voice, &famps, &mut rng);
+
+        // The band energies are fixed by normalization, so the total signal energy is
+        // deterministic.
+        let energy = (-128..128).map(|n| (dft.get(n) as f64 / 256.0).powi(2))
+            .fold(0.0, |s, x| s + x);
+        let expected = (-128..128).map(|n| (fdft.idft(n) as f64).powi(2))
+            .fold(0.0, |s, x| s + x);
+
+        assert!((energy / expected - 1.0).abs() < 1e-3);
+    }
+}
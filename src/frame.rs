@@ -1,5 +1,9 @@
 //! Received IMBE voice frame.
 
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
 use consts::SAMPLES_PER_FRAME;
 
 /// Represents the bit vectors u<sub>0</sub>, ..., u<sub>7</sub>, in that order.
@@ -9,6 +13,43 @@ pub type Chunks = [u32; 8];
 /// ϵ<sub>6</sub>, corresponding to the chunks u<sub>0</sub>, ..., u<sub>6</sub>.
 pub type Errors = [usize; 7];
 
+/// Widths in bits of the chunks u<sub>0</sub>, ..., u<sub>7</sub>.
+pub const CHUNK_BITS: [u32; 8] = [12, 12, 12, 12, 11, 11, 11, 7];
+/// Maximum errors the (23, 12) Golay code corrects in each of u<sub>0</sub>, ...,
+/// u<sub>3</sub>.
+pub const MAX_GOLAY_ERRORS: usize = 3;
+/// Maximum errors the (15, 11) Hamming code corrects in each of u<sub>4</sub>, ...,
+/// u<sub>6</sub>.
+pub const MAX_HAMMING_ERRORS: usize = 1;
+
+/// Error in the contents of a received frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FrameError {
+    /// Chunk u<sub>i</sub> at the given index has bits set beyond its width.
+    OversizedChunk(usize),
+    /// Error count ϵ<sub>i</sub> at the given index is more than the chunk's code can
+    /// correct.
+    InvalidErrors(usize),
+    /// The prioritized scan doesn't hold exactly the bits allocated to the quantized
+    /// amplitudes b<sub>m</sub>.
+    InconsistentScan,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::OversizedChunk(i) =>
+                write!(f, "chunk u{} exceeds {} bits", i, CHUNK_BITS[i]),
+            FrameError::InvalidErrors(i) =>
+                write!(f, "error count ϵ{} exceeds what its code corrects", i),
+            FrameError::InconsistentScan => write!(f, "inconsistent amplitude scan"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for FrameError {}
+
 /// A received IMBE voice frame.
 pub struct ReceivedFrame {
     /// Prioritized bit vector chunks, u<sub>0</sub>, ..., u<sub>7</sub>.
@@ -20,26 +61,116 @@ pub struct ReceivedFrame {
 impl ReceivedFrame {
     /// Create a new `ReceivedFrame` from the given chunks u<sub>0</sub>, ...,
     /// u<sub>7</sub> and error counts ϵ<sub>0</sub>, ..., ϵ<sub>6</sub>.
+    ///
+    /// Panics if a chunk has bits set beyond its width. The error counts are taken as
+    /// given, so use `try_new` to also check them.
     pub fn new(chunks: Chunks, errors: Errors) -> ReceivedFrame {
-        // First 4 chunks must have at most 12 bits.
-        for i in 0..=3 {
-            assert!(chunks[i] >> 12 == 0);
-        }
+        let frame = ReceivedFrame {
+            chunks: chunks,
+            errors: errors,
+        };
 
-        // Next 3 chunks must have at most 11 bits.
-        for i in 4..=6 {
-            assert!(chunks[i] >> 11 == 0);
+        if let Err(e) = frame.check_chunks() {
+            panic!("invalid frame: {}", e);
         }
 
-        // Final chunk must have at most 7 bits.
-        assert!(chunks[7] >> 7 == 0);
+        frame
+    }
 
-        ReceivedFrame {
+    /// Create a new `ReceivedFrame` from the given chunks u<sub>0</sub>, ...,
+    /// u<sub>7</sub> and error counts ϵ<sub>0</sub>, ..., ϵ<sub>6</sub>, checking that
+    /// each chunk fits its width and each error count is within what its code can
+    /// correct.
+    pub fn try_new(chunks: Chunks, errors: Errors) -> Result<ReceivedFrame, FrameError> {
+        let frame = ReceivedFrame {
             chunks: chunks,
             errors: errors,
+        };
+
+        frame.check()?;
+
+        Ok(frame)
+    }
+
+    /// Check that the chunks and error counts are valid, as in `try_new`.
+    pub fn check(&self) -> Result<(), FrameError> {
+        self.check_chunks()?;
+
+        for (i, &e) in self.errors.iter().enumerate() {
+            // First 4 chunks are Golay-coded and the rest are Hamming-coded.
+            let max = if i < 4 { MAX_GOLAY_ERRORS } else { MAX_HAMMING_ERRORS };
+
+            if e > max {
+                return Err(FrameError::InvalidErrors(i));
+            }
         }
+
+        Ok(())
+    }
+
+    /// Check that each chunk fits its width.
+    fn check_chunks(&self) -> Result<(), FrameError> {
+        for (i, (&c, &w)) in self.chunks.iter().zip(CHUNK_BITS.iter()).enumerate() {
+            if c >> w != 0 {
+                return Err(FrameError::OversizedChunk(i));
+            }
+        }
+
+        Ok(())
     }
 }
 
 /// Audio samples in a decoded voice frame.
 pub type AudioBuf = [f32; SAMPLES_PER_FRAME];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHUNKS: Chunks = [
+        0b001000010010,
+        0b110011001100,
+        0b111000111000,
+        0b111111111111,
+        0b10100110101,
+        0b00101111010,
+        0b01110111011,
+        0b00001000,
+    ];
+
+    #[test]
+    fn test_try_new() {
+        assert!(ReceivedFrame::try_new(CHUNKS, [3, 3, 3, 3, 1, 1, 1]).is_ok());
+
+        let mut chunks = CHUNKS;
+        chunks[4] = 1 << 11;
+        assert_eq!(ReceivedFrame::try_new(chunks, [0; 7]).err(),
+                   Some(FrameError::OversizedChunk(4)));
+
+        let mut chunks = CHUNKS;
+        chunks[7] = 1 << 7;
+        assert_eq!(ReceivedFrame::try_new(chunks, [0; 7]).err(),
+                   Some(FrameError::OversizedChunk(7)));
+
+        assert_eq!(ReceivedFrame::try_new(CHUNKS, [4, 0, 0, 0, 0, 0, 0]).err(),
+                   Some(FrameError::InvalidErrors(0)));
+        assert_eq!(ReceivedFrame::try_new(CHUNKS, [0, 0, 0, 0, 0, 2, 0]).err(),
+                   Some(FrameError::InvalidErrors(5)));
+    }
+
+    #[test]
+    fn test_new() {
+        // Error counts aren't checked.
+        let frame = ReceivedFrame::new(CHUNKS, [4, 0, 0, 0, 0, 0, 240]);
+        assert_eq!(frame.errors, [4, 0, 0, 0, 0, 0, 240]);
+        assert_eq!(frame.check(), Err(FrameError::InvalidErrors(0)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_oversized() {
+        let mut chunks = CHUNKS;
+        chunks[0] = 1 << 12;
+        ReceivedFrame::new(chunks, [0; 7]);
+    }
+}
@@ -1,12 +1,11 @@
 //! Quantized gain vector.
 
-use std::f32::consts::PI;
-
-use collect_slice::CollectSlice;
+use core::f32::consts::PI;
 
 use descramble::QuantizedAmplitudes;
 use params::BaseParams;
 use allocs::allocs;
+use math;
 
 /// Number of gain values used in the decoder.
 const NUM_GAINS: usize = 6;
@@ -28,16 +27,16 @@ impl Gains {
         gains[0] = GAIN[gain_idx];
 
         // Compute G_2, ..., G_6.
-        (3..=7).map(|m| {
+        for (g, m) in gains[1..].iter_mut().zip(3..=7) {
             let bits = alloc[m - 3];
             let step = steps[m - 3];
 
-            if bits == 0 {
+            *g = if bits == 0 {
                 0.0
             } else {
                 step * (amps.get(m) as f32 - (1 << (bits - 1)) as f32 + 0.5)
-            }
-        }).collect_slice_checked(&mut gains[1..]);
+            };
+        }
 
         Gains(gains)
     }
@@ -48,14 +47,14 @@ impl Gains {
 
         self.0[0] + 2.0 * (2..7).map(|m| {
             self.0[m - 1] *
-                (PI * (m as f32 - 1.0) * (i as f32 - 0.5) / 6.0).cos()
+                math::cos(PI * (m as f32 - 1.0) * (i as f32 - 0.5) / 6.0)
         }).fold(0.0, |s, x| s + x)
     }
 }
 
 /// Each STEPS[l][i] represents the step size Δ<sub>i+3</sub> = Δ<sub>m</sub>, 3 ≤ m ≤ 7,
 /// for the harmonics parameter l = L - 9.
-static STEPS: [[f32; 5]; 48] = [
+pub static STEPS: [[f32; 5]; 48] = [
     [0.003100, 0.004020, 0.003360, 0.002900, 0.002640],
     [0.006200, 0.004020, 0.006720, 0.005800, 0.005280],
     [0.012400, 0.008040, 0.006720, 0.011600, 0.010560],
@@ -108,7 +107,7 @@ static STEPS: [[f32; 5]; 48] = [
 
 /// Each GAIN[b<sub>2</sub>] represents the first gain value G<sub>1</sub> for the index
 /// b<sub>2</sub>.
-const GAIN: [f32; 64] = [
+pub const GAIN: [f32; 64] = [
     -2.842205,
     -2.694235,
     -2.558260,
@@ -0,0 +1,1145 @@
+//! Inner loops of frame synthesis.
+//!
+//! Each kernel has a portable scalar implementation. When the `simd` feature is enabled,
+//! vectorized implementations are also compiled for SSE2 and AVX2 on x86_64 and NEON on
+//! aarch64, and the best one supported by the running CPU is selected at runtime, once per
+//! process. Without the `std` feature, CPU features can't be queried at runtime, so the
+//! selection is made from the target features enabled at compile time instead.
+//!
+//! The vector kernels sum terms in a different order than the scalar ones, so output
+//! depends on the CPU by float rounding, up to
+//! [`MAX_ISA_DEVIATION`](constant.MAX_ISA_DEVIATION.html).
+//!
+//! For decoding many streams in lockstep, the oscillator banks and IDFT points of up to
+//! `BATCH_LANES` frames can also be laid out together, with one frame in each vector
+//! lane. The cross-stream kernels sum the terms of each frame in the same order as the
+//! per-frame kernel for the same instruction set, so their results are identical to
+//! running the per-frame kernel on each frame in turn.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use complex::Complex32;
+use consts::MAX_HARMONICS;
+use math;
+
+/// Check if the given x86_64 CPU feature is available.
+#[cfg(all(feature = "simd", feature = "std", target_arch = "x86_64"))]
+macro_rules! x86_feature {
+    ($f:tt) => { is_x86_feature_detected!($f) }
+}
+
+/// Check if the given x86_64 CPU feature is available.
+#[cfg(all(feature = "simd", not(feature = "std"), target_arch = "x86_64"))]
+macro_rules! x86_feature {
+    ($f:tt) => { cfg!(target_feature = $f) }
+}
+
+/// Check if the given aarch64 CPU feature is available.
+#[cfg(all(feature = "simd", feature = "std", target_arch = "aarch64"))]
+macro_rules! arm_feature {
+    ($f:tt) => { ::std::arch::is_aarch64_feature_detected!($f) }
+}
+
+/// Check if the given aarch64 CPU feature is available.
+#[cfg(all(feature = "simd", not(feature = "std"), target_arch = "aarch64"))]
+macro_rules! arm_feature {
+    ($f:tt) => { cfg!(target_feature = $f) }
+}
+
+/// Maximum number of frames evaluated together by the cross-stream kernels.
+pub const BATCH_LANES: usize = 8;
+
+/// Maximum difference between the results of the kernels for any two instruction sets,
+/// relative to the sum of the magnitudes of the terms: the amplitudes A<sub>k</sub> added
+/// to zeroed samples by `OscBank::accumulate`, and |Re X(m)| + |Im X(m)| for
+/// `phasor_dot`.
+pub const MAX_ISA_DEVIATION: f32 = 1e-5;
+
+/// Instruction sets in order of preference.
+const ISAS: [Isa; 4] = [Isa::Avx2, Isa::Neon, Isa::Sse2, Isa::Scalar];
+
+/// Index into `ISAS` of the detected instruction set plus one, or 0 before detection.
+static DETECTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Instruction set used to run a kernel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Isa {
+    /// Portable scalar code.
+    Scalar,
+    /// 4-wide x86_64 SSE2 vectors.
+    Sse2,
+    /// 8-wide x86_64 AVX2 vectors.
+    Avx2,
+    /// 4-wide aarch64 NEON vectors.
+    Neon,
+}
+
+impl Isa {
+    /// Detect the fastest instruction set supported by both the build and the running
+    /// CPU.
+    ///
+    /// The CPU is only probed on the first call, and the result is reused after.
+    pub fn detect() -> Isa {
+        match DETECTED.load(Ordering::Relaxed) {
+            0 => {
+                // Scalar is always supported, so this always finds one.
+                let idx = ISAS.iter().position(|isa| isa.supported()).unwrap();
+
+                // Racing threads all store the same result.
+                DETECTED.store(idx + 1, Ordering::Relaxed);
+                ISAS[idx]
+            },
+            n => ISAS[n - 1],
+        }
+    }
+
+    /// Check if kernels for the instruction set were compiled in and can run on the
+    /// current CPU.
+    pub fn supported(&self) -> bool {
+        match *self {
+            Isa::Scalar => true,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Sse2 => x86_feature!("sse2"),
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Avx2 => x86_feature!("avx2"),
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            Isa::Neon => arm_feature!("neon"),
+            _ => false,
+        }
+    }
+}
+
+/// Bank of sinusoidal oscillators A<sub>k</sub> cos(ω<sub>k</sub>t + φ<sub>k</sub>)
+/// evaluated together.
+///
+/// Oscillator parameters are held as a structure of arrays, with unused slots zeroed so
+/// vector kernels can run over whole lanes.
+pub struct OscBank {
+    /// Number of oscillators in the bank.
+    len: usize,
+    /// Amplitude A<sub>k</sub> of each oscillator.
+    amp: [f32; MAX_HARMONICS],
+    /// Phase step ω<sub>k</sub> of each oscillator.
+    step: [f32; MAX_HARMONICS],
+    /// Phase offset φ<sub>k</sub> of each oscillator.
+    phase: [f32; MAX_HARMONICS],
+    /// sin ω<sub>k</sub> for each oscillator.
+    step_sin: [f32; MAX_HARMONICS],
+    /// cos ω<sub>k</sub> for each oscillator.
+    step_cos: [f32; MAX_HARMONICS],
+    /// sin(ω<sub>k</sub>t + φ<sub>k</sub>) at the current t for each oscillator.
+    sin: [f32; MAX_HARMONICS],
+    /// cos(ω<sub>k</sub>t + φ<sub>k</sub>) at the current t for each oscillator.
+    cos: [f32; MAX_HARMONICS],
+}
+
+impl Default for OscBank {
+    fn default() -> OscBank { OscBank::new() }
+}
+
+impl OscBank {
+    /// Create a new empty `OscBank`.
+    pub fn new() -> OscBank {
+        OscBank {
+            len: 0,
+            amp: [0.0; MAX_HARMONICS],
+            step: [0.0; MAX_HARMONICS],
+            phase: [0.0; MAX_HARMONICS],
+            step_sin: [0.0; MAX_HARMONICS],
+            step_cos: [0.0; MAX_HARMONICS],
+            sin: [0.0; MAX_HARMONICS],
+            cos: [0.0; MAX_HARMONICS],
+        }
+    }
+
+    /// Retrieve the number of oscillators in the bank.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Check if the bank has no oscillators.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Remove all oscillators from the bank.
+    pub fn clear(&mut self) {
+        // Zeroing the amplitudes silences any stale lanes read by vector kernels.
+        self.amp = [0.0; MAX_HARMONICS];
+        self.len = 0;
+    }
+
+    /// Add an oscillator with the given amplitude A, phase step ω, and phase offset φ,
+    /// positioned at t = 0.
+    pub fn push(&mut self, amp: f32, step: f32, phase: f32) {
+        let k = self.len;
+        assert!(k < MAX_HARMONICS);
+
+        let (step_sin, step_cos) = math::sin_cos(step);
+        let (sin, cos) = math::sin_cos(phase);
+
+        self.amp[k] = amp;
+        self.step[k] = step;
+        self.phase[k] = phase;
+        self.step_sin[k] = step_sin;
+        self.step_cos[k] = step_cos;
+        self.sin[k] = sin;
+        self.cos[k] = cos;
+
+        self.len += 1;
+    }
+
+    /// Position every oscillator at the given t.
+    pub fn seek(&mut self, t: isize) {
+        for k in 0..self.len {
+            let (sin, cos) = math::sin_cos(self.step[k] * t as f32 + self.phase[k]);
+
+            self.sin[k] = sin;
+            self.cos[k] = cos;
+        }
+    }
+
+    /// Add the summed output of the oscillators at t, t + 1, ..., starting from the
+    /// current t, to each of the given samples.
+    pub fn accumulate(&self, out: &mut [f32]) {
+        self.accumulate_with(Isa::detect(), out)
+    }
+
+    /// Add the summed oscillator output to the given samples using the given instruction
+    /// set, which must be supported.
+    fn accumulate_with(&self, isa: Isa, out: &mut [f32]) {
+        debug_assert!(isa.supported());
+
+        match isa {
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Sse2 => unsafe { x86::accumulate_sse2(self, out) },
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Avx2 => unsafe { x86::accumulate_avx2(self, out) },
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            Isa::Neon => unsafe { arm::accumulate_neon(self, out) },
+            _ => accumulate_scalar(self, out),
+        }
+    }
+}
+
+/// Oscillator banks of up to `BATCH_LANES` frames laid out together.
+///
+/// Each parameter is held as rows of oscillators k with one bank per column, so a vector
+/// kernel steps oscillator k of every bank at once. Unused slots are zeroed.
+pub struct OscBatch {
+    /// Number of oscillators in the largest bank.
+    len: usize,
+    /// Number of oscillators in each bank.
+    lens: [usize; BATCH_LANES],
+    /// Amplitude A<sub>k</sub> of each oscillator.
+    amp: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// Phase step ω<sub>k</sub> of each oscillator.
+    step: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// Phase offset φ<sub>k</sub> of each oscillator.
+    phase: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// sin ω<sub>k</sub> for each oscillator.
+    step_sin: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// cos ω<sub>k</sub> for each oscillator.
+    step_cos: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// sin(ω<sub>k</sub>t + φ<sub>k</sub>) at the current t for each oscillator.
+    sin: [[f32; BATCH_LANES]; MAX_HARMONICS],
+    /// cos(ω<sub>k</sub>t + φ<sub>k</sub>) at the current t for each oscillator.
+    cos: [[f32; BATCH_LANES]; MAX_HARMONICS],
+}
+
+impl OscBatch {
+    /// Create a new `OscBatch` from the given banks, one per lane, at their current t.
+    pub fn new(banks: &[&OscBank]) -> OscBatch {
+        assert!(banks.len() <= BATCH_LANES);
+
+        let mut batch = OscBatch {
+            len: 0,
+            lens: [0; BATCH_LANES],
+            amp: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            step: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            phase: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            step_sin: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            step_cos: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            sin: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+            cos: [[0.0; BATCH_LANES]; MAX_HARMONICS],
+        };
+
+        for (s, b) in banks.iter().enumerate() {
+            batch.lens[s] = b.len;
+
+            for k in 0..b.len {
+                batch.amp[k][s] = b.amp[k];
+                batch.step[k][s] = b.step[k];
+                batch.phase[k][s] = b.phase[k];
+                batch.step_sin[k][s] = b.step_sin[k];
+                batch.step_cos[k][s] = b.step_cos[k];
+                batch.sin[k][s] = b.sin[k];
+                batch.cos[k][s] = b.cos[k];
+            }
+        }
+
+        batch.len = batch.lens.iter().cloned().max().unwrap_or(0);
+        batch
+    }
+
+    /// Position every oscillator at the given t, as `OscBank::seek` does.
+    pub fn seek(&mut self, t: isize) {
+        for (s, &len) in self.lens.iter().enumerate() {
+            for k in 0..len {
+                let (sin, cos) = math::sin_cos(self.step[k][s] * t as f32 +
+                                               self.phase[k][s]);
+
+                self.sin[k][s] = sin;
+                self.cos[k][s] = cos;
+            }
+        }
+    }
+
+    /// Add the summed output of each bank at t, t + 1, ..., starting from the current
+    /// t, to the corresponding column of the given rows of samples.
+    ///
+    /// The results are identical to calling `OscBank::accumulate` on each bank in turn.
+    pub fn accumulate(&self, out: &mut [[f32; BATCH_LANES]]) {
+        self.accumulate_with(Isa::detect(), out)
+    }
+
+    /// Add the summed output of each bank using the given instruction set, which must be
+    /// supported.
+    fn accumulate_with(&self, isa: Isa, out: &mut [[f32; BATCH_LANES]]) {
+        debug_assert!(isa.supported());
+
+        // Slots past the end of a bank have zero amplitude, so the blocks the per-frame
+        // kernel skips for that bank add nothing to its samples here.
+        match isa {
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Sse2 => unsafe { x86::accumulate_batch_sse2(self, out) },
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Isa::Avx2 => unsafe { x86::accumulate_batch_avx2(self, out) },
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            Isa::Neon => unsafe { arm::accumulate_batch_neon(self, out) },
+            _ => accumulate_batch_scalar(self, out),
+        }
+    }
+}
+
+/// Compute Re[X(0) + X(1) exp(*j*θ) + ··· + X(N-1) exp(*j*(N-1)θ)] for the given
+/// points X(m) and phase step θ.
+pub fn phasor_dot(points: &[Complex32], step: f32) -> f32 {
+    phasor_dot_with(Isa::detect(), points, step)
+}
+
+/// Compute the phasor sum using the given instruction set, which must be supported.
+fn phasor_dot_with(isa: Isa, points: &[Complex32], step: f32) -> f32 {
+    debug_assert!(isa.supported());
+
+    match isa {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::phasor_dot_sse2(points, step) },
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::phasor_dot_avx2(points, step) },
+        #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+        Isa::Neon => unsafe { arm::phasor_dot_neon(points, step) },
+        _ => phasor_dot_scalar(points, 0, step, 0.0),
+    }
+}
+
+/// Compute the phasor sum of `phasor_dot` for each lane of the given points X(m), held
+/// as rows of real and imaginary parts with one frame per column, and the given phase
+/// step θ shared by every lane.
+///
+/// The results are identical to calling `phasor_dot` on the points of each lane in turn.
+pub fn phasor_dot_batch(re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]], step: f32)
+    -> [f32; BATCH_LANES]
+{
+    phasor_dot_batch_with(Isa::detect(), re, im, step)
+}
+
+/// Compute the phasor sum of each lane using the given instruction set, which must be
+/// supported.
+fn phasor_dot_batch_with(isa: Isa, re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]],
+                         step: f32)
+    -> [f32; BATCH_LANES]
+{
+    debug_assert!(isa.supported());
+    assert_eq!(re.len(), im.len());
+
+    match isa {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::phasor_dot_batch_sse2(re, im, step) },
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::phasor_dot_batch_avx2(re, im, step) },
+        #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+        Isa::Neon => unsafe { arm::phasor_dot_batch_neon(re, im, step) },
+        _ => phasor_dot_batch_scalar(re, im, 0, step, [0.0; BATCH_LANES]),
+    }
+}
+
+/// Accumulate the oscillator bank output one oscillator at a time.
+fn accumulate_scalar(bank: &OscBank, out: &mut [f32]) {
+    for k in 0..bank.len {
+        let (mut sin, mut cos) = (bank.sin[k], bank.cos[k]);
+        let (step_sin, step_cos) = (bank.step_sin[k], bank.step_cos[k]);
+        let amp = bank.amp[k];
+
+        for x in out.iter_mut() {
+            *x += amp * cos;
+
+            let next = sin * step_cos + cos * step_sin;
+            cos = cos * step_cos - sin * step_sin;
+            sin = next;
+        }
+    }
+}
+
+/// Continue the phasor sum over the given points from the given index m, adding to the
+/// given partial sum.
+fn phasor_dot_scalar(points: &[Complex32], m: usize, step: f32, sum: f32) -> f32 {
+    let (mut sin, mut cos) = math::sin_cos(step * m as f32);
+    let (step_sin, step_cos) = math::sin_cos(step);
+
+    points[m..].iter().map(|x| {
+        let term = x.re * cos - x.im * sin;
+
+        let next = sin * step_cos + cos * step_sin;
+        cos = cos * step_cos - sin * step_sin;
+        sin = next;
+
+        term
+    }).fold(sum, |s, x| s + x)
+}
+
+/// Accumulate the output of each bank in the batch one oscillator at a time.
+fn accumulate_batch_scalar(batch: &OscBatch, out: &mut [[f32; BATCH_LANES]]) {
+    for k in 0..batch.len {
+        for s in 0..BATCH_LANES {
+            let (mut sin, mut cos) = (batch.sin[k][s], batch.cos[k][s]);
+            let (step_sin, step_cos) = (batch.step_sin[k][s], batch.step_cos[k][s]);
+            let amp = batch.amp[k][s];
+
+            for x in out.iter_mut() {
+                x[s] += amp * cos;
+
+                let next = sin * step_cos + cos * step_sin;
+                cos = cos * step_cos - sin * step_sin;
+                sin = next;
+            }
+        }
+    }
+}
+
+/// Continue the phasor sum of each lane over the given points from the given index m,
+/// adding to the given partial sums, as `phasor_dot_scalar` does.
+fn phasor_dot_batch_scalar(re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]],
+                           m: usize, step: f32, mut sum: [f32; BATCH_LANES])
+    -> [f32; BATCH_LANES]
+{
+    let (mut sin, mut cos) = math::sin_cos(step * m as f32);
+    let (step_sin, step_cos) = math::sin_cos(step);
+
+    for (re, im) in re[m..].iter().zip(im[m..].iter()) {
+        for s in 0..BATCH_LANES {
+            sum[s] += re[s] * cos - im[s] * sin;
+        }
+
+        let next = sin * step_cos + cos * step_sin;
+        cos = cos * step_cos - sin * step_sin;
+        sin = next;
+    }
+
+    sum
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    use complex::Complex32;
+    use math;
+
+    use super::{BATCH_LANES, OscBank, OscBatch, phasor_dot_batch_scalar, phasor_dot_scalar};
+
+    /// Sum the lanes of the given vector.
+    #[target_feature(enable = "sse2")]
+    unsafe fn hsum128(v: __m128) -> f32 {
+        let v = _mm_add_ps(v, _mm_movehl_ps(v, v));
+        let v = _mm_add_ss(v, _mm_shuffle_ps(v, v, 0b01));
+        _mm_cvtss_f32(v)
+    }
+
+    /// Sum the lanes of the given vector.
+    #[target_feature(enable = "avx2")]
+    unsafe fn hsum256(v: __m256) -> f32 {
+        hsum128(_mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1)))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn accumulate_sse2(bank: &OscBank, out: &mut [f32]) {
+        for k in (0..bank.len).step_by(4) {
+            let amp = _mm_loadu_ps(bank.amp.as_ptr().add(k));
+            let step_sin = _mm_loadu_ps(bank.step_sin.as_ptr().add(k));
+            let step_cos = _mm_loadu_ps(bank.step_cos.as_ptr().add(k));
+            let mut sin = _mm_loadu_ps(bank.sin.as_ptr().add(k));
+            let mut cos = _mm_loadu_ps(bank.cos.as_ptr().add(k));
+
+            for x in out.iter_mut() {
+                *x += hsum128(_mm_mul_ps(amp, cos));
+
+                let next = _mm_add_ps(_mm_mul_ps(sin, step_cos),
+                                      _mm_mul_ps(cos, step_sin));
+                cos = _mm_sub_ps(_mm_mul_ps(cos, step_cos), _mm_mul_ps(sin, step_sin));
+                sin = next;
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_avx2(bank: &OscBank, out: &mut [f32]) {
+        for k in (0..bank.len).step_by(8) {
+            let amp = _mm256_loadu_ps(bank.amp.as_ptr().add(k));
+            let step_sin = _mm256_loadu_ps(bank.step_sin.as_ptr().add(k));
+            let step_cos = _mm256_loadu_ps(bank.step_cos.as_ptr().add(k));
+            let mut sin = _mm256_loadu_ps(bank.sin.as_ptr().add(k));
+            let mut cos = _mm256_loadu_ps(bank.cos.as_ptr().add(k));
+
+            for x in out.iter_mut() {
+                *x += hsum256(_mm256_mul_ps(amp, cos));
+
+                let next = _mm256_add_ps(_mm256_mul_ps(sin, step_cos),
+                                         _mm256_mul_ps(cos, step_sin));
+                cos = _mm256_sub_ps(_mm256_mul_ps(cos, step_cos),
+                                    _mm256_mul_ps(sin, step_sin));
+                sin = next;
+            }
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn phasor_dot_sse2(points: &[Complex32], step: f32) -> f32 {
+        // Lane i handles the points m = 4j + i.
+        let (sin, cos) = lanes(&[0, 1, 2, 3], step);
+        let mut sin = _mm_loadu_ps(sin.as_ptr());
+        let mut cos = _mm_loadu_ps(cos.as_ptr());
+
+        let (step_sin, step_cos) = math::sin_cos(4.0 * step);
+        let step_sin = _mm_set1_ps(step_sin);
+        let step_cos = _mm_set1_ps(step_cos);
+
+        let mut acc = _mm_setzero_ps();
+        let blocks = points.len() / 4;
+        let ptr = points.as_ptr() as *const f32;
+
+        for j in 0..blocks {
+            let a = _mm_loadu_ps(ptr.add(8 * j));
+            let b = _mm_loadu_ps(ptr.add(8 * j + 4));
+            let re = _mm_shuffle_ps(a, b, 0b10_00_10_00);
+            let im = _mm_shuffle_ps(a, b, 0b11_01_11_01);
+
+            acc = _mm_add_ps(acc, _mm_sub_ps(_mm_mul_ps(re, cos), _mm_mul_ps(im, sin)));
+
+            let next = _mm_add_ps(_mm_mul_ps(sin, step_cos), _mm_mul_ps(cos, step_sin));
+            cos = _mm_sub_ps(_mm_mul_ps(cos, step_cos), _mm_mul_ps(sin, step_sin));
+            sin = next;
+        }
+
+        phasor_dot_scalar(points, blocks * 4, step, hsum128(acc))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn phasor_dot_avx2(points: &[Complex32], step: f32) -> f32 {
+        // Deinterleaving works within each 128-bit half, which leaves the points of each
+        // block in this order across the lanes.
+        let (sin, cos) = lanes(&[0, 1, 4, 5, 2, 3, 6, 7], step);
+        let mut sin = _mm256_loadu_ps(sin.as_ptr());
+        let mut cos = _mm256_loadu_ps(cos.as_ptr());
+
+        let (step_sin, step_cos) = math::sin_cos(8.0 * step);
+        let step_sin = _mm256_set1_ps(step_sin);
+        let step_cos = _mm256_set1_ps(step_cos);
+
+        let mut acc = _mm256_setzero_ps();
+        let blocks = points.len() / 8;
+        let ptr = points.as_ptr() as *const f32;
+
+        for j in 0..blocks {
+            let a = _mm256_loadu_ps(ptr.add(16 * j));
+            let b = _mm256_loadu_ps(ptr.add(16 * j + 8));
+            let re = _mm256_shuffle_ps(a, b, 0b10_00_10_00);
+            let im = _mm256_shuffle_ps(a, b, 0b11_01_11_01);
+
+            acc = _mm256_add_ps(acc, _mm256_sub_ps(_mm256_mul_ps(re, cos),
+                                                   _mm256_mul_ps(im, sin)));
+
+            let next = _mm256_add_ps(_mm256_mul_ps(sin, step_cos),
+                                     _mm256_mul_ps(cos, step_sin));
+            cos = _mm256_sub_ps(_mm256_mul_ps(cos, step_cos),
+                                _mm256_mul_ps(sin, step_sin));
+            sin = next;
+        }
+
+        phasor_dot_scalar(points, blocks * 8, step, hsum256(acc))
+    }
+
+    // The cross-stream kernels below hold one frame in each vector lane and keep a
+    // separate vector for each lane of the per-frame kernel, summing them in the same
+    // order as `hsum128` or `hsum256`.
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn accumulate_batch_sse2(batch: &OscBatch, out: &mut [[f32; BATCH_LANES]]) {
+        for g in (0..BATCH_LANES).step_by(4) {
+            for k in (0..batch.len).step_by(4) {
+                let mut amp = [_mm_setzero_ps(); 4];
+                let mut step_sin = [_mm_setzero_ps(); 4];
+                let mut step_cos = [_mm_setzero_ps(); 4];
+                let mut sin = [_mm_setzero_ps(); 4];
+                let mut cos = [_mm_setzero_ps(); 4];
+
+                for i in 0..4 {
+                    amp[i] = _mm_loadu_ps(batch.amp[k + i].as_ptr().add(g));
+                    step_sin[i] = _mm_loadu_ps(batch.step_sin[k + i].as_ptr().add(g));
+                    step_cos[i] = _mm_loadu_ps(batch.step_cos[k + i].as_ptr().add(g));
+                    sin[i] = _mm_loadu_ps(batch.sin[k + i].as_ptr().add(g));
+                    cos[i] = _mm_loadu_ps(batch.cos[k + i].as_ptr().add(g));
+                }
+
+                for x in out.iter_mut() {
+                    let sum = _mm_add_ps(
+                        _mm_add_ps(_mm_mul_ps(amp[0], cos[0]), _mm_mul_ps(amp[2], cos[2])),
+                        _mm_add_ps(_mm_mul_ps(amp[1], cos[1]), _mm_mul_ps(amp[3], cos[3])));
+
+                    let ptr = x.as_mut_ptr().add(g);
+                    _mm_storeu_ps(ptr, _mm_add_ps(_mm_loadu_ps(ptr), sum));
+
+                    for i in 0..4 {
+                        let next = _mm_add_ps(_mm_mul_ps(sin[i], step_cos[i]),
+                                              _mm_mul_ps(cos[i], step_sin[i]));
+                        cos[i] = _mm_sub_ps(_mm_mul_ps(cos[i], step_cos[i]),
+                                            _mm_mul_ps(sin[i], step_sin[i]));
+                        sin[i] = next;
+                    }
+                }
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_batch_avx2(batch: &OscBatch, out: &mut [[f32; BATCH_LANES]]) {
+        for k in (0..batch.len).step_by(8) {
+            let mut amp = [_mm256_setzero_ps(); 8];
+            let mut step_sin = [_mm256_setzero_ps(); 8];
+            let mut step_cos = [_mm256_setzero_ps(); 8];
+            let mut sin = [_mm256_setzero_ps(); 8];
+            let mut cos = [_mm256_setzero_ps(); 8];
+
+            for i in 0..8 {
+                amp[i] = _mm256_loadu_ps(batch.amp[k + i].as_ptr());
+                step_sin[i] = _mm256_loadu_ps(batch.step_sin[k + i].as_ptr());
+                step_cos[i] = _mm256_loadu_ps(batch.step_cos[k + i].as_ptr());
+                sin[i] = _mm256_loadu_ps(batch.sin[k + i].as_ptr());
+                cos[i] = _mm256_loadu_ps(batch.cos[k + i].as_ptr());
+            }
+
+            for x in out.iter_mut() {
+                let mut terms = [_mm256_setzero_ps(); 8];
+
+                for i in 0..8 {
+                    terms[i] = _mm256_mul_ps(amp[i], cos[i]);
+                }
+
+                let ptr = x.as_mut_ptr();
+                _mm256_storeu_ps(ptr, _mm256_add_ps(_mm256_loadu_ps(ptr), reduce256(&terms)));
+
+                for i in 0..8 {
+                    let next = _mm256_add_ps(_mm256_mul_ps(sin[i], step_cos[i]),
+                                             _mm256_mul_ps(cos[i], step_sin[i]));
+                    cos[i] = _mm256_sub_ps(_mm256_mul_ps(cos[i], step_cos[i]),
+                                           _mm256_mul_ps(sin[i], step_sin[i]));
+                    sin[i] = next;
+                }
+            }
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn phasor_dot_batch_sse2(re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]],
+                                        step: f32)
+        -> [f32; BATCH_LANES]
+    {
+        let (sin_start, cos_start) = lanes(&[0, 1, 2, 3], step);
+        let (step_sin, step_cos) = math::sin_cos(4.0 * step);
+        let step_sin = _mm_set1_ps(step_sin);
+        let step_cos = _mm_set1_ps(step_cos);
+
+        let blocks = re.len() / 4;
+        let mut sum = [0.0; BATCH_LANES];
+
+        for g in (0..BATCH_LANES).step_by(4) {
+            let mut sin = [_mm_setzero_ps(); 4];
+            let mut cos = [_mm_setzero_ps(); 4];
+            let mut acc = [_mm_setzero_ps(); 4];
+
+            for i in 0..4 {
+                sin[i] = _mm_set1_ps(sin_start[i]);
+                cos[i] = _mm_set1_ps(cos_start[i]);
+            }
+
+            for j in 0..blocks {
+                for i in 0..4 {
+                    let x_re = _mm_loadu_ps(re[4 * j + i].as_ptr().add(g));
+                    let x_im = _mm_loadu_ps(im[4 * j + i].as_ptr().add(g));
+
+                    acc[i] = _mm_add_ps(acc[i], _mm_sub_ps(_mm_mul_ps(x_re, cos[i]),
+                                                           _mm_mul_ps(x_im, sin[i])));
+
+                    let next = _mm_add_ps(_mm_mul_ps(sin[i], step_cos),
+                                          _mm_mul_ps(cos[i], step_sin));
+                    cos[i] = _mm_sub_ps(_mm_mul_ps(cos[i], step_cos),
+                                        _mm_mul_ps(sin[i], step_sin));
+                    sin[i] = next;
+                }
+            }
+
+            _mm_storeu_ps(sum.as_mut_ptr().add(g),
+                          _mm_add_ps(_mm_add_ps(acc[0], acc[2]), _mm_add_ps(acc[1], acc[3])));
+        }
+
+        phasor_dot_batch_scalar(re, im, blocks * 4, step, sum)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn phasor_dot_batch_avx2(re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]],
+                                        step: f32)
+        -> [f32; BATCH_LANES]
+    {
+        // Lane i of the per-frame kernel handles the points m = 8j + offsets[i].
+        let offsets = [0, 1, 4, 5, 2, 3, 6, 7];
+        let (sin_start, cos_start) = lanes(&offsets, step);
+        let (step_sin, step_cos) = math::sin_cos(8.0 * step);
+        let step_sin = _mm256_set1_ps(step_sin);
+        let step_cos = _mm256_set1_ps(step_cos);
+
+        let mut sin = [_mm256_setzero_ps(); 8];
+        let mut cos = [_mm256_setzero_ps(); 8];
+        let mut acc = [_mm256_setzero_ps(); 8];
+
+        for i in 0..8 {
+            sin[i] = _mm256_set1_ps(sin_start[i]);
+            cos[i] = _mm256_set1_ps(cos_start[i]);
+        }
+
+        let blocks = re.len() / 8;
+
+        for j in 0..blocks {
+            for i in 0..8 {
+                let x_re = _mm256_loadu_ps(re[8 * j + offsets[i]].as_ptr());
+                let x_im = _mm256_loadu_ps(im[8 * j + offsets[i]].as_ptr());
+
+                acc[i] = _mm256_add_ps(acc[i], _mm256_sub_ps(_mm256_mul_ps(x_re, cos[i]),
+                                                             _mm256_mul_ps(x_im, sin[i])));
+
+                let next = _mm256_add_ps(_mm256_mul_ps(sin[i], step_cos),
+                                         _mm256_mul_ps(cos[i], step_sin));
+                cos[i] = _mm256_sub_ps(_mm256_mul_ps(cos[i], step_cos),
+                                       _mm256_mul_ps(sin[i], step_sin));
+                sin[i] = next;
+            }
+        }
+
+        let mut sum = [0.0; BATCH_LANES];
+        _mm256_storeu_ps(sum.as_mut_ptr(), reduce256(&acc));
+
+        phasor_dot_batch_scalar(re, im, blocks * 8, step, sum)
+    }
+
+    /// Sum the given vectors in the order `hsum256` sums the lanes of a vector.
+    #[target_feature(enable = "avx2")]
+    unsafe fn reduce256(x: &[__m256; 8]) -> __m256 {
+        _mm256_add_ps(_mm256_add_ps(_mm256_add_ps(x[0], x[4]), _mm256_add_ps(x[2], x[6])),
+                      _mm256_add_ps(_mm256_add_ps(x[1], x[5]), _mm256_add_ps(x[3], x[7])))
+    }
+
+    /// Compute the starting (sin mθ, cos mθ) of lanes handling the given offsets m.
+    fn lanes(offsets: &[usize], step: f32) -> ([f32; 8], [f32; 8]) {
+        let mut sin = [0.0; 8];
+        let mut cos = [0.0; 8];
+
+        for (i, &m) in offsets.iter().enumerate() {
+            let (s, c) = math::sin_cos(step * m as f32);
+            sin[i] = s;
+            cos[i] = c;
+        }
+
+        (sin, cos)
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod arm {
+    use core::arch::aarch64::*;
+
+    use complex::Complex32;
+    use math;
+
+    use super::{BATCH_LANES, OscBank, OscBatch, phasor_dot_batch_scalar, phasor_dot_scalar};
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn accumulate_neon(bank: &OscBank, out: &mut [f32]) {
+        for k in (0..bank.len).step_by(4) {
+            let amp = vld1q_f32(bank.amp.as_ptr().add(k));
+            let step_sin = vld1q_f32(bank.step_sin.as_ptr().add(k));
+            let step_cos = vld1q_f32(bank.step_cos.as_ptr().add(k));
+            let mut sin = vld1q_f32(bank.sin.as_ptr().add(k));
+            let mut cos = vld1q_f32(bank.cos.as_ptr().add(k));
+
+            for x in out.iter_mut() {
+                *x += vaddvq_f32(vmulq_f32(amp, cos));
+
+                let next = vaddq_f32(vmulq_f32(sin, step_cos), vmulq_f32(cos, step_sin));
+                cos = vsubq_f32(vmulq_f32(cos, step_cos), vmulq_f32(sin, step_sin));
+                sin = next;
+            }
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn phasor_dot_neon(points: &[Complex32], step: f32) -> f32 {
+        // Lane i handles the points m = 4j + i.
+        let mut sin = [0.0; 4];
+        let mut cos = [0.0; 4];
+
+        for i in 0..4 {
+            let (s, c) = math::sin_cos(step * i as f32);
+            sin[i] = s;
+            cos[i] = c;
+        }
+
+        let mut sin = vld1q_f32(sin.as_ptr());
+        let mut cos = vld1q_f32(cos.as_ptr());
+
+        let (step_sin, step_cos) = math::sin_cos(4.0 * step);
+        let step_sin = vdupq_n_f32(step_sin);
+        let step_cos = vdupq_n_f32(step_cos);
+
+        let mut acc = vdupq_n_f32(0.0);
+        let blocks = points.len() / 4;
+        let ptr = points.as_ptr() as *const f32;
+
+        for j in 0..blocks {
+            let x = vld2q_f32(ptr.add(8 * j));
+
+            acc = vaddq_f32(acc, vsubq_f32(vmulq_f32(x.0, cos), vmulq_f32(x.1, sin)));
+
+            let next = vaddq_f32(vmulq_f32(sin, step_cos), vmulq_f32(cos, step_sin));
+            cos = vsubq_f32(vmulq_f32(cos, step_cos), vmulq_f32(sin, step_sin));
+            sin = next;
+        }
+
+        phasor_dot_scalar(points, blocks * 4, step, vaddvq_f32(acc))
+    }
+
+    // The cross-stream kernels below hold one frame in each vector lane and keep a
+    // separate vector for each lane of the per-frame kernel, summing them in the same
+    // order as `vaddvq_f32`.
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn accumulate_batch_neon(batch: &OscBatch, out: &mut [[f32; BATCH_LANES]]) {
+        for g in (0..BATCH_LANES).step_by(4) {
+            for k in (0..batch.len).step_by(4) {
+                let mut amp = [vdupq_n_f32(0.0); 4];
+                let mut step_sin = [vdupq_n_f32(0.0); 4];
+                let mut step_cos = [vdupq_n_f32(0.0); 4];
+                let mut sin = [vdupq_n_f32(0.0); 4];
+                let mut cos = [vdupq_n_f32(0.0); 4];
+
+                for i in 0..4 {
+                    amp[i] = vld1q_f32(batch.amp[k + i].as_ptr().add(g));
+                    step_sin[i] = vld1q_f32(batch.step_sin[k + i].as_ptr().add(g));
+                    step_cos[i] = vld1q_f32(batch.step_cos[k + i].as_ptr().add(g));
+                    sin[i] = vld1q_f32(batch.sin[k + i].as_ptr().add(g));
+                    cos[i] = vld1q_f32(batch.cos[k + i].as_ptr().add(g));
+                }
+
+                for x in out.iter_mut() {
+                    let sum = vaddq_f32(
+                        vaddq_f32(vmulq_f32(amp[0], cos[0]), vmulq_f32(amp[1], cos[1])),
+                        vaddq_f32(vmulq_f32(amp[2], cos[2]), vmulq_f32(amp[3], cos[3])));
+
+                    let ptr = x.as_mut_ptr().add(g);
+                    vst1q_f32(ptr, vaddq_f32(vld1q_f32(ptr), sum));
+
+                    for i in 0..4 {
+                        let next = vaddq_f32(vmulq_f32(sin[i], step_cos[i]),
+                                             vmulq_f32(cos[i], step_sin[i]));
+                        cos[i] = vsubq_f32(vmulq_f32(cos[i], step_cos[i]),
+                                           vmulq_f32(sin[i], step_sin[i]));
+                        sin[i] = next;
+                    }
+                }
+            }
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn phasor_dot_batch_neon(re: &[[f32; BATCH_LANES]], im: &[[f32; BATCH_LANES]],
+                                        step: f32)
+        -> [f32; BATCH_LANES]
+    {
+        let (step_sin, step_cos) = math::sin_cos(4.0 * step);
+        let step_sin = vdupq_n_f32(step_sin);
+        let step_cos = vdupq_n_f32(step_cos);
+
+        let blocks = re.len() / 4;
+        let mut sum = [0.0; BATCH_LANES];
+
+        for g in (0..BATCH_LANES).step_by(4) {
+            let mut sin = [vdupq_n_f32(0.0); 4];
+            let mut cos = [vdupq_n_f32(0.0); 4];
+            let mut acc = [vdupq_n_f32(0.0); 4];
+
+            for i in 0..4 {
+                let (s, c) = math::sin_cos(step * i as f32);
+                sin[i] = vdupq_n_f32(s);
+                cos[i] = vdupq_n_f32(c);
+            }
+
+            for j in 0..blocks {
+                for i in 0..4 {
+                    let x_re = vld1q_f32(re[4 * j + i].as_ptr().add(g));
+                    let x_im = vld1q_f32(im[4 * j + i].as_ptr().add(g));
+
+                    acc[i] = vaddq_f32(acc[i], vsubq_f32(vmulq_f32(x_re, cos[i]),
+                                                         vmulq_f32(x_im, sin[i])));
+
+                    let next = vaddq_f32(vmulq_f32(sin[i], step_cos),
+                                         vmulq_f32(cos[i], step_sin));
+                    cos[i] = vsubq_f32(vmulq_f32(cos[i], step_cos),
+                                       vmulq_f32(sin[i], step_sin));
+                    sin[i] = next;
+                }
+            }
+
+            vst1q_f32(sum.as_mut_ptr().add(g),
+                      vaddq_f32(vaddq_f32(acc[0], acc[1]), vaddq_f32(acc[2], acc[3])));
+        }
+
+        phasor_dot_batch_scalar(re, im, blocks * 4, step, sum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use complex::Complex32;
+
+    /// Retrieve the instruction sets that can be tested on this host.
+    ///
+    /// The baseline vector set of the target is always among them when the `simd` feature
+    /// is enabled, while others, such as AVX2 on older x86_64 CPUs, can't be run and are
+    /// left out.
+    fn supported() -> Vec<Isa> {
+        if cfg!(all(feature = "simd", target_arch = "x86_64")) {
+            assert!(Isa::Sse2.supported());
+        }
+
+        if cfg!(all(feature = "simd", target_arch = "aarch64")) {
+            assert!(Isa::Neon.supported());
+        }
+
+        ISAS.iter().cloned().filter(|isa| isa.supported()).collect()
+    }
+
+    fn bank(len: usize) -> OscBank {
+        let mut bank = OscBank::new();
+
+        for k in 0..len {
+            bank.push(10.0 + k as f32, 0.17 * (k + 1) as f32, 0.9 * k as f32);
+        }
+
+        bank
+    }
+
+    #[test]
+    fn test_detect() {
+        assert!(Isa::Scalar.supported());
+        assert!(Isa::detect().supported());
+        assert_eq!(Isa::detect(), ISAS[DETECTED.load(Ordering::Relaxed) - 1]);
+        assert_eq!(Isa::detect(), supported()[0]);
+
+        if cfg!(not(feature = "simd")) {
+            assert_eq!(Isa::detect(), Isa::Scalar);
+        }
+    }
+
+    #[test]
+    fn test_bank() {
+        for &len in &[0, 1, 5, 16, 56] {
+            let mut b = bank(len);
+            b.seek(-37);
+            assert_eq!(b.len(), len);
+
+            let mut expected = [0.0; 40];
+
+            for (i, x) in expected.iter_mut().enumerate() {
+                let t = i as f32 - 37.0;
+
+                *x = 1.0 + (0..len).map(|k| {
+                    (10.0 + k as f32) * (0.17 * (k + 1) as f32 * t + 0.9 * k as f32).cos()
+                }).fold(0.0, |s, x| s + x);
+            }
+
+            for isa in supported() {
+                let mut out = [1.0; 40];
+                b.accumulate_with(isa, &mut out[..]);
+
+                for (x, y) in out.iter().zip(expected.iter()) {
+                    assert!((x - y).abs() < 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        let lens = [0, 1, 5, 16, 56, 9, 30, 3];
+
+        for isa in supported() {
+            for count in 0..=lens.len() {
+                let mut banks = lens[..count].iter().map(|&len| bank(len))
+                    .collect::<Vec<_>>();
+
+                for (i, b) in banks.iter_mut().enumerate() {
+                    b.seek(i as isize * 13 - 40);
+                }
+
+                let refs = banks.iter().collect::<Vec<_>>();
+                let mut batch = OscBatch::new(&refs[..]);
+                let mut out = [[1.0; BATCH_LANES]; 40];
+                batch.accumulate_with(isa, &mut out[..]);
+
+                for (s, b) in banks.iter().enumerate() {
+                    let mut expected = [1.0; 40];
+                    b.accumulate_with(isa, &mut expected[..]);
+
+                    for (x, y) in out.iter().zip(expected.iter()) {
+                        assert_eq!(x[s], *y);
+                    }
+                }
+
+                for x in out.iter() {
+                    assert!(x[count..].iter().all(|&x| x == 1.0));
+                }
+
+                for b in banks.iter_mut() {
+                    b.seek(25);
+                }
+
+                batch.seek(25);
+
+                let mut out = [[0.0; BATCH_LANES]; 40];
+                batch.accumulate_with(isa, &mut out[..]);
+
+                for (s, b) in banks.iter().enumerate() {
+                    let mut expected = [0.0; 40];
+                    b.accumulate_with(isa, &mut expected[..]);
+
+                    for (x, y) in out.iter().zip(expected.iter()) {
+                        assert_eq!(x[s], *y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_phasor_batch() {
+        for &len in &[0, 3, 8, 13, 128] {
+            let points = (0..BATCH_LANES).map(|s| {
+                (0..len).map(|m| {
+                    Complex32::new(((m + s) * 5 % 7) as f32 - 3.0,
+                                   ((m * s + 1) * 3 % 11) as f32 - 5.0)
+                }).collect::<Vec<_>>()
+            }).collect::<Vec<_>>();
+
+            let mut re = vec![[0.0; BATCH_LANES]; len];
+            let mut im = vec![[0.0; BATCH_LANES]; len];
+
+            for (s, p) in points.iter().enumerate() {
+                for (m, x) in p.iter().enumerate() {
+                    re[m][s] = x.re;
+                    im[m][s] = x.im;
+                }
+            }
+
+            for &step in &[0.0, 0.3, -2.1, 3.0] {
+                for isa in supported() {
+                    let sum = phasor_dot_batch_with(isa, &re[..], &im[..], step);
+
+                    for (x, p) in sum.iter().zip(points.iter()) {
+                        assert_eq!(*x, phasor_dot_with(isa, &p[..], step));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_phasor_dot() {
+        for &len in &[0, 3, 8, 13, 128] {
+            let points = (0..len).map(|m| {
+                Complex32::new((m * 5 % 7) as f32 - 3.0, (m * 3 % 11) as f32 - 5.0)
+            }).collect::<Vec<_>>();
+
+            for &step in &[0.0, 0.3, -2.1, 3.0] {
+                let expected = points.iter().enumerate().map(|(m, x)| {
+                    let (sin, cos) = (step * m as f32).sin_cos();
+                    x.re * cos - x.im * sin
+                }).fold(0.0, |s, x| s + x);
+
+                for isa in supported() {
+                    let x = phasor_dot_with(isa, &points[..], step);
+                    assert!((x - expected).abs() < 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_deviation() {
+        for &len in &[1, 7, 24, 56] {
+            let mut b = OscBank::new();
+
+            for k in 0..len {
+                b.push((k * 37 % 101) as f32 * 10.0, 0.05 * (k * 7 % 61) as f32,
+                       0.3 * (k * 11 % 19) as f32);
+            }
+
+            b.seek(len as isize - 30);
+
+            let total = (0..len).map(|k| b.amp[k]).fold(0.0, |s, x| s + x);
+            let mut expected = [0.0; 160];
+            b.accumulate_with(Isa::Scalar, &mut expected[..]);
+
+            for isa in supported() {
+                let mut out = [0.0; 160];
+                b.accumulate_with(isa, &mut out[..]);
+
+                for (x, y) in out.iter().zip(expected.iter()) {
+                    assert!((x - y).abs() <= MAX_ISA_DEVIATION * total);
+                }
+            }
+        }
+
+        for &len in &[5, 64, 128] {
+            let points = (0..len).map(|m| {
+                Complex32::new((m * 37 % 101) as f32 - 50.0, (m * 13 % 89) as f32 - 44.0)
+            }).collect::<Vec<_>>();
+
+            let total = points.iter().fold(0.0, |s, x| s + x.re.abs() + x.im.abs());
+
+            for n in 0..256 {
+                let step = 2.0 * ::core::f32::consts::PI * n as f32 / 256.0;
+                let expected = phasor_dot_with(Isa::Scalar, &points[..], step);
+
+                for isa in supported() {
+                    let x = phasor_dot_with(isa, &points[..], step);
+                    assert!((x - expected).abs() <= MAX_ISA_DEVIATION * total);
+                }
+            }
+        }
+    }
+}
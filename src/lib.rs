@@ -1,30 +1,70 @@
 //! Decode the Improved Multi-Band Excitation (IMBE) digital voice codec.
+//!
+//! The decoding pipeline allocates nothing and builds under `no_std` when the default
+//! `std` feature is disabled. That feature adds threaded synthesis, decoders seeded from
+//! the OS, and the modules that adapt decoding to `std::io` streams.
 
 #![feature(inclusive_range_syntax)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(any(feature = "std", test))]
+extern crate core;
 
 extern crate arrayvec;
-extern crate collect_slice;
+#[cfg(feature = "std")]
 extern crate crossbeam;
-extern crate slice_mip;
-extern crate num;
-extern crate iq_osc;
 extern crate rand;
 
 pub mod allocs;
 pub mod coefs;
+pub mod comfort;
+pub mod complex;
 pub mod consts;
 pub mod decode;
 pub mod descramble;
 pub mod enhance;
+pub mod fft;
+pub mod fixed;
 pub mod frame;
 pub mod gain;
+pub mod kernel;
+#[cfg(feature = "std")]
+pub mod manager;
+pub mod math;
+pub mod normal;
 pub mod params;
+pub mod pcm;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod pool;
 pub mod prev;
+pub mod quality;
+#[cfg(feature = "std")]
+pub mod reader;
+#[cfg(feature = "std")]
+pub mod resample;
 pub mod scan;
+#[cfg(feature = "std")]
+pub mod sink;
+pub mod snapshot;
 pub mod spectral;
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod stream;
 pub mod unvoiced;
 pub mod voiced;
 pub mod window;
 
-pub use decode::ImbeDecoder;
+pub use decode::{DecodeStatus, ImbeDecoder, Threading};
+pub use fixed::FixedDecoder;
 pub use frame::ReceivedFrame;
+#[cfg(feature = "std")]
+pub use manager::DecodeManager;
+pub use policy::ErrorPolicy;
+pub use quality::Quality;
+#[cfg(feature = "std")]
+pub use reader::PcmReader;
+#[cfg(feature = "std")]
+pub use sink::Sink;
+#[cfg(feature = "std")]
+pub use stream::StreamDecoder;
@@ -0,0 +1,462 @@
+//! Decoding of many concurrent streams over a shared worker pool.
+//!
+//! Systems such as trunking recorders follow many calls at once, each of which is an
+//! independent stream of frames with its own decoder state. A `DecodeManager` owns those
+//! states, keyed by a caller-chosen stream ID, and decodes batches of tagged frames as
+//! one job per stream on a shared `WorkerPool`, so the work for all streams is scheduled
+//! over a single fixed set of threads.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, XorShiftRng};
+
+use consts::SAMPLES_PER_FRAME;
+use decode::{ImbeDecoder, Threading};
+use frame::{AudioBuf, FrameError, ReceivedFrame};
+use policy::{ErrorPolicy, Standard};
+use pool::WorkerPool;
+
+/// Error decoding a frame of a stream.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StreamError {
+    /// The frame can't be decoded, as in `ImbeDecoder::try_decode`.
+    Frame(FrameError),
+    /// Decoding panicked, so the stream's state was discarded.
+    Panicked,
+}
+
+impl From<FrameError> for StreamError {
+    fn from(e: FrameError) -> StreamError { StreamError::Frame(e) }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamError::Frame(e) => write!(f, "{}", e),
+            StreamError::Panicked => write!(f, "stream decoder panicked"),
+        }
+    }
+}
+
+impl error::Error for StreamError {}
+
+/// Creates the decoder for a new stream with the given ID.
+type Factory<K, R, P> = Box<dyn FnMut(&K) -> ImbeDecoder<R, P> + Send>;
+
+/// Decoder state for a single stream.
+struct Stream<R: Rng, P: ErrorPolicy> {
+    /// Frame decoder, which synthesizes on the worker thread it runs on.
+    decoder: ImbeDecoder<R, P>,
+    /// Time of the last batch that included a frame for this stream.
+    active: Instant,
+}
+
+/// Decodes frames from many streams, identified by IDs of type `K`, over a shared pool.
+///
+/// A stream's state is created when its first frame arrives and is evicted once no frame
+/// has arrived for it within the configured timeout, so a later frame with the same ID
+/// starts again from the initial decoder state.
+///
+/// Each stream's decoder draws random terms from a generator of type `R` and conceals
+/// errors with the policy `P`, as in `ImbeDecoder`.
+pub struct DecodeManager<K: Eq + Hash, R: Rng = XorShiftRng, P: ErrorPolicy = Standard> {
+    /// Decoder state of each active stream.
+    streams: HashMap<K, Stream<R, P>>,
+    /// Creates the decoder of each new stream.
+    factory: Factory<K, R, P>,
+    /// Threads that run decode jobs.
+    pool: WorkerPool,
+    /// Time a stream can go without frames before its state is evicted.
+    timeout: Duration,
+}
+
+impl<K: Eq + Hash + Clone + Send + 'static> DecodeManager<K> {
+    /// Create a new `DecodeManager` with no streams that decodes on the given pool and
+    /// evicts streams that are idle for the given timeout.
+    ///
+    /// Each stream's decoder draws random terms from a generator seeded by the OS.
+    pub fn new(pool: WorkerPool, timeout: Duration) -> Self {
+        Self::with_factory(pool, timeout, |_| {
+            ImbeDecoder::with_threading(Threading::Single)
+        })
+    }
+}
+
+impl<K, R, P> DecodeManager<K, R, P>
+    where K: Eq + Hash + Clone + Send + 'static,
+          R: Rng + Send + 'static,
+          P: ErrorPolicy + Send + 'static
+{
+    /// Create a new `DecodeManager` as in `new`, creating the decoder of each new stream
+    /// by calling the given function with the stream's ID.
+    ///
+    /// Decoders already run on a pool thread, so they should use `Threading::Single`.
+    /// Seeding each decoder's generator from the stream ID makes decoding deterministic.
+    pub fn with_factory<F>(pool: WorkerPool, timeout: Duration, factory: F) -> Self
+        where F: FnMut(&K) -> ImbeDecoder<R, P> + Send + 'static
+    {
+        DecodeManager {
+            streams: HashMap::new(),
+            factory: Box::new(factory),
+            pool: pool,
+            timeout: timeout,
+        }
+    }
+
+    /// Retrieve the number of streams with saved decoder state.
+    pub fn streams(&self) -> usize { self.streams.len() }
+
+    /// Check if the stream with the given ID has saved decoder state.
+    pub fn contains(&self, id: &K) -> bool { self.streams.contains_key(id) }
+
+    /// Discard the saved state of the stream with the given ID, returning whether it
+    /// existed.
+    pub fn remove(&mut self, id: &K) -> bool { self.streams.remove(id).is_some() }
+
+    /// Discard the saved state of every stream that has been idle for the timeout.
+    ///
+    /// This is also done at the start of each call to `decode`.
+    pub fn evict_idle(&mut self) {
+        self.evict(Instant::now());
+    }
+
+    /// Decode the given frames, each tagged with the ID of its stream, and return the
+    /// decoded audio, or the error for frames that can't be decoded, in the same order.
+    ///
+    /// Frames of the same stream are decoded in the order given, and frames of different
+    /// streams are decoded in parallel on the pool. Each frame is decoded as in
+    /// `ImbeDecoder::try_decode`, so a frame that fails leaves its stream's state
+    /// unchanged for the next frame.
+    ///
+    /// If decoding a frame panics, that frame and the rest of its stream's frames in the
+    /// batch fail with `StreamError::Panicked`, and the stream's state is discarded, so
+    /// its next frame starts from a new decoder. Other streams are unaffected.
+    pub fn decode<I>(&mut self, frames: I) -> Vec<(K, Result<AudioBuf, StreamError>)>
+        where I: IntoIterator<Item = (K, ReceivedFrame)>
+    {
+        self.decode_at(frames, Instant::now())
+    }
+
+    /// Decode the given frames as in `decode`, with the given time as the current time.
+    fn decode_at<I>(&mut self, frames: I, now: Instant)
+        -> Vec<(K, Result<AudioBuf, StreamError>)>
+        where I: IntoIterator<Item = (K, ReceivedFrame)>
+    {
+        self.evict(now);
+
+        // Group frames by stream, remembering the position of each in the batch.
+        let mut index = HashMap::new();
+        let mut groups: Vec<(K, Vec<(usize, ReceivedFrame)>)> = vec![];
+        let mut out = vec![];
+
+        for (id, frame) in frames {
+            out.push((id.clone(), None));
+
+            let g = *index.entry(id.clone()).or_insert_with(|| {
+                groups.push((id, vec![]));
+                groups.len() - 1
+            });
+
+            groups[g].1.push((out.len() - 1, frame));
+        }
+
+        let (tx, rx) = mpsc::channel();
+
+        for (id, frames) in groups {
+            // The job takes ownership of the stream state and sends it back when done.
+            let mut stream = match self.streams.remove(&id) {
+                Some(s) => s,
+                None => Stream {
+                    decoder: (self.factory)(&id),
+                    active: now,
+                },
+            };
+            let tx = tx.clone();
+
+            self.pool.execute(move || {
+                let mut failed = false;
+
+                let out = frames.into_iter().map(|(i, frame)| {
+                    if failed {
+                        return (i, Err(StreamError::Panicked));
+                    }
+
+                    let mut buf = [0.0; SAMPLES_PER_FRAME];
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        stream.decoder.try_decode(frame, &mut buf)
+                    }));
+
+                    match result {
+                        Ok(r) => (i, r.map(|_| buf).map_err(StreamError::from)),
+                        Err(_) => {
+                            failed = true;
+                            (i, Err(StreamError::Panicked))
+                        },
+                    }
+                }).collect::<Vec<_>>();
+
+                // A decoder that panicked may be left inconsistent, so it isn't kept.
+                tx.send((id, if failed { None } else { Some(stream) }, out)).ok();
+            });
+        }
+
+        drop(tx);
+
+        for (id, stream, results) in rx.iter() {
+            for (i, result) in results {
+                out[i].1 = Some(result);
+            }
+
+            if let Some(mut stream) = stream {
+                stream.active = now;
+                self.streams.insert(id, stream);
+            }
+        }
+
+        // A job that failed outside of decoding never sent its results, and took its
+        // stream's state with it.
+        out.into_iter().map(|(id, r)| (id, r.unwrap_or(Err(StreamError::Panicked))))
+            .collect()
+    }
+
+    /// Discard the state of streams that are idle for the timeout as of the given time.
+    fn evict(&mut self, now: Instant) {
+        let timeout = self.timeout;
+        self.streams.retain(|_, s| now.duration_since(s.active) < timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use consts::SAMPLES_PER_FRAME;
+    use decode::{ImbeDecoder, Threading};
+    use frame::ReceivedFrame;
+    use policy::Strict;
+    use pool::WorkerPool;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    /// Generator that panics when drawn from if `fail` is set.
+    struct Flaky {
+        fail: bool,
+        rng: XorShiftRng,
+    }
+
+    impl Rng for Flaky {
+        fn next_u32(&mut self) -> u32 {
+            assert!(!self.fail, "generator failed");
+            self.rng.next_u32()
+        }
+    }
+
+    /// Create a fully-voiced frame, which involves no random terms, with the given first
+    /// chunk.
+    fn voiced(u0: u32) -> ReceivedFrame {
+        ReceivedFrame::new([
+            u0,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b11111111111,
+            0b11111111111,
+            0b01110111011,
+            0b00001000,
+        ], [0; 7])
+    }
+
+    /// Create a frame with unvoiced bands.
+    fn mixed() -> ReceivedFrame {
+        ReceivedFrame::new([
+            0b001000010010,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b10100110101,
+            0b00101111010,
+            0b01110111011,
+            0b00001000,
+        ], [0; 7])
+    }
+
+    /// Decode the given frames with a single standalone decoder.
+    fn standalone(frames: &[u32]) -> Vec<AudioBuf> {
+        let mut dec = ImbeDecoder::with_threading(Threading::Single);
+
+        frames.iter().map(|&u0| {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            dec.decode(voiced(u0), &mut buf);
+            buf
+        }).collect()
+    }
+
+    const A: [u32; 3] = [0b001000010010, 0b010000110010, 0b001100010110];
+    const B: [u32; 3] = [0b010000110010, 0b001100010110, 0b001000010010];
+
+    #[test]
+    fn test_interleaved() {
+        let mut m = DecodeManager::new(WorkerPool::new(2), Duration::from_secs(10));
+
+        // Two frames of the first stream share a batch with the second stream.
+        let out = m.decode(vec![
+            (1, voiced(A[0])),
+            (2, voiced(B[0])),
+            (1, voiced(A[1])),
+        ]);
+
+        assert_eq!(out.iter().map(|x| x.0).collect::<Vec<_>>(), vec![1, 2, 1]);
+        assert!(out.iter().all(|x| x.1.is_ok()));
+        assert_eq!(m.streams(), 2);
+
+        let out = out.into_iter().chain(m.decode(vec![
+            (2, voiced(B[1])),
+            (2, voiced(B[2])),
+            (1, voiced(A[2])),
+        ])).collect::<Vec<_>>();
+
+        let pick = |id| out.iter().filter(|x| x.0 == id)
+            .map(|x| &x.1.as_ref().unwrap()[..])
+            .collect::<Vec<_>>();
+
+        let a = standalone(&A);
+        let b = standalone(&B);
+
+        assert_eq!(pick(1), a.iter().map(|x| &x[..]).collect::<Vec<_>>());
+        assert_eq!(pick(2), b.iter().map(|x| &x[..]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_factory() {
+        let run = || {
+            let factory = |&id: &u32| {
+                let seeded = XorShiftRng::from_seed([id, 2, 3, 4]);
+                ImbeDecoder::with_rng_threading(seeded, Threading::Single)
+                    .with_policy(Strict)
+            };
+
+            let mut m = DecodeManager::with_factory(WorkerPool::new(2),
+                                                    Duration::from_secs(10), factory);
+
+            // Frames with unvoiced bands draw random terms.
+            m.decode(vec![(1, mixed()), (2, mixed()), (1, mixed()), (2, voiced(B[0]))])
+                .into_iter().map(|x| x.1.unwrap().to_vec()).collect::<Vec<_>>()
+        };
+
+        let a = run();
+
+        // Streams seeded alike produce the same audio, and others differ.
+        assert_eq!(a, run());
+        assert!(a[0] != a[1]);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut m = DecodeManager::new(WorkerPool::new(2), Duration::from_secs(5));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        m.decode_at(vec![("a", voiced(A[0])), ("b", voiced(B[0]))], at(0));
+        m.decode_at(vec![("a", voiced(A[1]))], at(4));
+        assert_eq!(m.streams(), 2);
+
+        // The second stream has now been idle for the timeout.
+        let out = m.decode_at(vec![("a", voiced(A[2])), ("b", voiced(B[1]))], at(5));
+        assert!(m.contains(&"a"));
+        assert!(m.contains(&"b"));
+
+        // The first stream continued, while the second restarted from the initial state.
+        assert_eq!(&out[0].1.unwrap()[..], &standalone(&A)[2][..]);
+        assert_eq!(&out[1].1.unwrap()[..], &standalone(&B[1..2])[0][..]);
+
+        m.decode_at(vec![("b", voiced(B[2]))], at(10));
+        assert!(!m.contains(&"a"));
+        assert_eq!(m.streams(), 1);
+
+        assert!(m.remove(&"b"));
+        assert!(!m.remove(&"b"));
+        assert_eq!(m.streams(), 0);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut m = DecodeManager::new(WorkerPool::new(1), Duration::from_secs(10));
+
+        let mut bad = voiced(A[1]);
+        bad.errors = [4, 0, 0, 0, 0, 0, 0];
+
+        let out = m.decode(vec![
+            (1, voiced(A[0])),
+            (1, bad),
+            (2, voiced(B[0])),
+            (1, voiced(A[1])),
+        ]);
+
+        assert_eq!(out[1].1, Err(StreamError::Frame(FrameError::InvalidErrors(0))));
+        assert_eq!(m.streams(), 2);
+
+        // The failed frame leaves the stream's state in place, and the pool still works.
+        let out = out.into_iter().chain(m.decode(vec![(1, voiced(A[2]))]))
+            .filter(|x| x.0 == 1 && x.1.is_ok())
+            .map(|x| x.1.unwrap())
+            .collect::<Vec<_>>();
+
+        let a = standalone(&A);
+
+        assert_eq!(out.len(), 3);
+
+        for (x, y) in out.iter().zip(a.iter()) {
+            assert_eq!(&x[..], &y[..]);
+        }
+    }
+    #[test]
+    fn test_panic() {
+        let mut failed = false;
+
+        // Only the first decoder of the second stream fails.
+        let factory = move |&id: &u32| {
+            let flaky = Flaky {
+                fail: id == 2 && !failed,
+                rng: XorShiftRng::new_unseeded(),
+            };
+
+            failed |= id == 2;
+
+            ImbeDecoder::with_rng_threading(flaky, Threading::Single)
+        };
+
+        let mut m = DecodeManager::with_factory(WorkerPool::new(1), Duration::from_secs(10),
+                                                factory);
+
+        let out = m.decode(vec![
+            (1, voiced(A[0])),
+            (2, voiced(B[0])),
+            (2, voiced(B[1])),
+            (1, voiced(A[1])),
+        ]);
+
+        assert_eq!(out[1].1, Err(StreamError::Panicked));
+        assert_eq!(out[2].1, Err(StreamError::Panicked));
+
+        // Only the failed stream is discarded, and the pool's only worker carries on.
+        assert!(m.contains(&1));
+        assert!(!m.contains(&2));
+
+        let out = out.into_iter().chain(m.decode(vec![(1, voiced(A[2])), (2, voiced(B[0]))]))
+            .collect::<Vec<_>>();
+
+        let a = standalone(&A);
+
+        for (x, y) in out.iter().filter(|x| x.0 == 1).zip(a.iter()) {
+            assert_eq!(&x.1.unwrap()[..], &y[..]);
+        }
+
+        // The failed stream restarts from a new decoder.
+        assert_eq!(&out[5].1.unwrap()[..], &standalone(&B)[0][..]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Elementary functions of `f32` that don't depend on `std`.
+//!
+//! The functions follow the usual libm approach: the argument is reduced to a small
+//! interval, a polynomial is evaluated there in `f64`, and the result is rounded back to
+//! `f32`. Sines and cosines reduce their argument exactly using the bits of 2/π, so they
+//! stay accurate even for the large phases accumulated during voiced synthesis.
+//!
+//! These are used whether or not `std` is enabled, rather than the inherent methods of
+//! `f32`, so a decoder produces the same output from the same generator in every build.
+
+use core::f32;
+use core::f64::consts::{FRAC_PI_2, FRAC_PI_4, LN_2, LOG2_E, SQRT_2};
+
+/// Smallest magnitude at which every `f32` is an integer.
+const INTEGRAL: f32 = 8388608.0;
+
+/// Bits of 2/π after the binary point, most significant first.
+static FRAC_2_PI: [u64; 4] = [
+    0xa2f9836e4e441529,
+    0xfc2757d1f534ddc0,
+    0xdb6295993c439041,
+    0xfe5163abdebbc561,
+];
+
+/// Compute sin x.
+pub fn sin(x: f32) -> f32 { sin_cos(x).0 }
+
+/// Compute cos x.
+pub fn cos(x: f32) -> f32 { sin_cos(x).1 }
+
+/// Compute (sin x, cos x).
+pub fn sin_cos(x: f32) -> (f32, f32) {
+    if !x.is_finite() {
+        return (f32::NAN, f32::NAN);
+    }
+
+    let (quadrant, r) = reduce(x.abs());
+    let (s, c) = (sin_poly(r), cos_poly(r));
+
+    // Rotate back by the multiple of π/2 removed during reduction.
+    let (s, c) = match quadrant & 3 {
+        0 => (s, c),
+        1 => (c, -s),
+        2 => (-s, -c),
+        _ => (-c, s),
+    };
+
+    // sin is odd and cos is even.
+    ((if x < 0.0 { -s } else { s }) as f32, c as f32)
+}
+
+/// Reduce the given nonnegative x to x = qπ/2 + r with |r| ≤ π/4, returning the
+/// quadrant q mod 4 and r.
+fn reduce(x: f32) -> (u32, f64) {
+    if (x as f64) <= FRAC_PI_4 {
+        return (0, x as f64);
+    }
+
+    // Since x ≥ π/4 is normal, x = M 2^E for an integer 2^23 ≤ M < 2^24.
+    let bits = x.to_bits();
+    let mant = (bits & 0x7fffff | 0x800000) as u128;
+    let exp = ((bits >> 23) & 0xff) as i32 - 150;
+
+    // Bit j of 2/π contributes M 2^(E-j-1) to 2x/π, which is a multiple of 4 for
+    // j < E - 2 and can be dropped without changing the quadrant. The 96 bits from
+    // there on carry far more precision than the 24-bit mantissa needs.
+    let start = (exp - 2).max(0) as u32;
+    let shift = start + 96 - exp as u32;
+    let prod = mant * frac_2_pi(start);
+
+    let mut quadrant = (prod >> shift) as u32 & 3;
+    let frac = prod & ((1 << shift) - 1);
+    let mut frac = (frac >> (shift - 53)) as f64 / (1u64 << 53) as f64;
+
+    // Round to the nearest quadrant so the remainder is within ±π/4.
+    if frac >= 0.5 {
+        frac -= 1.0;
+        quadrant += 1;
+    }
+
+    (quadrant, frac * FRAC_PI_2)
+}
+
+/// Retrieve the 96 bits of 2/π starting at the given bit after the binary point.
+fn frac_2_pi(start: u32) -> u128 {
+    let (word, off) = ((start / 64) as usize, start % 64);
+    let hi = (FRAC_2_PI[word] as u128) << 64 | FRAC_2_PI[word + 1] as u128;
+    let lo = (FRAC_2_PI[word + 2] as u128) >> (64 - off);
+
+    ((hi << off) | lo) >> 32
+}
+
+/// Compute sin r for |r| ≤ π/4 with its Taylor polynomial.
+fn sin_poly(r: f64) -> f64 {
+    let z = r * r;
+
+    r + r * z * (-1.0 / 6.0 + z * (1.0 / 120.0 + z * (-1.0 / 5040.0 +
+        z * (1.0 / 362880.0 + z * (-1.0 / 39916800.0)))))
+}
+
+/// Compute cos r for |r| ≤ π/4 with its Taylor polynomial.
+fn cos_poly(r: f64) -> f64 {
+    let z = r * r;
+
+    1.0 + z * (-1.0 / 2.0 + z * (1.0 / 24.0 + z * (-1.0 / 720.0 +
+        z * (1.0 / 40320.0 + z * (-1.0 / 3628800.0 + z * (1.0 / 479001600.0))))))
+}
+
+/// Compute e<sup>x</sup>.
+pub fn exp(x: f32) -> f32 { exp2_wide(x as f64 * LOG2_E) }
+
+/// Compute 2<sup>x</sup>.
+pub fn exp2(x: f32) -> f32 { exp2_wide(x as f64) }
+
+/// Compute 2<sup>y</sup> rounded to `f32`.
+fn exp2_wide(y: f64) -> f32 {
+    if y.is_nan() {
+        return f32::NAN;
+    }
+
+    if y >= 128.0 {
+        return f32::INFINITY;
+    }
+
+    if y < -150.0 {
+        return 0.0;
+    }
+
+    // Split y = n + t with integer n and |t| ≤ 1/2, so 2^y = 2^n e^(t ln 2).
+    let n = floor_wide(y + 0.5);
+    let t = (y - n) * LN_2;
+
+    let p = 1.0 + t * (1.0 + t * (1.0 / 2.0 + t * (1.0 / 6.0 + t * (1.0 / 24.0 +
+        t * (1.0 / 120.0 + t * (1.0 / 720.0 + t * (1.0 / 5040.0 +
+        t * (1.0 / 40320.0))))))));
+
+    // Scaling by 2^n is exact, and the final rounding handles subnormal results.
+    (p * f64::from_bits(((n as i64 + 1023) as u64) << 52)) as f32
+}
+
+/// Compute the natural logarithm of x.
+pub fn ln(x: f32) -> f32 { (log2_wide(x) * LN_2) as f32 }
+
+/// Compute the base-2 logarithm of x.
+pub fn log2(x: f32) -> f32 { log2_wide(x) as f32 }
+
+/// Compute log<sub>2</sub>x without rounding to `f32`.
+fn log2_wide(x: f32) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+
+    if x.is_infinite() {
+        return f64::INFINITY;
+    }
+
+    // Every f32, including subnormals, is a normal f64, so x = m 2^e with m in
+    // [1, 2) can be read off the bits.
+    let bits = (x as f64).to_bits();
+    let mut exp = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut mant = f64::from_bits(bits & ((1 << 52) - 1) | 1023 << 52);
+
+    // Center the mantissa on 1 to keep the series argument small.
+    if mant > SQRT_2 {
+        mant /= 2.0;
+        exp += 1;
+    }
+
+    // ln m = 2 atanh s for s = (m - 1) / (m + 1), where |s| < 0.172.
+    let s = (mant - 1.0) / (mant + 1.0);
+    let z = s * s;
+
+    let ln = 2.0 * s * (1.0 + z * (1.0 / 3.0 + z * (1.0 / 5.0 + z * (1.0 / 7.0 +
+        z * (1.0 / 9.0 + z * (1.0 / 11.0))))));
+
+    exp as f64 + ln * LOG2_E
+}
+
+/// Compute x<sup>y</sup>.
+pub fn powf(x: f32, y: f32) -> f32 {
+    if y == 0.0 || x == 1.0 {
+        return 1.0;
+    }
+
+    if x < 0.0 {
+        // Negative bases only have real powers for integer exponents.
+        if trunc(y) != y {
+            return f32::NAN;
+        }
+
+        let p = powf(-x, y);
+        let odd = y.abs() < INTEGRAL && (y as i64) % 2 != 0;
+
+        return if odd { -p } else { p };
+    }
+
+    exp2_wide(y as f64 * log2_wide(x))
+}
+
+/// Compute x<sup>n</sup> by repeated squaring, in the same order as the compiler
+/// runtime.
+pub fn powi(x: f32, n: i32) -> f32 {
+    let mut base = x;
+    let mut rem = n.unsigned_abs();
+    let mut prod = 1.0;
+
+    loop {
+        if rem & 1 != 0 {
+            prod *= base;
+        }
+
+        rem >>= 1;
+
+        if rem == 0 {
+            break;
+        }
+
+        base *= base;
+    }
+
+    if n < 0 { 1.0 / prod } else { prod }
+}
+
+/// Compute the square root of x.
+pub fn sqrt(x: f32) -> f32 {
+    if x < 0.0 {
+        return f32::NAN;
+    }
+
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+
+    // Halving the exponent gives an estimate within a few percent, which Newton's
+    // method refines past f32 precision.
+    let w = x as f64;
+    let mut y = f64::from_bits(0x1ff7a3bea91d9b1b + (w.to_bits() >> 1));
+
+    for _ in 0..4 {
+        y = 0.5 * (y + w / y);
+    }
+
+    y as f32
+}
+
+/// Compute the largest integer less than or equal to x.
+pub fn floor(x: f32) -> f32 {
+    let t = trunc(x);
+    if t > x { t - 1.0 } else { t }
+}
+
+/// Compute the largest integer less than or equal to the given y, which must be
+/// well within the range of `i64`.
+fn floor_wide(y: f64) -> f64 {
+    let t = y as i64 as f64;
+    if t > y { t - 1.0 } else { t }
+}
+
+/// Compute the smallest integer greater than or equal to x.
+pub fn ceil(x: f32) -> f32 {
+    let t = trunc(x);
+    if t < x { t + 1.0 } else { t }
+}
+
+/// Round x to the nearest integer, with halfway cases away from zero.
+pub fn round(x: f32) -> f32 {
+    let t = trunc(x);
+    if (x - t).abs() >= 0.5 { t + x.signum() } else { t }
+}
+
+/// Compute the integer part of x.
+pub fn trunc(x: f32) -> f32 {
+    if x.abs() < INTEGRAL { x as i32 as f32 } else { x }
+}
+
+/// Compute the fractional part of x.
+pub fn fract(x: f32) -> f32 { x - trunc(x) }
+
+#[cfg(test)]
+mod test {
+    use math;
+
+    /// Check if the given values differ by at most the given number of ULPs.
+    fn ulps(a: f32, b: f32, max: u32) -> bool {
+        if a.is_nan() || b.is_nan() {
+            return a.is_nan() && b.is_nan();
+        }
+
+        a == b || (a.signum() == b.signum() &&
+            (a.to_bits() as i64 - b.to_bits() as i64).abs() <= max as i64)
+    }
+
+    /// Generate a deterministic spread of values covering the given range.
+    fn spread(lower: f32, upper: f32) -> Vec<f32> {
+        (0..20000).map(|i| {
+            lower + (upper - lower) * ((i * 7919 % 20000) as f32 / 20000.0)
+        }).collect()
+    }
+
+    #[test]
+    fn test_trig() {
+        let mut xs = spread(-10.0, 10.0);
+        xs.extend(spread(-1e5, 1e5));
+        xs.extend(&[0.0, -0.0, 1e-40, 0.785, 1e6, 123456.79, 3e9, -7e20, 3e38]);
+
+        for &x in &xs {
+            let (s, c) = math::sin_cos(x);
+            assert!((s - x.sin()).abs() < 2e-7, "sin {}", x);
+            assert!((c - x.cos()).abs() < 2e-7, "cos {}", x);
+        }
+
+        assert!(math::sin(f32::INFINITY).is_nan());
+        assert!(math::cos(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_exp_log() {
+        for &x in &spread(-160.0, 130.0) {
+            assert!(ulps(math::exp2(x), x.exp2(), 1), "exp2 {}", x);
+        }
+
+        for &x in &spread(-100.0, 88.0) {
+            assert!(ulps(math::exp(x), x.exp(), 1), "exp {}", x);
+        }
+
+        let mut xs = spread(0.0, 4.0);
+        xs.extend(spread(0.0, 1e6));
+        xs.extend(&[1e-44, 1e-30, 3e38, f32::INFINITY]);
+
+        for &x in &xs {
+            assert!(ulps(math::log2(x), x.log2(), 1), "log2 {}", x);
+            assert!(ulps(math::ln(x), x.ln(), 1), "ln {}", x);
+        }
+
+        assert!(math::log2(-1.0).is_nan());
+        assert_eq!(math::log2(0.0), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_pow() {
+        for &x in &spread(0.0, 1e5) {
+            for &y in &[0.375, 0.25, -1.5, 2.0, 0.0] {
+                assert!(ulps(math::powf(x, y), x.powf(y), 2), "powf {} {}", x, y);
+            }
+
+            for &n in &[0, 1, 2, 3, -2] {
+                assert_eq!(math::powi(x, n), x.powi(n));
+            }
+        }
+
+        assert_eq!(math::powf(-2.0, 3.0), -8.0);
+        assert_eq!(math::powf(-2.0, 2.0), 4.0);
+        assert!(math::powf(-2.0, 0.5).is_nan());
+    }
+
+    #[test]
+    fn test_sqrt() {
+        let mut xs = spread(0.0, 100.0);
+        xs.extend(spread(0.0, 1e30));
+        xs.extend(&[1e-45, 1e-40, 2.0, 3e38, f32::INFINITY, 0.0]);
+
+        for &x in &xs {
+            assert_eq!(math::sqrt(x), x.sqrt());
+        }
+
+        assert!(math::sqrt(-1.0).is_nan());
+    }
+
+    #[test]
+    fn test_rounding() {
+        let mut xs = spread(-100.0, 100.0);
+        xs.extend(&[0.5, -0.5, 1.5, -2.5, 0.49999997, 8388607.5, 1e10, -3e38]);
+
+        for &x in &xs {
+            assert_eq!(math::floor(x), x.floor());
+            assert_eq!(math::ceil(x), x.ceil());
+            assert_eq!(math::round(x), x.round());
+            assert_eq!(math::trunc(x), x.trunc());
+            assert_eq!(math::fract(x), x.fract());
+        }
+    }
+}
@@ -1,10 +1,13 @@
 //! Frame parameters.
 
-use std::cmp::min;
-use std::f32::consts::PI;
+use core::cmp::min;
+use core::f32::consts::PI;
+
+use math;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 
 /// Basic parameters of the current frame.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct BaseParams {
     /// Fundamental frequency ω<sub>0</sub> the frame is derived from.
     pub fundamental: f32,
@@ -27,9 +30,13 @@ impl BaseParams {
     /// Create a new `BaseParams` from the given floating-point period b<sub>0</sub>.
     fn from_float(period: f32) -> BaseParams {
         // Compute Eq 46.
-        let f = 4.0 * PI / (period + 39.5);
+        Self::from_fundamental(4.0 * PI / (period + 39.5))
+    }
+
+    /// Create a new `BaseParams` from the given fundamental frequency ω<sub>0</sub>.
+    fn from_fundamental(f: f32) -> BaseParams {
         // Compute Eq 47.
-        let h = (0.9254 * (PI / f + 0.25).floor()) as u32;
+        let h = (0.9254 * math::floor(PI / f + 0.25)) as u32;
         // Compute Eq 48.
         let b = min((h + 2) / 3, 12);
 
@@ -53,6 +60,37 @@ impl Default for BaseParams {
     }
 }
 
+impl Persist for BaseParams {
+    fn save(&self, w: &mut Writer) {
+        w.f32(self.fundamental);
+        w.u32(self.harmonics);
+        w.u32(self.bands);
+    }
+
+    fn load(r: &mut Reader) -> Result<BaseParams, SnapshotError> {
+        let fundamental = r.f32()?;
+        let harmonics = r.u32()?;
+        let bands = r.u32()?;
+
+        // Other values would index past the DFT and harmonic tables used in decoding, so
+        // ω_0 must be within the range of Eq 46 over valid periods, and L and K must be
+        // derived from it by Eqs 47 and 48.
+        let range = BaseParams::new(207).fundamental..=BaseParams::new(0).fundamental;
+
+        if !range.contains(&fundamental) {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        let p = BaseParams::from_fundamental(fundamental);
+
+        if p.harmonics != harmonics || p.bands != bands {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(p)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -77,4 +115,34 @@ mod tests {
         assert_eq!(p.harmonics, 33);
         assert_eq!(p.bands, 11);
     }
+
+    #[test]
+    fn test_persist() {
+        let load = |fundamental: f32, harmonics: u32, bands: u32| {
+            let mut buf = [0; 32];
+
+            let len = {
+                let mut w = Writer::new(&mut buf);
+                w.f32(fundamental);
+                w.u32(harmonics);
+                w.u32(bands);
+                w.finish().unwrap()
+            };
+
+            let mut r = Reader::new(&buf[..len]).unwrap();
+            BaseParams::load(&mut r).map(|p| (p.fundamental, p.harmonics, p.bands))
+        };
+
+        let p = BaseParams::new(104);
+        assert_eq!(load(p.fundamental, 33, 11), Ok((p.fundamental, 33, 11)));
+
+        let p = BaseParams::default();
+        assert_eq!(load(p.fundamental, 30, 10), Ok((p.fundamental, 30, 10)));
+
+        assert_eq!(load(1.0, 33, 11), Err(SnapshotError::InvalidValue));
+        assert_eq!(load(0.01, 33, 11), Err(SnapshotError::InvalidValue));
+        assert_eq!(load(f32::NAN, 33, 11), Err(SnapshotError::InvalidValue));
+        assert_eq!(load(p.fundamental, 33, 10), Err(SnapshotError::InvalidValue));
+        assert_eq!(load(p.fundamental, 30, 11), Err(SnapshotError::InvalidValue));
+    }
 }
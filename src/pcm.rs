@@ -0,0 +1,149 @@
+//! Conversion of decoded audio samples to calibrated PCM formats.
+//!
+//! The samples s(n) written by the decoder keep the scale of the synthesis equations in
+//! the standard, which is that of 16-bit linear PCM: a sample magnitude of
+//! [`FULL_SCALE`](constant.FULL_SCALE.html) corresponds to digital full scale. The
+//! conversions in this module are all defined in terms of that mapping,
+//!
+//! > x<sub>i16</sub>(n) = clamp(round(s(n)), -32768, 32767)
+//! >
+//! > x<sub>norm</sub>(n) = clamp(s(n) / 32768, -1, 1)
+//!
+//! so that out-of-range samples saturate rather than wrap around.
+//!
+//! When reducing to 16 bits, triangular-PDF (TPDF) dither with a peak amplitude of 1 LSB
+//! can optionally be added before rounding to decorrelate the quantization error from
+//! the signal.
+
+use rand::Rng;
+
+use consts::SAMPLES_PER_FRAME;
+use frame::AudioBuf;
+use math;
+
+/// Decoder sample magnitude that corresponds to digital full scale.
+pub const FULL_SCALE: f32 = 32768.0;
+
+/// 16-bit PCM samples in a decoded voice frame.
+pub type PcmBuf = [i16; SAMPLES_PER_FRAME];
+
+/// Convert the given decoder sample to a 16-bit PCM sample, saturating if it falls
+/// outside the representable range.
+pub fn to_i16(s: f32) -> i16 {
+    math::round(s).clamp(-FULL_SCALE, FULL_SCALE - 1.0) as i16
+}
+
+/// Convert the given decoder sample to a normalized sample in [-1, 1], saturating if it
+/// falls outside full scale.
+pub fn to_normalized(s: f32) -> f32 {
+    (s / FULL_SCALE).clamp(-1.0, 1.0)
+}
+
+/// Convert the given decoded samples to 16-bit PCM samples.
+pub fn quantize(buf: &AudioBuf, out: &mut PcmBuf) {
+    for (o, &s) in out.iter_mut().zip(buf.iter()) {
+        *o = to_i16(s);
+    }
+}
+
+/// Convert the given decoded samples to 16-bit PCM samples, applying dither from the
+/// given generator before rounding.
+pub fn quantize_dithered<R: Rng>(buf: &AudioBuf, dither: &mut Dither<R>,
+                                 out: &mut PcmBuf)
+{
+    for (o, &s) in out.iter_mut().zip(buf.iter()) {
+        *o = to_i16(s + dither.sample());
+    }
+}
+
+/// Normalize the given decoded samples in place to the range [-1, 1].
+pub fn normalize(buf: &mut AudioBuf) {
+    for s in buf.iter_mut() {
+        *s = to_normalized(*s);
+    }
+}
+
+/// Generates triangular-PDF dither with a peak amplitude of 1 LSB.
+pub struct Dither<R: Rng>(R);
+
+impl<R: Rng> Dither<R> {
+    /// Create a new `Dither` drawing uniform noise from the given generator.
+    pub fn new(rng: R) -> Self {
+        Dither(rng)
+    }
+
+    /// Draw the next dither value, in units of 16-bit LSBs.
+    pub fn sample(&mut self) -> f32 {
+        // The difference of two independent uniform variables on [0, 1) has a
+        // triangular distribution on (-1, 1).
+        self.0.gen::<f32>() - self.0.gen::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::XorShiftRng;
+
+    #[test]
+    fn test_to_i16() {
+        assert_eq!(to_i16(0.0), 0);
+        assert_eq!(to_i16(0.4), 0);
+        assert_eq!(to_i16(0.6), 1);
+        assert_eq!(to_i16(-0.6), -1);
+        assert_eq!(to_i16(1234.5), 1235);
+        assert_eq!(to_i16(32767.0), 32767);
+        assert_eq!(to_i16(32767.4), 32767);
+        assert_eq!(to_i16(32768.0), 32767);
+        assert_eq!(to_i16(100000.0), 32767);
+        assert_eq!(to_i16(-32768.0), -32768);
+        assert_eq!(to_i16(-100000.0), -32768);
+    }
+
+    #[test]
+    fn test_to_normalized() {
+        assert_eq!(to_normalized(0.0), 0.0);
+        assert_eq!(to_normalized(16384.0), 0.5);
+        assert_eq!(to_normalized(-16384.0), -0.5);
+        assert_eq!(to_normalized(32768.0), 1.0);
+        assert_eq!(to_normalized(50000.0), 1.0);
+        assert_eq!(to_normalized(-50000.0), -1.0);
+    }
+
+    #[test]
+    fn test_quantize() {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        buf[0] = 1.4;
+        buf[1] = -40000.0;
+        buf[2] = 40000.0;
+
+        let mut out = [0; SAMPLES_PER_FRAME];
+        quantize(&buf, &mut out);
+
+        assert_eq!(out[0], 1);
+        assert_eq!(out[1], -32768);
+        assert_eq!(out[2], 32767);
+        assert!(out[3..].iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn test_dither() {
+        let mut d = Dither::new(XorShiftRng::new_unseeded());
+        let mut sum = 0.0;
+
+        for _ in 0..10000 {
+            let x = d.sample();
+            assert!(x > -1.0 && x < 1.0);
+            sum += x;
+        }
+
+        assert!((sum / 10000.0f32).abs() < 0.02);
+
+        // Dither should never move a sample by more than 1 LSB.
+        let buf = [100.0; SAMPLES_PER_FRAME];
+        let mut out = [0; SAMPLES_PER_FRAME];
+        quantize_dithered(&buf, &mut d, &mut out);
+
+        assert!(out.iter().all(|x| (99..=101).contains(x)));
+    }
+}
@@ -0,0 +1,147 @@
+//! Policies for concealing frames with uncorrected errors.
+//!
+//! From the error characteristics of each frame, the decoder decides whether to repeat
+//! the previous frame or mute, and how strongly to smooth the spectral amplitudes and
+//! voicing decisions. An `ErrorPolicy` makes those decisions: `Standard` follows the
+//! thresholds in the standard, `Lenient` conceals fewer frames to favor intelligibility on
+//! marginal channels, and `Strict` conceals more to favor suppressing artifacts.
+
+use enhance::{self, EnhanceErrors, FrameEnergy};
+use math;
+
+/// Makes the error concealment decisions for each frame.
+///
+/// Each method defaults to the behavior in the standard, so an implementation only needs
+/// to override the decisions it changes.
+pub trait ErrorPolicy {
+    /// Check whether the current frame should be discarded and the previous frame
+    /// repeated based on the given error characteristics of the current frame.
+    fn should_repeat(&self, errors: &EnhanceErrors) -> bool {
+        enhance::should_repeat(errors)
+    }
+
+    /// Check whether the current frame should be discarded and muted based on the given
+    /// error characteristics of the current frame.
+    fn should_mute(&self, errors: &EnhanceErrors) -> bool {
+        enhance::should_mute(errors)
+    }
+
+    /// Compute the adaptive threshold V<sub>M</sub> above which enhanced spectral
+    /// amplitudes are forced voiced, from the given error characteristics and current
+    /// frame energy.
+    fn voice_thresh(&self, errors: &EnhanceErrors, energy: &FrameEnergy) -> f32 {
+        enhance::voice_thresh(errors, energy)
+    }
+
+    /// Compute the spectral amplitude threshold τ<sub>M</sub> from the given error
+    /// characteristics and previous amplitude threshold.
+    fn amp_thresh(&self, errors: &EnhanceErrors, prev: f32) -> f32 {
+        enhance::amp_thresh(errors, prev)
+    }
+}
+
+/// Makes each decision as in the standard (Eqs 97, 98, 112, and 115 and the muting
+/// condition on [p47]).
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Standard;
+
+impl ErrorPolicy for Standard {}
+
+/// Repeats and mutes only frames with more errors than the standard allows, and skips
+/// forced voicing on channels with a low error rate.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Lenient;
+
+impl ErrorPolicy for Lenient {
+    fn should_repeat(&self, errors: &EnhanceErrors) -> bool {
+        errors.golay_init >= 3 && errors.total as f32 >= 14.0 + 40.0 * errors.rate
+    }
+
+    fn should_mute(&self, errors: &EnhanceErrors) -> bool {
+        errors.rate > 0.15
+    }
+
+    fn voice_thresh(&self, errors: &EnhanceErrors, energy: &FrameEnergy) -> f32 {
+        if errors.rate <= 0.0125 {
+            f32::MAX
+        } else {
+            enhance::voice_thresh(errors, energy)
+        }
+    }
+}
+
+/// Repeats and mutes frames with fewer errors than the standard allows, and applies the
+/// strongest smoothing to any frame with errors or a raised error rate.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Strict;
+
+impl ErrorPolicy for Strict {
+    fn should_repeat(&self, errors: &EnhanceErrors) -> bool {
+        errors.golay_init >= 1 && errors.total as f32 >= 6.0 + 40.0 * errors.rate
+    }
+
+    fn should_mute(&self, errors: &EnhanceErrors) -> bool {
+        errors.rate > 0.05
+    }
+
+    fn voice_thresh(&self, errors: &EnhanceErrors, energy: &FrameEnergy) -> f32 {
+        if errors.rate <= 0.005 && errors.total == 0 {
+            f32::MAX
+        } else {
+            1.414 * math::powf(energy.tracking, 0.375)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn errors(total: usize, rate: f32, golay_init: usize) -> EnhanceErrors {
+        EnhanceErrors {
+            total: total,
+            rate: rate,
+            golay_init: golay_init,
+            hamming_init: 0,
+        }
+    }
+
+    #[test]
+    fn test_standard() {
+        let fen = FrameEnergy::default();
+
+        for &(total, rate, golay) in &[(0, 0.0, 0), (12, 0.01, 2), (30, 0.1, 3)] {
+            let e = errors(total, rate, golay);
+
+            assert_eq!(Standard.should_repeat(&e), enhance::should_repeat(&e));
+            assert_eq!(Standard.should_mute(&e), enhance::should_mute(&e));
+            assert_eq!(Standard.voice_thresh(&e, &fen), enhance::voice_thresh(&e, &fen));
+            assert_eq!(Standard.amp_thresh(&e, 100.0), enhance::amp_thresh(&e, 100.0));
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let fen = FrameEnergy::default();
+        let policies: [&dyn ErrorPolicy; 3] = [&Lenient, &Standard, &Strict];
+
+        // Each policy conceals at least the frames the more lenient ones do.
+        for total in 0..40 {
+            for golay in 0..4 {
+                for &rate in &[0.0, 0.004, 0.01, 0.03, 0.06, 0.1, 0.2] {
+                    let e = errors(total, rate, golay.min(total));
+
+                    for w in policies.windows(2) {
+                        assert!(!w[0].should_repeat(&e) || w[1].should_repeat(&e));
+                        assert!(!w[0].should_mute(&e) || w[1].should_mute(&e));
+                        assert!(w[0].voice_thresh(&e, &fen) >= w[1].voice_thresh(&e, &fen));
+                    }
+                }
+            }
+        }
+
+        let e = errors(13, 0.06, 2);
+        assert!(!Lenient.should_repeat(&e) && !Standard.should_mute(&e));
+        assert!(Standard.should_repeat(&e) && Strict.should_mute(&e));
+    }
+}
@@ -0,0 +1,175 @@
+//! Long-lived worker threads for frame synthesis.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Work split into parts that are each run on a worker.
+///
+/// Submitting a part only clones the `Arc`, so work that recurs every frame can reuse the
+/// same storage rather than allocating a job for each part.
+pub trait Parts: Send + Sync {
+    /// Run the part with the given index.
+    fn run(&self, part: usize);
+}
+
+/// Unit of work executed on a worker thread.
+enum Job {
+    /// Run the given closure.
+    Once(Box<dyn FnOnce() + Send>),
+    /// Run the given part of the given work.
+    Part(Arc<dyn Parts>, usize),
+}
+
+/// A fixed set of worker threads that execute jobs submitted from any number of decoders.
+///
+/// Handles are cheap to clone and share the same threads, which exit once the last handle
+/// is dropped. A job that panics is abandoned, and its worker goes on to the next job, so
+/// one failing job doesn't take a thread away from the rest.
+#[derive(Clone)]
+pub struct WorkerPool(Arc<PoolInner>);
+
+/// Shared state behind a `WorkerPool` handle.
+struct PoolInner {
+    /// Submits jobs to the workers.
+    jobs: Mutex<Option<Sender<Job>>>,
+    /// Worker thread handles, joined on drop.
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Create a new `WorkerPool` with the given number of worker threads.
+    pub fn new(threads: usize) -> WorkerPool {
+        assert!(threads > 0);
+
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        let workers = (0..threads).map(|i| {
+            let rx = rx.clone();
+
+            thread::Builder::new()
+                .name(format!("imbe-worker-{}", i))
+                .spawn(move || work(&rx))
+                .expect("unable to spawn worker thread")
+        }).collect();
+
+        WorkerPool(Arc::new(PoolInner {
+            jobs: Mutex::new(Some(tx)),
+            workers: workers,
+        }))
+    }
+
+    /// Retrieve the number of worker threads in the pool.
+    pub fn threads(&self) -> usize { self.0.workers.len() }
+
+    /// Queue the given job for execution on the next available worker.
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.send(Job::Once(Box::new(job)));
+    }
+
+    /// Queue the given part of the given work for execution on the next available
+    /// worker.
+    pub fn execute_part(&self, parts: Arc<dyn Parts>, part: usize) {
+        self.send(Job::Part(parts, part));
+    }
+
+    /// Queue the given job.
+    fn send(&self, job: Job) {
+        self.0.jobs.lock().unwrap()
+            .as_ref().expect("pool is shut down")
+            .send(job).expect("worker threads exited");
+    }
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        // Closing the channel causes each worker to exit after finishing queued jobs.
+        self.jobs.lock().unwrap().take();
+
+        for w in self.workers.drain(..) {
+            w.join().ok();
+        }
+    }
+}
+
+/// Run jobs from the given queue until it's closed.
+fn work(rx: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match rx.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        // Keep the worker alive if the job panics. Whoever waits on the job sees the
+        // failure through state the job shares, such as a channel whose sender it drops.
+        panic::catch_unwind(AssertUnwindSafe(|| match job {
+            Job::Once(f) => f(),
+            Job::Part(parts, i) => parts.run(i),
+        })).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_pool() {
+        let pool = WorkerPool::new(3);
+        assert_eq!(pool.threads(), 3);
+
+        let (tx, rx) = channel();
+
+        for i in 0..20 {
+            let tx = tx.clone();
+            pool.clone().execute(move || tx.send(i * 2).unwrap());
+        }
+
+        let mut results = (0..20).map(|_| rx.recv().unwrap()).collect::<Vec<usize>>();
+        results.sort();
+
+        assert_eq!(results, (0..20).map(|i| i * 2).collect::<Vec<usize>>());
+    }
+
+    struct Sum(Mutex<(usize, Sender<()>)>);
+
+    impl Parts for Sum {
+        fn run(&self, part: usize) {
+            let mut s = self.0.lock().unwrap();
+            s.0 += part;
+            s.1.send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parts() {
+        let pool = WorkerPool::new(2);
+        let (tx, rx) = channel();
+        let sum = Arc::new(Sum(Mutex::new((0, tx))));
+
+        for i in 0..10 {
+            pool.execute_part(sum.clone(), i);
+        }
+
+        for _ in 0..10 {
+            rx.recv().unwrap();
+        }
+
+        assert_eq!(sum.0.lock().unwrap().0, 45);
+    }
+
+    #[test]
+    fn test_panic() {
+        let pool = WorkerPool::new(1);
+        let (tx, rx) = channel();
+
+        pool.execute(|| panic!("job failed"));
+
+        // The only worker survives to run the next job.
+        pool.execute(move || tx.send(()).unwrap());
+        rx.recv().unwrap();
+    }
+}
@@ -3,12 +3,14 @@
 use descramble::VoiceDecisions;
 use enhance::{FrameEnergy, EnhancedSpectrals};
 use params::BaseParams;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 use spectral::Spectrals;
 use unvoiced::UnvoicedDft;
 use voiced::{Phase, PhaseBase};
 
 /// Various parameters saved from the previous frame, used when constructing the current
 /// frame.
+#[derive(Clone)]
 pub struct PrevFrame {
     pub params: BaseParams,
     pub spectrals: Spectrals,
@@ -42,3 +44,40 @@ impl Default for PrevFrame {
         }
     }
 }
+
+impl Persist for PrevFrame {
+    fn save(&self, w: &mut Writer) {
+        self.params.save(w);
+        self.spectrals.save(w);
+        self.enhanced.save(w);
+        self.voice.save(w);
+        w.f32(self.err_rate);
+        self.energy.save(w);
+        w.f32(self.amp_thresh);
+        self.unvoiced.save(w);
+        self.phase_base.save(w);
+        self.phase.save(w);
+    }
+
+    fn load(r: &mut Reader) -> Result<PrevFrame, SnapshotError> {
+        let prev = PrevFrame {
+            params: BaseParams::load(r)?,
+            spectrals: Spectrals::load(r)?,
+            enhanced: EnhancedSpectrals::load(r)?,
+            voice: VoiceDecisions::load(r)?,
+            err_rate: r.f32()?,
+            energy: FrameEnergy::load(r)?,
+            amp_thresh: r.f32()?,
+            unvoiced: UnvoicedDft::load(r)?,
+            phase_base: PhaseBase::load(r)?,
+            phase: Phase::load(r)?,
+        };
+
+        // The decisions are always made for the frame they're saved with.
+        if *prev.voice.params() != prev.params {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(prev)
+    }
+}
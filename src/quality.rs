@@ -0,0 +1,230 @@
+//! Trading decoding quality for decoding time.
+//!
+//! Each `Quality` level below `Full` drops some of the more expensive steps of decoding,
+//! and a `Governor` can step between levels to keep the time spent on each frame within
+//! a budget.
+
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+use consts::MAX_HARMONICS;
+use unvoiced::IdftMethod;
+
+/// Level of decoding quality.
+///
+/// Levels are ordered from best to cheapest, so the greater of two levels is the cheaper
+/// one.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Quality {
+    /// Decode every frame as specified.
+    Full,
+    /// Evaluate the unvoiced IDFT by FFT, and synthesize at most 32 voiced harmonics.
+    Reduced,
+    /// Synthesize at most 16 voiced harmonics, and additionally skip the adaptive
+    /// smoothing of spectral amplitudes and voicing decisions.
+    Minimal,
+}
+
+impl Quality {
+    /// Retrieve the next cheaper level, if any.
+    pub fn cheaper(self) -> Option<Quality> {
+        match self {
+            Quality::Full => Some(Quality::Reduced),
+            Quality::Reduced => Some(Quality::Minimal),
+            Quality::Minimal => None,
+        }
+    }
+
+    /// Retrieve the next better level, if any.
+    pub fn better(self) -> Option<Quality> {
+        match self {
+            Quality::Full => None,
+            Quality::Reduced => Some(Quality::Full),
+            Quality::Minimal => Some(Quality::Reduced),
+        }
+    }
+
+    /// Retrieve the maximum number of harmonics included in voiced synthesis.
+    pub fn max_harmonics(self) -> usize {
+        match self {
+            Quality::Full => MAX_HARMONICS,
+            Quality::Reduced => 32,
+            Quality::Minimal => 16,
+        }
+    }
+
+    /// Check if the enhanced spectral amplitudes and voicing decisions are smoothed
+    /// according to the error characteristics of each frame.
+    pub fn smooth(self) -> bool { self != Quality::Minimal }
+
+    /// Retrieve the method used to evaluate the unvoiced IDFT, given the method
+    /// configured for full quality.
+    pub fn idft(self, method: IdftMethod) -> IdftMethod {
+        match self {
+            Quality::Full => method,
+            _ => IdftMethod::Fft,
+        }
+    }
+}
+
+impl Default for Quality {
+    /// Decode at full quality.
+    fn default() -> Quality { Quality::Full }
+}
+
+/// Weight of each new frame in the average decoding time.
+#[cfg(feature = "std")]
+const SMOOTHING: f32 = 0.25;
+/// Number of frames to measure at a level before considering a switch.
+#[cfg(feature = "std")]
+const HOLD: u32 = 8;
+/// Fraction of the budget the average decoding time must fall below before switching to
+/// a better level.
+#[cfg(feature = "std")]
+const HEADROOM: f32 = 0.5;
+
+/// Steps between quality levels to keep the average decoding time of each frame within
+/// a budget.
+///
+/// The governor moves to a cheaper level when the average time spent on recent frames
+/// exceeds the budget, and back to a better level once it falls well within it. Each
+/// level is measured for several frames before the governor can switch again.
+#[cfg(feature = "std")]
+pub struct Governor {
+    /// Target decoding time of each frame, in seconds.
+    budget: f32,
+    /// Current quality level.
+    level: Quality,
+    /// Moving average of the decoding time of recent frames, in seconds.
+    average: f32,
+    /// Number of frames measured at the current level.
+    frames: u32,
+}
+
+#[cfg(feature = "std")]
+impl Governor {
+    /// Create a new `Governor` starting at full quality with the given budget for each
+    /// frame.
+    pub fn new(budget: Duration) -> Governor {
+        Governor {
+            budget: seconds(budget),
+            level: Quality::Full,
+            average: 0.0,
+            frames: 0,
+        }
+    }
+
+    /// Retrieve the current quality level.
+    pub fn level(&self) -> Quality { self.level }
+
+    /// Record the given time taken to decode a frame at the current level, and switch
+    /// levels if needed.
+    pub fn update(&mut self, elapsed: Duration) {
+        let elapsed = seconds(elapsed);
+
+        self.average = if self.frames == 0 {
+            elapsed
+        } else {
+            self.average + SMOOTHING * (elapsed - self.average)
+        };
+
+        self.frames += 1;
+
+        if self.frames < HOLD {
+            return;
+        }
+
+        let next = if self.average > self.budget {
+            self.level.cheaper()
+        } else if self.average < HEADROOM * self.budget {
+            self.level.better()
+        } else {
+            None
+        };
+
+        if let Some(level) = next {
+            self.level = level;
+            self.frames = 0;
+        }
+    }
+}
+
+/// Convert the given duration to seconds.
+#[cfg(feature = "std")]
+fn seconds(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 * 1e-9
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert!(Quality::Full < Quality::Reduced);
+        assert!(Quality::Reduced < Quality::Minimal);
+
+        assert_eq!(Quality::Full.cheaper(), Some(Quality::Reduced));
+        assert_eq!(Quality::Minimal.cheaper(), None);
+        assert_eq!(Quality::Minimal.better(), Some(Quality::Reduced));
+        assert_eq!(Quality::Full.better(), None);
+
+        assert_eq!(Quality::Full.idft(IdftMethod::Direct), IdftMethod::Direct);
+        assert_eq!(Quality::Reduced.idft(IdftMethod::Direct), IdftMethod::Fft);
+        assert!(Quality::Reduced.smooth());
+        assert!(!Quality::Minimal.smooth());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_governor() {
+        let ms = Duration::from_millis;
+        let mut g = Governor::new(ms(10));
+
+        // A slow first frame doesn't switch levels before the average settles.
+        g.update(ms(20));
+
+        for _ in 0..20 {
+            g.update(ms(7));
+        }
+
+        assert_eq!(g.level(), Quality::Full);
+
+        for _ in 0..HOLD {
+            g.update(ms(15));
+        }
+
+        assert_eq!(g.level(), Quality::Reduced);
+
+        for _ in 0..HOLD {
+            g.update(ms(12));
+        }
+
+        assert_eq!(g.level(), Quality::Minimal);
+
+        for _ in 0..HOLD * 4 {
+            g.update(ms(20));
+        }
+
+        assert_eq!(g.level(), Quality::Minimal);
+
+        // Within the budget but without headroom, the level is held.
+        for _ in 0..HOLD * 4 {
+            g.update(ms(8));
+        }
+
+        assert_eq!(g.level(), Quality::Minimal);
+
+        for _ in 0..HOLD {
+            g.update(ms(3));
+        }
+
+        assert_eq!(g.level(), Quality::Reduced);
+
+        for _ in 0..HOLD {
+            g.update(ms(3));
+        }
+
+        assert_eq!(g.level(), Quality::Full);
+    }
+}
@@ -0,0 +1,218 @@
+//! Adapter exposing decoded audio as a byte stream.
+
+use std::io::{self, Read};
+
+use rand::{Rng, XorShiftRng};
+
+use consts::SAMPLES_PER_FRAME;
+use decode::ImbeDecoder;
+use frame::ReceivedFrame;
+use pcm;
+use policy::{ErrorPolicy, Standard};
+
+/// Number of bytes in a frame of 16-bit PCM samples.
+const FRAME_BYTES: usize = SAMPLES_PER_FRAME * 2;
+
+/// Decodes frames from an iterator on demand and reads them out as signed 16-bit
+/// little-endian PCM bytes, scaled as described in the `pcm` module.
+///
+/// Frames are only pulled from the iterator and decoded when all bytes of the previous
+/// frame have been read, so reads of any size are supported. The stream ends when the
+/// iterator is exhausted.
+///
+/// Frames are decoded as in `ImbeDecoder::try_decode`. A frame that fails is concealed
+/// as in `ImbeDecoder::decode_lost`, so a corrupt frame never interrupts the stream, and
+/// is counted in `invalid`.
+///
+/// The frame decoder draws random terms from a generator of type `R` and conceals errors
+/// with the policy `P`, as in `ImbeDecoder`.
+pub struct PcmReader<I, R = XorShiftRng, P = Standard>
+    where I: Iterator<Item = ReceivedFrame>, R: Rng, P: ErrorPolicy
+{
+    /// Source of frames.
+    frames: I,
+    /// Decoder for received frames.
+    decoder: ImbeDecoder<R, P>,
+    /// Bytes of the most recently decoded frame.
+    buf: [u8; FRAME_BYTES],
+    /// Offset of the next unread byte in the buffer.
+    pos: usize,
+    /// Number of frames that failed to decode and were concealed.
+    invalid: u64,
+}
+
+impl<I, R, P> PcmReader<I, R, P>
+    where I: Iterator<Item = ReceivedFrame>, R: Rng, P: ErrorPolicy
+{
+    /// Create a new `PcmReader` decoding frames from the given iterator with the given
+    /// decoder.
+    pub fn new(frames: I, decoder: ImbeDecoder<R, P>) -> Self {
+        PcmReader {
+            frames: frames,
+            decoder: decoder,
+            buf: [0; FRAME_BYTES],
+            // Start with an empty buffer.
+            pos: FRAME_BYTES,
+            invalid: 0,
+        }
+    }
+
+    /// Retrieve the number of frames so far that failed to decode and were concealed.
+    pub fn invalid(&self) -> u64 { self.invalid }
+
+    /// Consume the reader and return the frame source and decoder. Any buffered bytes
+    /// are discarded.
+    pub fn into_inner(self) -> (I, ImbeDecoder<R, P>) { (self.frames, self.decoder) }
+
+    /// Decode the next frame into the byte buffer, returning false if there are no frames
+    /// left.
+    fn fill(&mut self) -> bool {
+        let frame = match self.frames.next() {
+            Some(f) => f,
+            None => return false,
+        };
+
+        let mut samples = [0; SAMPLES_PER_FRAME];
+
+        if self.decoder.try_decode_i16(frame, &mut samples).is_err() {
+            let mut buf = [0.0; SAMPLES_PER_FRAME];
+            self.decoder.decode_lost(&mut buf);
+            pcm::quantize(&buf, &mut samples);
+
+            self.invalid += 1;
+        }
+
+        for (b, &s) in self.buf.chunks_mut(2).zip(samples.iter()) {
+            b[0] = s as u8;
+            b[1] = (s >> 8) as u8;
+        }
+
+        self.pos = 0;
+
+        true
+    }
+}
+
+impl<I, R, P> Read for PcmReader<I, R, P>
+    where I: Iterator<Item = ReceivedFrame>, R: Rng, P: ErrorPolicy
+{
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+
+        if self.pos == FRAME_BYTES && !self.fill() {
+            return Ok(0);
+        }
+
+        let avail = &self.buf[self.pos..];
+        let n = avail.len().min(out.len());
+
+        out[..n].copy_from_slice(&avail[..n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use policy::Strict;
+    use rand::SeedableRng;
+
+    fn frames(n: usize) -> Vec<ReceivedFrame> {
+        (0..n).map(|i| {
+            if i % 3 == 0 {
+                // Silence frame.
+                ReceivedFrame::new([0b110110000000, 0, 0, 0, 0, 0, 0, 0], [0; 7])
+            } else {
+                ReceivedFrame::new([
+                    0b001000010010,
+                    0b110011001100,
+                    0b111000111000,
+                    0b111111111111,
+                    0b10100110101,
+                    0b00101111010,
+                    0b01110111011,
+                    0b00001000,
+                ], [0; 7])
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_read_all() {
+        let mut r = PcmReader::new(frames(4).into_iter(), ImbeDecoder::new());
+        let mut bytes = vec![];
+        r.read_to_end(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 4 * 320);
+        // First frame is silence.
+        assert!(bytes[..320].iter().all(|&b| b == 0));
+        assert!(bytes[320..640].iter().any(|&b| b != 0));
+        assert_eq!(r.read(&mut [0; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_partial() {
+        let mut r = PcmReader::new(frames(3).into_iter(), ImbeDecoder::new());
+        let mut buf = [0; 7];
+        let mut total = 0;
+
+        loop {
+            let n = r.read(&mut buf).unwrap();
+
+            if n == 0 {
+                break;
+            }
+
+            // Reads never span a frame boundary.
+            assert!(total / 320 == (total + n - 1) / 320);
+            total += n;
+        }
+
+        assert_eq!(total, 3 * 320);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut frames = frames(3);
+        frames[1].errors[6] = 2;
+
+        let mut r = PcmReader::new(frames.into_iter(), ImbeDecoder::new());
+        let mut bytes = vec![];
+
+        // The invalid frame is concealed in place rather than failing the read.
+        r.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 3 * 320);
+        assert_eq!(r.invalid(), 1);
+
+        // Concealment starts by repeating the last frame, which was silence.
+        assert!(bytes[320..640].iter().all(|&b| b == 0));
+        assert!(bytes[640..].iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn test_decoder() {
+        let read = || {
+            let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+            let decoder = ImbeDecoder::with_rng(seeded).with_policy(Strict);
+            let mut bytes = vec![];
+
+            PcmReader::new(frames(5).into_iter(), decoder)
+                .read_to_end(&mut bytes).unwrap();
+
+            bytes
+        };
+
+        // Decoders seeded alike produce the same audio.
+        assert_eq!(read(), read());
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut r = PcmReader::new(frames(0).into_iter(), ImbeDecoder::new());
+        assert_eq!(r.read(&mut [0; 16]).unwrap(), 0);
+    }
+}
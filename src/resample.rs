@@ -0,0 +1,368 @@
+//! Sample rate conversion of decoded audio.
+//!
+//! The decoder produces audio at the fixed 8kHz rate of the codec. This module converts
+//! it to another rate L/M × 8kHz with a polyphase FIR filter: the signal is conceptually
+//! upsampled by L with zero stuffing, lowpass filtered to remove images and aliases, and
+//! then decimated by M, but only the filter phases that contribute to retained output
+//! samples are ever evaluated.
+//!
+//! Filter history and the fractional output position are carried across calls, so audio
+//! can be fed in frame by frame without discontinuities at frame boundaries. The filter
+//! is linear phase and delays the signal by (`TAPS` - 1 / L) / 2 input samples.
+
+use std::error;
+use std::f64::consts::PI;
+use std::fmt;
+
+use consts::SAMPLE_RATE;
+
+/// Number of filter taps applied per output sample, in units of input samples.
+pub const TAPS: usize = 64;
+
+/// Number of zero input samples fed through the filter by `Resampler::drain`, which is
+/// enough to push out the output delayed from the last input sample.
+pub const DRAIN_LEN: usize = TAPS / 2;
+
+/// Maximum upsampling factor L, which bounds the filter bank at `TAPS` × L coefficients.
+/// This covers the common audio rates, such as 44.1kHz with L = 441.
+pub const MAX_INTERP: usize = 1024;
+
+/// Shape parameter β of the Kaiser window applied to the prototype filter, giving
+/// roughly 85dB of stopband attenuation.
+const KAISER_BETA: f64 = 8.6;
+
+/// Width of the filter transition band, as a fraction of the lower of the input and output
+/// Nyquist frequencies. This is fixed by the number of taps and the window shape.
+const TRANSITION: f64 = 0.17;
+
+/// Error in creating a `Resampler`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RateError {
+    /// The output rate is zero.
+    Zero,
+    /// The ratio of the output rate to the decoder rate needs the given upsampling factor
+    /// L, which exceeds `MAX_INTERP`.
+    Ratio(usize),
+}
+
+impl fmt::Display for RateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RateError::Zero => write!(f, "zero output rate"),
+            RateError::Ratio(l) =>
+                write!(f, "rate ratio needs upsampling by {}, over {}", l, MAX_INTERP),
+        }
+    }
+}
+
+impl error::Error for RateError {}
+
+/// Streaming polyphase resampler from the decoder sample rate to an arbitrary output rate.
+pub struct Resampler {
+    /// Output sample rate.
+    rate: usize,
+    /// Upsampling factor L.
+    interp: usize,
+    /// Downsampling factor M.
+    decim: usize,
+    /// Polyphase filter bank, with `TAPS` coefficients for each of the L phases. The
+    /// coefficients in each phase are ordered from the oldest to the newest input sample.
+    bank: Vec<f32>,
+    /// Input history, stored twice over so the most recent `TAPS` samples are always
+    /// contiguous.
+    hist: [f32; 2 * TAPS],
+    /// Position of the most recent sample in the first half of the history.
+    pos: usize,
+    /// Phase, in units of the upsampled rate, of the next output sample relative to the
+    /// next input sample.
+    phase: usize,
+}
+
+impl Resampler {
+    /// Create a new `Resampler` converting from the decoder sample rate to the given
+    /// output rate in Hz.
+    ///
+    /// The rate must reduce with the decoder rate to a ratio L/M with L at most
+    /// `MAX_INTERP`, so the filter bank stays small.
+    pub fn new(rate: usize) -> Result<Resampler, RateError> {
+        if rate == 0 {
+            return Err(RateError::Zero);
+        }
+
+        let g = gcd(rate, SAMPLE_RATE);
+        let interp = rate / g;
+        let decim = SAMPLE_RATE / g;
+
+        if interp > MAX_INTERP {
+            return Err(RateError::Ratio(interp));
+        }
+
+        Ok(Resampler {
+            rate: rate,
+            interp: interp,
+            decim: decim,
+            bank: design(interp, decim),
+            hist: [0.0; 2 * TAPS],
+            pos: TAPS - 1,
+            phase: 0,
+        })
+    }
+
+    /// Retrieve the output sample rate in Hz.
+    pub fn rate(&self) -> usize { self.rate }
+
+    /// Compute the number of output samples produced for the next `n` input samples.
+    pub fn output_len(&self, n: usize) -> usize {
+        // Count the output positions that fall in [0, nL) starting from the current phase.
+        if self.phase >= n * self.interp {
+            0
+        } else {
+            (n * self.interp - self.phase).div_ceil(self.decim)
+        }
+    }
+
+    /// Resample the given input samples, appending the produced output samples to the
+    /// given buffer.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        out.reserve(self.output_len(input.len()));
+
+        for &x in input {
+            self.pos = (self.pos + 1) % TAPS;
+            self.hist[self.pos] = x;
+            self.hist[self.pos + TAPS] = x;
+
+            // The most recent TAPS samples, oldest first.
+            let window = &self.hist[self.pos + 1..self.pos + 1 + TAPS];
+
+            while self.phase < self.interp {
+                let coefs = &self.bank[self.phase * TAPS..(self.phase + 1) * TAPS];
+
+                out.push(coefs.iter().zip(window.iter())
+                    .fold(0.0, |s, (&h, &x)| s + h * x));
+
+                self.phase += self.decim;
+            }
+
+            self.phase -= self.interp;
+        }
+    }
+
+    /// Append the output still held back by the filter delay at the end of a stream to
+    /// the given buffer, as if `DRAIN_LEN` more zero input samples had been given.
+    pub fn drain(&mut self, out: &mut Vec<f32>) {
+        self.process(&[0.0; DRAIN_LEN], out);
+    }
+
+    /// Clear the filter history, as for the start of a new stream.
+    pub fn reset(&mut self) {
+        self.hist = [0.0; 2 * TAPS];
+        self.pos = TAPS - 1;
+        self.phase = 0;
+    }
+}
+
+/// Design the polyphase filter bank for the given upsampling factor L and downsampling
+/// factor M.
+fn design(interp: usize, decim: usize) -> Vec<f32> {
+    let len = TAPS * interp;
+    let center = (len - 1) as f64 / 2.0;
+
+    // Place the cutoff so the transition band ends at the lower Nyquist frequency, in
+    // cycles per sample at the upsampled rate.
+    let nyquist = 0.5 / interp.max(decim) as f64;
+    let cutoff = nyquist * (1.0 - TRANSITION / 2.0);
+
+    let proto = (0..len).map(|n| {
+        let t = n as f64 - center;
+
+        let sinc = if t == 0.0 {
+            2.0 * cutoff
+        } else {
+            (2.0 * PI * cutoff * t).sin() / (PI * t)
+        };
+
+        sinc * kaiser(t / center)
+    }).collect::<Vec<f64>>();
+
+    // Normalize for a passband gain of L, which makes up for the energy lost to zero
+    // stuffing.
+    let scale = interp as f64 / proto.iter().fold(0.0, |s, &x| s + x);
+
+    let mut bank = Vec::with_capacity(len);
+
+    for p in 0..interp {
+        // Output at phase p draws on prototype coefficients p, p + L, p + 2L, ..., with
+        // coefficient p + kL applied to the input k samples in the past.
+        bank.extend((0..TAPS).rev().map(|k| (proto[p + k * interp] * scale) as f32));
+    }
+
+    bank
+}
+
+/// Compute the Kaiser window at the given position x, -1 ≤ x ≤ 1.
+fn kaiser(x: f64) -> f64 {
+    bessel_i0(KAISER_BETA * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(KAISER_BETA)
+}
+
+/// Compute the zeroth-order modified Bessel function of the first kind, I<sub>0</sub>(x).
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+
+    // Sum the power series until terms become insignificant.
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+
+    sum
+}
+
+/// Compute the greatest common divisor of the given values.
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+    use consts::{SAMPLE_RATE, SAMPLES_PER_FRAME};
+
+    fn tone(freq: f32, n: usize) -> Vec<f32> {
+        (0..n).map(|i| {
+            (2.0 * PI * freq * i as f32 / SAMPLE_RATE as f32).sin()
+        }).collect()
+    }
+
+    /// Compute the magnitude of the given frequency component in the given signal.
+    fn magnitude(sig: &[f32], freq: f32, rate: usize) -> f32 {
+        let (re, im) = sig.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, &x)| {
+            let w = 2.0 * PI * freq * i as f32 / rate as f32;
+            (re + x * w.cos(), im + x * w.sin())
+        });
+
+        2.0 * (re * re + im * im).sqrt() / sig.len() as f32
+    }
+
+    #[test]
+    fn test_ratios() {
+        let r = Resampler::new(16000).unwrap();
+        assert_eq!((r.interp, r.decim), (2, 1));
+        let r = Resampler::new(44100).unwrap();
+        assert_eq!((r.interp, r.decim), (441, 80));
+        let r = Resampler::new(48000).unwrap();
+        assert_eq!((r.interp, r.decim), (6, 1));
+        let r = Resampler::new(11025).unwrap();
+        assert_eq!((r.interp, r.decim), (441, 320));
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(Resampler::new(0).err(), Some(RateError::Zero));
+        assert_eq!(Resampler::new(44101).err(), Some(RateError::Ratio(44101)));
+        assert!(Resampler::new(176400).is_ok());
+    }
+
+    #[test]
+    fn test_frame_lens() {
+        let buf = [0.0; SAMPLES_PER_FRAME];
+
+        for &(rate, len) in &[(16000, 320), (44100, 882), (48000, 960), (8000, 160)] {
+            let mut r = Resampler::new(rate).unwrap();
+            let mut out = vec![];
+
+            for i in 1..=10 {
+                assert_eq!(r.output_len(SAMPLES_PER_FRAME), len);
+                r.process(&buf[..], &mut out);
+                assert_eq!(out.len(), i * len);
+            }
+        }
+
+        // Non-integer output frame lengths should average out.
+        let mut r = Resampler::new(11025).unwrap();
+        let mut out = vec![];
+
+        for _ in 0..80 {
+            let n = r.output_len(SAMPLES_PER_FRAME);
+            assert!(n == 220 || n == 221);
+            r.process(&buf[..], &mut out);
+        }
+
+        assert_eq!(out.len(), 11025 * 80 * SAMPLES_PER_FRAME / SAMPLE_RATE);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let sig = tone(1000.0, SAMPLES_PER_FRAME * 8);
+
+        let mut whole = vec![];
+        Resampler::new(44100).unwrap().process(&sig[..], &mut whole);
+
+        let mut parts = vec![];
+        let mut r = Resampler::new(44100).unwrap();
+
+        for chunk in sig.chunks(SAMPLES_PER_FRAME) {
+            r.process(chunk, &mut parts);
+        }
+
+        assert_eq!(whole, parts);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut sig = vec![0.0; SAMPLES_PER_FRAME];
+        sig[SAMPLES_PER_FRAME - 1] = 1.0;
+
+        let mut out = vec![];
+        let mut r = Resampler::new(16000).unwrap();
+        r.process(&sig[..], &mut out);
+
+        // The impulse at the end is still in the filter.
+        assert!(out.iter().all(|&x| x.abs() < 0.5));
+
+        let len = out.len();
+        r.drain(&mut out);
+
+        assert_eq!(out.len(), len + 2 * DRAIN_LEN);
+        assert!(out[len..].iter().any(|&x| x > 0.5));
+    }
+
+    #[test]
+    fn test_tone() {
+        for &rate in &[16000, 44100, 48000] {
+            let sig = tone(1000.0, SAMPLES_PER_FRAME * 20);
+
+            let mut out = vec![];
+            let mut r = Resampler::new(rate).unwrap();
+            r.process(&sig[..], &mut out);
+
+            // Skip over the filter delay and startup transient.
+            let skip = 2 * TAPS * rate / SAMPLE_RATE;
+            let delay = (TAPS as f32 - 1.0 / r.interp as f32) / 2.0 / SAMPLE_RATE as f32;
+
+            for (i, &y) in out.iter().enumerate().skip(skip) {
+                let t = i as f32 / rate as f32 - delay;
+                assert!((y - (2.0 * PI * 1000.0 * t).sin()).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_images() {
+        let sig = tone(3000.0, SAMPLES_PER_FRAME * 40);
+
+        for &rate in &[16000, 44100, 48000] {
+            let mut out = vec![];
+            Resampler::new(rate).unwrap().process(&sig[..], &mut out);
+
+            let out = &out[out.len() / 4..];
+
+            assert!((magnitude(out, 3000.0, rate) - 1.0).abs() < 0.01);
+            // The first image of the tone should be strongly attenuated.
+            assert!(magnitude(out, 5000.0, rate) < 1e-3);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! Scanning procedure used in descrambling.
 
-use std::ops::Range;
+use core::ops::Range;
 
 use params::BaseParams;
 use frame::Chunks;
@@ -0,0 +1,258 @@
+//! Destinations for decoded audio.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use consts::SAMPLE_RATE;
+use pcm;
+
+/// Receives decoded audio samples.
+///
+/// Samples are given on the 16-bit PCM scale described in the `pcm` module.
+pub trait Sink {
+    /// Consume the given samples.
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    /// Finalize the sink after the last samples of a stream have been written.
+    fn finish(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+impl Sink for Vec<f32> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.extend_from_slice(samples);
+        Ok(())
+    }
+}
+
+impl<S: Sink> Sink for &mut S {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> { (**self).write(samples) }
+    fn finish(&mut self) -> io::Result<()> { (**self).finish() }
+}
+
+/// Writes samples as raw signed 16-bit little-endian PCM.
+pub struct PcmSink<W: Write>(W);
+
+impl<W: Write> PcmSink<W> {
+    /// Create a new `PcmSink` writing into the given stream.
+    pub fn new(stream: W) -> Self { PcmSink(stream) }
+
+    /// Consume the sink and return the underlying stream.
+    pub fn into_inner(self) -> W { self.0 }
+}
+
+impl<W: Write> Sink for PcmSink<W> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        write_pcm(&mut self.0, samples)
+    }
+
+    fn finish(&mut self) -> io::Result<()> { self.0.flush() }
+}
+
+/// Writes samples as a 16-bit mono WAV file.
+///
+/// The header is written up front with placeholder lengths, which are filled in when the
+/// sink is finished. The header starts at the position of the file when the sink is
+/// created, so the WAV data can follow other content.
+pub struct WavSink<W: Write + Seek> {
+    /// Underlying file.
+    stream: W,
+    /// Position of the start of the header in the file.
+    start: u64,
+    /// Number of samples written so far.
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    /// Create a new `WavSink` at the decoder sample rate, writing into the given file.
+    pub fn new(stream: W) -> io::Result<Self> {
+        Self::with_rate(stream, SAMPLE_RATE)
+    }
+
+    /// Create a new `WavSink` at the given sample rate, writing into the given file.
+    pub fn with_rate(mut stream: W, rate: usize) -> io::Result<Self> {
+        let start = stream.stream_position()?;
+        write_wav_header(&mut stream, rate, None)?;
+
+        Ok(WavSink {
+            stream: stream,
+            start: start,
+            samples: 0,
+        })
+    }
+
+    /// Consume the sink and return the underlying file.
+    pub fn into_inner(self) -> W { self.stream }
+}
+
+impl<W: Write + Seek> Sink for WavSink<W> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        write_pcm(&mut self.stream, samples)?;
+        self.samples = self.samples.saturating_add(samples.len() as u32);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data = self.samples.saturating_mul(2);
+
+        // Patch the RIFF and data chunk lengths.
+        self.stream.seek(SeekFrom::Start(self.start + 4))?;
+        self.stream.write_all(&le32(data.saturating_add(36)))?;
+        self.stream.seek(SeekFrom::Start(self.start + 40))?;
+        self.stream.write_all(&le32(data))?;
+        self.stream.seek(SeekFrom::End(0))?;
+
+        self.stream.flush()
+    }
+}
+
+/// Write a 44-byte header for a 16-bit mono WAV file with the given sample rate and
+/// number of data bytes.
+///
+/// If the data length isn't known, as when streaming to a pipe, the lengths are set to
+/// their maximum values, which most readers treat as "until end of stream."
+pub fn write_wav_header<W: Write>(stream: &mut W, rate: usize, data: Option<u32>)
+    -> io::Result<()>
+{
+    let (riff, data) = match data {
+        Some(d) => (d.saturating_add(36), d),
+        None => (!0, !0),
+    };
+
+    let mut hdr = [0; 44];
+
+    hdr[0..4].copy_from_slice(b"RIFF");
+    hdr[4..8].copy_from_slice(&le32(riff));
+    hdr[8..12].copy_from_slice(b"WAVE");
+    hdr[12..16].copy_from_slice(b"fmt ");
+    // Length of the format chunk.
+    hdr[16..20].copy_from_slice(&le32(16));
+    // Linear PCM format.
+    hdr[20..22].copy_from_slice(&le16(1));
+    // Number of channels.
+    hdr[22..24].copy_from_slice(&le16(1));
+    hdr[24..28].copy_from_slice(&le32(rate as u32));
+    // Byte rate.
+    hdr[28..32].copy_from_slice(&le32(rate as u32 * 2));
+    // Bytes per sample frame.
+    hdr[32..34].copy_from_slice(&le16(2));
+    // Bits per sample.
+    hdr[34..36].copy_from_slice(&le16(16));
+    hdr[36..40].copy_from_slice(b"data");
+    hdr[40..44].copy_from_slice(&le32(data));
+
+    stream.write_all(&hdr[..])
+}
+
+/// Write the given samples as signed 16-bit little-endian PCM.
+fn write_pcm<W: Write>(stream: &mut W, samples: &[f32]) -> io::Result<()> {
+    let mut bytes = [0; 512];
+
+    for chunk in samples.chunks(bytes.len() / 2) {
+        for (b, &s) in bytes.chunks_mut(2).zip(chunk.iter()) {
+            b.copy_from_slice(&le16(pcm::to_i16(s) as u16));
+        }
+
+        stream.write_all(&bytes[..chunk.len() * 2])?;
+    }
+
+    Ok(())
+}
+
+/// Encode the given value as little-endian bytes.
+fn le16(x: u16) -> [u8; 2] {
+    [x as u8, (x >> 8) as u8]
+}
+
+/// Encode the given value as little-endian bytes.
+fn le32(x: u32) -> [u8; 4] {
+    [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_pcm() {
+        let mut s = PcmSink::new(vec![]);
+        s.write(&[0.0, 1.0, -1.0, 40000.0, -40000.0, 258.0]).unwrap();
+        s.finish().unwrap();
+
+        assert_eq!(s.into_inner(), vec![
+            0x00, 0x00,
+            0x01, 0x00,
+            0xff, 0xff,
+            0xff, 0x7f,
+            0x00, 0x80,
+            0x02, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn test_pcm_long() {
+        let samples = (0..1000).map(|x| x as f32).collect::<Vec<f32>>();
+
+        let mut s = PcmSink::new(vec![]);
+        s.write(&samples[..]).unwrap();
+
+        let bytes = s.into_inner();
+        assert_eq!(bytes.len(), 2000);
+
+        for (i, b) in bytes.chunks(2).enumerate() {
+            assert_eq!(b[0] as usize | (b[1] as usize) << 8, i);
+        }
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut s = WavSink::new(Cursor::new(vec![])).unwrap();
+        s.write(&[1.0, 2.0, 3.0]).unwrap();
+        s.write(&[-1.0]).unwrap();
+        s.finish().unwrap();
+
+        let bytes = s.into_inner().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &[44, 0, 0, 0]);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[16..24], &[16, 0, 0, 0, 1, 0, 1, 0]);
+        assert_eq!(&bytes[24..28], &[0x40, 0x1f, 0, 0]);
+        assert_eq!(&bytes[28..32], &[0x80, 0x3e, 0, 0]);
+        assert_eq!(&bytes[32..36], &[2, 0, 16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &[8, 0, 0, 0]);
+        assert_eq!(&bytes[44..], &[1, 0, 2, 0, 3, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_wav_offset() {
+        let mut file = Cursor::new(vec![]);
+        file.write_all(b"prefix").unwrap();
+
+        let mut s = WavSink::new(file).unwrap();
+        s.write(&[1.0, 2.0]).unwrap();
+        s.finish().unwrap();
+
+        let bytes = s.into_inner().into_inner();
+
+        // Data ahead of the header is left alone.
+        assert_eq!(&bytes[..6], b"prefix");
+        assert_eq!(&bytes[6..10], b"RIFF");
+        assert_eq!(&bytes[10..14], &[40, 0, 0, 0]);
+        assert_eq!(&bytes[46..50], &[4, 0, 0, 0]);
+        assert_eq!(&bytes[50..], &[1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let mut bytes = vec![];
+        write_wav_header(&mut bytes, 48000, None).unwrap();
+
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(&bytes[4..8], &[0xff; 4]);
+        assert_eq!(&bytes[24..28], &[0x80, 0xbb, 0, 0]);
+        assert_eq!(&bytes[40..44], &[0xff; 4]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Versioned binary snapshots of decoder state.
+//!
+//! A snapshot holds the history an `ImbeDecoder` carries from one frame to the next, so
+//! a stream can be handed off to a decoder in another process and resumed without the
+//! artifacts of restarting from the initial state.
+//!
+//! The format starts with the 4-byte magic `IMBS` and a 16-bit format version, followed
+//! by each saved value in a fixed order: integers and floats are little-endian, floats
+//! are stored by their IEEE 754 bits, and variable-length sequences are preceded by their
+//! length in a single byte. The version is increased whenever the layout changes, so a
+//! snapshot is never misread by a decoder expecting a different layout.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use arrayvec::{Array, ArrayVec};
+
+/// Magic bytes at the start of every snapshot.
+pub const MAGIC: [u8; 4] = *b"IMBS";
+/// Version of the snapshot format written by this crate.
+pub const VERSION: u16 = 1;
+/// Maximum length in bytes of a snapshot, with comfort noise and statistics enabled.
+pub const MAX_SNAPSHOT_LEN: usize = 6144;
+
+/// Error in writing or reading a snapshot.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SnapshotError {
+    /// The output buffer is too small to hold the snapshot.
+    BufferTooSmall,
+    /// The data doesn't start with the snapshot magic.
+    BadMagic,
+    /// The snapshot was written by an unknown format version.
+    UnsupportedVersion(u16),
+    /// The data ends before the snapshot is complete.
+    Truncated,
+    /// A saved value is out of range.
+    InvalidValue,
+    /// The data continues after the snapshot is complete.
+    TrailingData,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::BufferTooSmall => write!(f, "snapshot buffer too small"),
+            SnapshotError::BadMagic => write!(f, "missing snapshot magic"),
+            SnapshotError::UnsupportedVersion(v) =>
+                write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::Truncated => write!(f, "truncated snapshot"),
+            SnapshotError::InvalidValue => write!(f, "invalid value in snapshot"),
+            SnapshotError::TrailingData => write!(f, "trailing data after snapshot"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for SnapshotError {}
+
+/// State that can be saved in and restored from a snapshot.
+pub trait Persist: Sized {
+    /// Write the state to the given snapshot.
+    fn save(&self, w: &mut Writer);
+
+    /// Read state written by `save` from the given snapshot.
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError>;
+}
+
+/// Writes values into a snapshot buffer.
+///
+/// Writes past the end of the buffer are dropped and reported when the snapshot is
+/// finished, so saving doesn't need to check each write.
+pub struct Writer<'a> {
+    /// Output buffer.
+    buf: &'a mut [u8],
+    /// Number of bytes written, including any that didn't fit.
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    /// Create a new `Writer` that starts a snapshot with the magic and current version
+    /// in the given buffer.
+    pub fn new(buf: &'a mut [u8]) -> Writer<'a> {
+        let mut w = Writer {
+            buf: buf,
+            pos: 0,
+        };
+
+        w.bytes(&MAGIC);
+        w.u16(VERSION);
+        w
+    }
+
+    /// Complete the snapshot, returning its length in bytes.
+    pub fn finish(self) -> Result<usize, SnapshotError> {
+        if self.pos > self.buf.len() {
+            Err(SnapshotError::BufferTooSmall)
+        } else {
+            Ok(self.pos)
+        }
+    }
+
+    /// Write the given raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        let end = self.pos + bytes.len();
+
+        if end <= self.buf.len() {
+            self.buf[self.pos..end].copy_from_slice(bytes);
+        }
+
+        self.pos = end;
+    }
+
+    /// Write the given byte.
+    pub fn u8(&mut self, x: u8) { self.bytes(&[x]) }
+
+    /// Write the given boolean as a byte of 0 or 1.
+    pub fn bool(&mut self, x: bool) { self.u8(x as u8) }
+
+    /// Write the given 16-bit integer.
+    pub fn u16(&mut self, x: u16) { self.bytes(&[x as u8, (x >> 8) as u8]) }
+
+    /// Write the given 32-bit integer.
+    pub fn u32(&mut self, x: u32) {
+        self.bytes(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8])
+    }
+
+    /// Write the given 64-bit integer.
+    pub fn u64(&mut self, x: u64) {
+        self.u32(x as u32);
+        self.u32((x >> 32) as u32);
+    }
+
+    /// Write the given float.
+    pub fn f32(&mut self, x: f32) { self.u32(x.to_bits()) }
+
+    /// Write the given floats without a length.
+    pub fn f32s(&mut self, xs: &[f32]) {
+        for &x in xs {
+            self.f32(x);
+        }
+    }
+
+    /// Write the given floats preceded by their length, which must fit in a byte.
+    pub fn f32_list(&mut self, xs: &[f32]) {
+        assert!(xs.len() < 256);
+
+        self.u8(xs.len() as u8);
+        self.f32s(xs);
+    }
+}
+
+/// Reads values from a snapshot.
+pub struct Reader<'a> {
+    /// Remaining unread bytes.
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Create a new `Reader` over the given snapshot, checking its magic and version.
+    pub fn new(buf: &'a [u8]) -> Result<Reader<'a>, SnapshotError> {
+        let mut r = Reader { buf: buf };
+
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        match r.u16()? {
+            VERSION => Ok(r),
+            v => Err(SnapshotError::UnsupportedVersion(v)),
+        }
+    }
+
+    /// Check that the snapshot has been completely read.
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::TrailingData)
+        }
+    }
+
+    /// Read the given number of raw bytes.
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if len > self.buf.len() {
+            return Err(SnapshotError::Truncated);
+        }
+
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        Ok(bytes)
+    }
+
+    /// Read a byte.
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> { Ok(self.bytes(1)?[0]) }
+
+    /// Read a boolean written as a byte of 0 or 1.
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidValue),
+        }
+    }
+
+    /// Read a 16-bit integer.
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.bytes(2)?;
+        Ok(b[0] as u16 | (b[1] as u16) << 8)
+    }
+
+    /// Read a 32-bit integer.
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.bytes(4)?;
+        Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+
+    /// Read a 64-bit integer.
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let lo = self.u32()? as u64;
+        let hi = self.u32()? as u64;
+
+        Ok(lo | hi << 32)
+    }
+
+    /// Read a float.
+    pub fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    /// Read floats, without a length, into the given buffer.
+    pub fn f32s(&mut self, xs: &mut [f32]) -> Result<(), SnapshotError> {
+        for x in xs.iter_mut() {
+            *x = self.f32()?;
+        }
+
+        Ok(())
+    }
+
+    /// Read floats written by `Writer::f32_list`, which must fit in the returned vector.
+    pub fn f32_list<A>(&mut self) -> Result<ArrayVec<A>, SnapshotError>
+        where A: Array<Item = f32>
+    {
+        let mut xs = ArrayVec::new();
+        let len = self.u8()? as usize;
+
+        if len > xs.capacity() {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        for _ in 0..len {
+            xs.push(self.f32()?);
+        }
+
+        Ok(xs)
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn save(&self, w: &mut Writer) {
+        w.bool(self.is_some());
+
+        if let Some(ref x) = *self {
+            x.save(w);
+        }
+    }
+
+    fn load(r: &mut Reader) -> Result<Option<T>, SnapshotError> {
+        if r.bool()? {
+            Ok(Some(T::load(r)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut buf = [0; 64];
+
+        let len = {
+            let mut w = Writer::new(&mut buf);
+            w.u8(0xab);
+            w.bool(true);
+            w.u16(0x1234);
+            w.u32(0xdeadbeef);
+            w.u64(0x0123456789abcdef);
+            w.f32(-1.5);
+            w.f32_list(&[0.25, 3.0]);
+            w.finish().unwrap()
+        };
+
+        assert_eq!(&buf[..6], b"IMBS\x01\x00");
+        assert_eq!(len, 6 + 1 + 1 + 2 + 4 + 8 + 4 + 1 + 8);
+
+        let mut r = Reader::new(&buf[..len]).unwrap();
+        assert_eq!(r.u8(), Ok(0xab));
+        assert_eq!(r.bool(), Ok(true));
+        assert_eq!(r.u16(), Ok(0x1234));
+        assert_eq!(r.u32(), Ok(0xdeadbeef));
+        assert_eq!(r.u64(), Ok(0x0123456789abcdef));
+        assert_eq!(r.f32(), Ok(-1.5));
+
+        // Lists that don't fit are rejected.
+        let mut s = Reader::new(&buf[..len]).unwrap();
+        s.bytes(20).unwrap();
+        assert_eq!(s.f32_list::<[f32; 1]>().err(), Some(SnapshotError::InvalidValue));
+
+        let xs = r.f32_list::<[f32; 2]>().unwrap();
+        assert_eq!(&xs[..], &[0.25, 3.0]);
+        assert_eq!(r.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut buf = [0; 8];
+
+        {
+            let mut w = Writer::new(&mut buf);
+            w.u32(1);
+            assert_eq!(w.finish(), Err(SnapshotError::BufferTooSmall));
+        }
+
+        assert_eq!(Reader::new(b"IMBX\x01\x00").err(), Some(SnapshotError::BadMagic));
+        assert_eq!(Reader::new(b"IMB").err(), Some(SnapshotError::BadMagic));
+        assert_eq!(Reader::new(b"IMBS\x02\x00").err(),
+                   Some(SnapshotError::UnsupportedVersion(2)));
+
+        let mut r = Reader::new(b"IMBS\x01\x00\x02\x00\x00").unwrap();
+        assert_eq!(r.bool(), Err(SnapshotError::InvalidValue));
+        assert_eq!(r.u32(), Err(SnapshotError::Truncated));
+
+        let r = Reader::new(b"IMBS\x01\x00\x00").unwrap();
+        assert_eq!(r.finish(), Err(SnapshotError::TrailingData));
+    }
+}
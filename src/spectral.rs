@@ -1,13 +1,15 @@
 //! Spectral amplitudes.
 
-use std;
+use core;
 
 use arrayvec::ArrayVec;
 
 use coefs::Coefficients;
 use consts::MAX_HARMONICS;
+use math;
 use params::BaseParams;
 use prev::PrevFrame;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 
 /// Spectral amplitudes M<sub>l</sub>, 1 ≤ l ≤ L, measure the spectral envelope of the
 /// voiced/unvoiced signal spectrum.
@@ -24,7 +26,7 @@ impl Spectrals {
         // Compute (k_l,  δ_l) for the given harmonic l [p35].
         let indexes = |l: u32| {
             let k = scale * l as f32;
-            (k.trunc() as usize, k.fract())
+            (math::trunc(k) as usize, math::fract(k))
         };
 
         // Compute prediction coefficient ρ [p27].
@@ -32,21 +34,21 @@ impl Spectrals {
 
         // Compute the sum term.
         let sum = (1..=params.harmonics).map(|l| indexes(l)).map(|(k, dec)| {
-            (1.0 - dec) * prev.spectrals.get(k).log2() +
-                dec * prev.spectrals.get(k + 1).log2()
+            (1.0 - dec) * math::log2(prev.spectrals.get(k)) +
+                dec * math::log2(prev.spectrals.get(k + 1))
         }).fold(0.0, |s, x| s + x) / params.harmonics as f32;
 
         // Compute M_l for each harmonic l.
         Spectrals((1..=params.harmonics).map(|l| {
             let (k, dec) = indexes(l);
 
-            (
+            math::exp2(
                 coefs.get(l as usize) + pred * (
-                    (1.0 - dec) * prev.spectrals.get(k).log2() +
-                    dec * prev.spectrals.get(k + 1).log2() -
+                    (1.0 - dec) * math::log2(prev.spectrals.get(k)) +
+                    dec * math::log2(prev.spectrals.get(k + 1)) -
                     sum
                 )
-            ).exp2()
+            )
         }).collect())
     }
 
@@ -64,7 +66,7 @@ impl Spectrals {
     }
 }
 
-impl std::ops::Deref for Spectrals {
+impl core::ops::Deref for Spectrals {
     type Target = ArrayVec<[f32; MAX_HARMONICS]>;
     fn deref(&self) -> &Self::Target { &self.0 }
 }
@@ -77,6 +79,21 @@ impl Default for Spectrals {
     }
 }
 
+impl Persist for Spectrals {
+    fn save(&self, w: &mut Writer) { w.f32_list(&self.0[..]) }
+
+    fn load(r: &mut Reader) -> Result<Spectrals, SnapshotError> {
+        let s = Spectrals(r.f32_list()?);
+
+        // Amplitudes past the end are extended from the last one by Eq 79.
+        if s.0.is_empty() {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(s)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
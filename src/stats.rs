@@ -0,0 +1,357 @@
+//! Call-quality statistics collected while decoding.
+//!
+//! A `Stats` is updated by `ImbeDecoder` with how each frame of a call was handled and
+//! the error and energy values computed for it, so metrics can be gathered without
+//! instrumenting the decoding pipeline. Everything is kept in fixed-size storage, so
+//! collection allocates nothing: the ϵ<sub>R</sub> trajectory covers the whole call by
+//! halving its resolution whenever it fills.
+
+use core::fmt;
+
+use arrayvec::ArrayVec;
+
+use decode::DecodeStatus;
+use descramble::Bootstrap;
+use frame::ReceivedFrame;
+use prev::PrevFrame;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
+
+/// Number of bins in the ϵ<sub>T</sub> histogram, which covers the most errors the
+/// Golay and Hamming codes can correct in a frame.
+pub const ERROR_BINS: usize = 16;
+/// Number of bins in the b<sub>0</sub> histogram, one for each valid period.
+pub const PERIOD_BINS: usize = 208;
+/// Maximum number of points kept in the ϵ<sub>R</sub> trajectory.
+pub const TRAJECTORY_POINTS: usize = 128;
+
+/// Running statistics of a decoded call.
+#[derive(Clone)]
+pub struct Stats {
+    /// Number of frames decoded as voice.
+    voice: u32,
+    /// Number of frames replaced with a repeat, for any reason.
+    repeat: u32,
+    /// Number of frames muted because of errors.
+    mute: u32,
+    /// Number of silence frames.
+    silence: u32,
+    /// Number of frames lost entirely.
+    lost: u32,
+    /// Number of frames with each ϵ<sub>T</sub>.
+    errors: [u32; ERROR_BINS],
+    /// Sampled ϵ<sub>R</sub> after each frame.
+    rates: Trajectory,
+    /// Sum of the energy tracker S<sub>E</sub> over voice frames.
+    tracking: f64,
+    /// Number of voice frames with each b<sub>0</sub>.
+    periods: [u32; PERIOD_BINS],
+}
+
+impl Stats {
+    /// Create a new `Stats` with no frames recorded.
+    pub fn new() -> Stats {
+        Stats {
+            voice: 0,
+            repeat: 0,
+            mute: 0,
+            silence: 0,
+            lost: 0,
+            errors: [0; ERROR_BINS],
+            rates: Trajectory::new(),
+            tracking: 0.0,
+            periods: [0; PERIOD_BINS],
+        }
+    }
+
+    /// Record the given frame, handled with the given status, along with the
+    /// parameters saved after decoding it.
+    pub fn record(&mut self, frame: &ReceivedFrame, status: &DecodeStatus,
+                  cur: &PrevFrame)
+    {
+        match *status {
+            DecodeStatus::Voice(_) => {
+                self.voice += 1;
+                self.tracking += cur.energy.tracking as f64;
+
+                if let Bootstrap::Period(p) = Bootstrap::new(&frame.chunks) {
+                    self.periods[p as usize] += 1;
+                }
+            },
+            DecodeStatus::InvalidPeriod | DecodeStatus::Repeat(_) => self.repeat += 1,
+            DecodeStatus::Mute(_) => self.mute += 1,
+            DecodeStatus::Silence => self.silence += 1,
+        }
+
+        if let Some(e) = status.errors() {
+            self.errors[e.total.min(ERROR_BINS - 1)] += 1;
+        }
+
+        self.rates.push(cur.err_rate);
+    }
+
+    /// Record a lost frame, with the given ϵ<sub>R</sub> saved after concealing it.
+    pub fn record_lost(&mut self, err_rate: f32) {
+        self.lost += 1;
+        self.rates.push(err_rate);
+    }
+
+    /// Retrieve the number of frames decoded as voice.
+    pub fn voice(&self) -> u32 { self.voice }
+
+    /// Retrieve the number of frames replaced with a repeat of the previous frame, either
+    /// for an invalid b<sub>0</sub> or for errors.
+    pub fn repeat(&self) -> u32 { self.repeat }
+
+    /// Retrieve the number of frames muted because of errors.
+    pub fn mute(&self) -> u32 { self.mute }
+
+    /// Retrieve the number of silence frames.
+    pub fn silence(&self) -> u32 { self.silence }
+
+    /// Retrieve the number of frames lost entirely and concealed by `decode_lost`.
+    pub fn lost(&self) -> u32 { self.lost }
+
+    /// Retrieve the total number of frames recorded.
+    pub fn frames(&self) -> u32 {
+        self.voice + self.repeat + self.mute + self.silence + self.lost
+    }
+
+    /// Retrieve the histogram of ϵ<sub>T</sub>, indexed by the number of errors, over
+    /// the frames it was computed for.
+    ///
+    /// The last bin also counts any frames with more errors.
+    pub fn errors(&self) -> &[u32] { &self.errors[..] }
+
+    /// Retrieve the ϵ<sub>R</sub> trajectory, sampled every `rate_stride` frames from
+    /// the first.
+    pub fn rates(&self) -> &[f32] { &self.rates.points[..] }
+
+    /// Retrieve the number of frames between points of the ϵ<sub>R</sub> trajectory.
+    pub fn rate_stride(&self) -> u32 { self.rates.stride }
+
+    /// Retrieve the mean of the energy tracker S<sub>E</sub> over voice frames, if any.
+    pub fn mean_tracking(&self) -> Option<f32> {
+        if self.voice == 0 {
+            None
+        } else {
+            Some((self.tracking / self.voice as f64) as f32)
+        }
+    }
+
+    /// Retrieve the histogram of b<sub>0</sub>, indexed by period, over voice frames.
+    pub fn periods(&self) -> &[u32] { &self.periods[..] }
+
+    /// Retrieve a summary of the statistics, which formats as a JSON object.
+    pub fn summary<'a>(&'a self) -> Summary<'a> { Summary(self) }
+}
+
+impl Default for Stats {
+    fn default() -> Stats { Stats::new() }
+}
+
+impl Persist for Stats {
+    fn save(&self, w: &mut Writer) {
+        for &x in &[self.voice, self.repeat, self.mute, self.silence, self.lost] {
+            w.u32(x);
+        }
+
+        for &x in self.errors.iter() {
+            w.u32(x);
+        }
+
+        self.rates.save(w);
+        w.u64(self.tracking.to_bits());
+
+        for &x in self.periods.iter() {
+            w.u32(x);
+        }
+    }
+
+    fn load(r: &mut Reader) -> Result<Stats, SnapshotError> {
+        let mut s = Stats {
+            voice: r.u32()?,
+            repeat: r.u32()?,
+            mute: r.u32()?,
+            silence: r.u32()?,
+            lost: r.u32()?,
+            errors: [0; ERROR_BINS],
+            rates: Trajectory::new(),
+            tracking: 0.0,
+            periods: [0; PERIOD_BINS],
+        };
+
+        for x in s.errors.iter_mut() {
+            *x = r.u32()?;
+        }
+
+        s.rates = Trajectory::load(r)?;
+        s.tracking = f64::from_bits(r.u64()?);
+
+        for x in s.periods.iter_mut() {
+            *x = r.u32()?;
+        }
+
+        Ok(s)
+    }
+}
+
+/// Summary of a call's statistics, which formats as a JSON object for storage or
+/// reporting at the end of a call.
+pub struct Summary<'a>(&'a Stats);
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.0;
+
+        write!(f, "{{\"frames\":{},\"voice\":{},\"repeat\":{},\"mute\":{},\
+                   \"silence\":{},\"lost\":{},",
+               s.frames(), s.voice, s.repeat, s.mute, s.silence, s.lost)?;
+
+        f.write_str("\"errors\":")?;
+        write_list(f, s.errors())?;
+
+        write!(f, ",\"rate_stride\":{},\"rates\":", s.rate_stride())?;
+        write_list(f, s.rates())?;
+
+        match s.mean_tracking() {
+            Some(t) => write!(f, ",\"mean_tracking\":{}", t)?,
+            None => f.write_str(",\"mean_tracking\":null")?,
+        }
+
+        f.write_str(",\"periods\":")?;
+        write_list(f, s.periods())?;
+
+        f.write_str("}")
+    }
+}
+
+/// Write the given values as a JSON array.
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, values: &[T]) -> fmt::Result {
+    f.write_str("[")?;
+
+    for (i, x) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+
+        write!(f, "{}", x)?;
+    }
+
+    f.write_str("]")
+}
+
+/// Values sampled at a fixed stride that halves in resolution whenever it fills.
+#[derive(Clone)]
+struct Trajectory {
+    /// Sampled values, the i'th of which was taken at frame i × `stride`.
+    points: ArrayVec<[f32; TRAJECTORY_POINTS]>,
+    /// Number of frames between points.
+    stride: u32,
+    /// Number of frames pushed.
+    frames: u32,
+}
+
+impl Trajectory {
+    /// Create a new `Trajectory` with no points, sampling every frame.
+    fn new() -> Trajectory {
+        Trajectory {
+            points: ArrayVec::new(),
+            stride: 1,
+            frames: 0,
+        }
+    }
+
+    /// Push the value for the next frame.
+    fn push(&mut self, x: f32) {
+        if self.frames.is_multiple_of(self.stride) {
+            if self.points.is_full() {
+                // Keep every other point, which remain at multiples of the doubled
+                // stride, as does the current frame.
+                self.points = self.points.iter().step_by(2).cloned().collect();
+                self.stride *= 2;
+            }
+
+            self.points.push(x);
+        }
+
+        self.frames += 1;
+    }
+}
+
+impl Persist for Trajectory {
+    fn save(&self, w: &mut Writer) {
+        w.f32_list(&self.points[..]);
+        w.u32(self.stride);
+        w.u32(self.frames);
+    }
+
+    fn load(r: &mut Reader) -> Result<Trajectory, SnapshotError> {
+        let t = Trajectory {
+            points: r.f32_list()?,
+            stride: r.u32()?,
+            frames: r.u32()?,
+        };
+
+        // The stride starts at 1 and only ever doubles.
+        if !t.stride.is_power_of_two() {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        // A point is kept for every multiple of the stride.
+        let points = (t.frames as u64).div_ceil(t.stride as u64);
+
+        if t.points.len() as u64 != points {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trajectory() {
+        let mut t = Trajectory::new();
+
+        for i in 0..TRAJECTORY_POINTS {
+            t.push(i as f32);
+        }
+
+        assert_eq!(t.stride, 1);
+        assert_eq!(t.points.len(), TRAJECTORY_POINTS);
+
+        t.push(TRAJECTORY_POINTS as f32);
+
+        assert_eq!(t.stride, 2);
+        assert_eq!(t.points.len(), TRAJECTORY_POINTS / 2 + 1);
+
+        // Continue to several times the capacity.
+        for i in TRAJECTORY_POINTS + 1..TRAJECTORY_POINTS * 5 {
+            t.push(i as f32);
+        }
+
+        assert_eq!(t.stride, 8);
+
+        for (i, &x) in t.points.iter().enumerate() {
+            assert_eq!(x, (i * 8) as f32);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut s = Stats::new();
+        s.record_lost(0.5);
+        s.record_lost(0.25);
+
+        let json = format!("{}", s.summary());
+
+        assert!(json.starts_with("{\"frames\":2,\"voice\":0,\"repeat\":0,\"mute\":0,\
+                                  \"silence\":0,\"lost\":2,\"errors\":[0,0,"));
+        assert!(json.contains(",\"rate_stride\":1,\"rates\":[0.5,0.25],"));
+        assert!(json.contains(",\"mean_tracking\":null,\"periods\":[0,0,"));
+        assert!(json.ends_with("0]}"));
+    }
+}
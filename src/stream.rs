@@ -0,0 +1,228 @@
+//! Push-style decoding of a stream of IMBE frames into a sink.
+
+use std::io;
+
+use rand::{Rng, XorShiftRng};
+
+use consts::{SAMPLE_RATE, SAMPLES_PER_FRAME};
+use decode::{DecodeStatus, ImbeDecoder};
+use frame::ReceivedFrame;
+use policy::{ErrorPolicy, Standard};
+use resample::{RateError, Resampler};
+use sink::Sink;
+
+/// Decodes a stream of frames and pushes the resulting audio into a `Sink`.
+///
+/// The frame decoder draws random terms from a generator of type `R` and conceals errors
+/// with the policy `P`, as in `ImbeDecoder`.
+pub struct StreamDecoder<S: Sink, R: Rng = XorShiftRng, P: ErrorPolicy = Standard> {
+    /// Underlying frame decoder.
+    decoder: ImbeDecoder<R, P>,
+    /// Destination of decoded audio.
+    sink: S,
+    /// Converts audio to the sink's sample rate, if it differs from the decoder's.
+    resampler: Option<Resampler>,
+    /// Scratch space for resampled audio.
+    resampled: Vec<f32>,
+}
+
+impl<S: Sink> StreamDecoder<S> {
+    /// Create a new `StreamDecoder` writing audio at the decoder sample rate into the
+    /// given sink.
+    pub fn new(sink: S) -> Self {
+        Self::with_resampler(ImbeDecoder::new(), sink, None)
+    }
+
+    /// Create a new `StreamDecoder` writing audio resampled to the given rate in Hz into
+    /// the given sink, or fail if the rate isn't supported by `Resampler`.
+    pub fn with_rate(sink: S, rate: usize) -> Result<Self, RateError> {
+        Self::with_decoder(ImbeDecoder::new(), sink, rate)
+    }
+}
+
+impl<S: Sink, R: Rng, P: ErrorPolicy> StreamDecoder<S, R, P> {
+    /// Create a new `StreamDecoder` using the given frame decoder and writing audio
+    /// resampled to the given rate in Hz into the given sink, or fail if the rate isn't
+    /// supported by `Resampler`.
+    pub fn with_decoder(decoder: ImbeDecoder<R, P>, sink: S, rate: usize)
+        -> Result<Self, RateError>
+    {
+        let resampler = if rate == SAMPLE_RATE {
+            None
+        } else {
+            Some(Resampler::new(rate)?)
+        };
+
+        Ok(Self::with_resampler(decoder, sink, resampler))
+    }
+
+    /// Create a new `StreamDecoder` using the given frame decoder and resampler.
+    fn with_resampler(decoder: ImbeDecoder<R, P>, sink: S, resampler: Option<Resampler>)
+        -> Self
+    {
+        StreamDecoder {
+            decoder: decoder,
+            sink: sink,
+            resampler: resampler,
+            resampled: Vec::new(),
+        }
+    }
+
+    /// Decode the given frame into the sink, returning how the frame was handled.
+    ///
+    /// The frame is decoded as in `ImbeDecoder::try_decode`. If it fails, the error is
+    /// returned with kind `InvalidData`, nothing is written, and the decoder state is
+    /// unchanged, so the frame can be concealed with `push_lost` instead.
+    pub fn push(&mut self, frame: ReceivedFrame) -> io::Result<DecodeStatus> {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+
+        let status = self.decoder.try_decode(frame, &mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.write(&buf[..])?;
+        Ok(status)
+    }
+
+    /// Notify the decoder that a frame in the stream was lost, and write concealment audio
+    /// in its place.
+    pub fn push_lost(&mut self) -> io::Result<()> {
+        let mut buf = [0.0; SAMPLES_PER_FRAME];
+        self.decoder.decode_lost(&mut buf);
+        self.write(&buf[..])
+    }
+
+    /// Write any resampled audio still held back by the filter delay, then finalize the
+    /// sink at the end of the stream and return it.
+    pub fn finish(mut self) -> io::Result<S> {
+        if let Some(ref mut r) = self.resampler {
+            self.resampled.clear();
+            r.drain(&mut self.resampled);
+            self.sink.write(&self.resampled[..])?;
+        }
+
+        self.sink.finish()?;
+        Ok(self.sink)
+    }
+
+    /// Retrieve a reference to the sink.
+    pub fn sink(&self) -> &S { &self.sink }
+
+    /// Retrieve a mutable reference to the sink.
+    pub fn sink_mut(&mut self) -> &mut S { &mut self.sink }
+
+    /// Write the given decoded samples into the sink, resampling if necessary.
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        match self.resampler {
+            Some(ref mut r) => {
+                self.resampled.clear();
+                r.process(samples, &mut self.resampled);
+                self.sink.write(&self.resampled[..])
+            },
+            None => self.sink.write(samples),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use frame::ReceivedFrame;
+    use policy::Strict;
+    use rand::SeedableRng;
+    use resample::DRAIN_LEN;
+    use sink::WavSink;
+
+    fn silence() -> ReceivedFrame {
+        ReceivedFrame::new([0b110110000000, 0, 0, 0, 0, 0, 0, 0], [0; 7])
+    }
+
+    fn voice() -> ReceivedFrame {
+        ReceivedFrame::new([
+            0b001000010010,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b10100110101,
+            0b00101111010,
+            0b01110111011,
+            0b00001000,
+        ], [0; 7])
+    }
+
+    #[test]
+    fn test_vec() {
+        let mut s = StreamDecoder::new(vec![]);
+
+        s.push(silence()).unwrap();
+        assert_eq!(s.sink().len(), 160);
+        assert!(s.sink().iter().all(|&x| x == 0.0));
+
+        s.push(voice()).unwrap();
+        s.push_lost().unwrap();
+        assert_eq!(s.sink().len(), 480);
+        assert!(s.sink()[160..].iter().any(|&x| x != 0.0));
+
+        s.sink_mut().clear();
+        assert_eq!(s.finish().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut s = StreamDecoder::new(vec![]);
+        let mut frame = voice();
+        frame.chunks[0] = 1 << 12;
+
+        let err = s.push(frame).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(s.sink().len(), 0);
+
+        s.push_lost().unwrap();
+        s.push(voice()).unwrap();
+        assert_eq!(s.sink().len(), 320);
+    }
+
+    #[test]
+    fn test_decoder() {
+        let run = || {
+            let seeded = XorShiftRng::from_seed([1, 2, 3, 4]);
+            let decoder = ImbeDecoder::with_rng(seeded).with_policy(Strict);
+            let mut s = StreamDecoder::with_decoder(decoder, vec![], SAMPLE_RATE)
+                .unwrap();
+
+            s.push(voice()).unwrap();
+            s.push_lost().unwrap();
+            s.push(voice()).unwrap();
+            s.finish().unwrap()
+        };
+
+        // Decoders seeded alike produce the same audio.
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut s = StreamDecoder::with_rate(vec![], 48000).unwrap();
+
+        s.push(voice()).unwrap();
+        s.push_lost().unwrap();
+        s.push(silence()).unwrap();
+
+        // Finishing pushes out the samples delayed by the resampler.
+        assert_eq!(s.finish().unwrap().len(), 960 * 3 + 6 * DRAIN_LEN);
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut s = StreamDecoder::new(WavSink::new(Cursor::new(vec![])).unwrap());
+
+        for _ in 0..5 {
+            s.push(voice()).unwrap();
+        }
+
+        let bytes = s.finish().unwrap().into_inner().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 5 * 160 * 2);
+        assert_eq!(&bytes[40..44], &[0x40, 0x06, 0, 0]);
+    }
+}
@@ -80,21 +80,32 @@
 //!
 //! which requires half as many U<sub>w</sub>(m) values and performs no complex
 //! arithmetic.
+//!
+//! ## FFT Synthesis
+//!
+//! Evaluating the above sum directly costs O(N) for each of the two u<sub>w</sub>(n)
+//! terms in every output sample of Eq 126. As an alternative, the entire u<sub>w</sub>(n)
+//! signal for -128 ≤ n < 128 can be computed once per frame with a 256-point inverse
+//! FFT of the half spectrum (zero-padded above m = 127), taking 2 Re[·] / 256 of each
+//! output point. When `IdftMethod::Fft` is selected, `Unvoiced` computes this signal for
+//! both frames up front and uses it for the overlap-add.
 
-use std::f32::consts::PI;
+use core::f32::consts::PI;
 
-use slice_mip::MapInPlace;
-use num::complex::Complex32;
-use num::traits::Zero;
-use iq_osc::IQOsc;
-use rand::distributions::IndependentSample;
-use rand::distributions::normal::Normal;
+use arrayvec::ArrayVec;
 use rand::Rng;
 
+use complex::Complex32;
 use consts::SAMPLES_PER_FRAME;
 use descramble::VoiceDecisions;
+use fft;
+use frame::AudioBuf;
+use kernel::{self, BATCH_LANES};
 use enhance::EnhancedSpectrals;
+use math;
+use normal;
 use params::BaseParams;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 use window;
 
 /// Unvoiced scaling coefficient γ<sub>w</sub> computed from Eq 121.
@@ -106,12 +117,25 @@ const DFT_SIZE: usize = 256;
 const IDFT_SIZE: usize = 256;
 
 /// Number of points in real half of DFT.
-const DFT_HALF: usize = DFT_SIZE / 2;
+pub const DFT_HALF: usize = DFT_SIZE / 2;
 /// Number of points in real half of IDFT.
 const IDFT_HALF: usize = IDFT_SIZE / 2;
 
+/// Method used to evaluate the IDFT u<sub>w</sub>(n) during unvoiced synthesis.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum IdftMethod {
+    /// Evaluate the partial IDFT sum at each point.
+    Direct,
+    /// Look up each point in the signal computed by FFT when synthesis begins.
+    Fft,
+}
+
 /// Constructs unvoiced DFT/IDFT.
-pub struct UnvoicedDft([Complex32; DFT_HALF]);
+#[derive(Clone)]
+pub struct UnvoicedDft(
+    /// Real half of the DFT U<sub>w</sub>(m), 0 ≤ m < 128.
+    [Complex32; DFT_HALF],
+);
 
 impl UnvoicedDft {
     /// Construct a new `UnvoicedDft` from the given frame parameters and noise generator.
@@ -122,8 +146,8 @@ impl UnvoicedDft {
         // DFT values default to 0 according to Eqs 119 and 124.
         let mut dft = [Complex32::default(); DFT_HALF];
 
-        // Create a Gaussian distribution with mean μ = 0 and variance σ^2 = E_w / 2.
-        let gaus = Normal::new(0.0, (window::ENERGY_SYNTHESIS / 2.0).sqrt() as f64);
+        // Use a Gaussian distribution with mean μ = 0 and variance σ^2 = E_w / 2.
+        let std_dev = math::sqrt(window::ENERGY_SYNTHESIS / 2.0);
 
         for (l, &amp) in amps.iter().enumerate() {
             let l = l + 1;
@@ -137,8 +161,7 @@ impl UnvoicedDft {
 
             // Populate the current band with random spectrum.
             for m in lower..upper {
-                dft[m] = Complex32::new(gaus.ind_sample(&mut rng) as f32,
-                                        gaus.ind_sample(&mut rng) as f32);
+                dft[m] = gaussian(&mut rng, std_dev);
             }
 
             // Compute energy of current band according to Eq 120.
@@ -148,15 +171,26 @@ impl UnvoicedDft {
             // Compute power of current band according to Eq 120.
             let power = energy / (upper - lower) as f32;
             // Compute scale for current enhanced spectral amplitude according to Eq 120.
-            let scale = SCALING_COEF * amp / power.sqrt();
+            let scale = SCALING_COEF * amp / math::sqrt(power);
 
             // Scale the band according to Eq 120.
-            (&mut dft[lower..upper]).map_in_place(|&x| scale * x);
+            for x in dft[lower..upper].iter_mut() {
+                *x = scale * *x;
+            }
         }
 
+        UnvoicedDft::from_dft(dft)
+    }
+
+    /// Construct a new `UnvoicedDft` from the given real half of the DFT
+    /// U<sub>w</sub>(m), 0 ≤ m < 128.
+    pub fn from_dft(dft: [Complex32; DFT_HALF]) -> Self {
         UnvoicedDft(dft)
     }
 
+    /// Retrieve the real half of the DFT U<sub>w</sub>(m), 0 ≤ m < 128.
+    pub fn dft(&self) -> &[Complex32] { &self.0[..] }
+
     /// Compute the IDFT u<sub>w</sub>(n) at the given point n.
     pub fn idft(&self, n: isize) -> f32 {
         // The IDFT is zero outside the defined range [p59].
@@ -164,12 +198,8 @@ impl UnvoicedDft {
             return 0.0;
         }
 
-        let mut osc = IQOsc::new(0.0, 2.0 / IDFT_SIZE as f32 * PI * n as f32);
-
-        2.0 / IDFT_SIZE as f32 * self.0.iter().map(|x| {
-            let (sin, cos) = osc.next();
-            x.re * cos - x.im * sin
-        }).fold(0.0, |s, x| s + x)
+        2.0 / IDFT_SIZE as f32 *
+            kernel::phasor_dot(&self.0[..], 2.0 / IDFT_SIZE as f32 * PI * n as f32)
     }
 }
 
@@ -182,6 +212,71 @@ impl Default for UnvoicedDft {
     }
 }
 
+impl Persist for UnvoicedDft {
+    /// Save the DFT, from which the IDFT is computed.
+    fn save(&self, w: &mut Writer) {
+        for x in self.0.iter() {
+            w.f32(x.re);
+            w.f32(x.im);
+        }
+    }
+
+    fn load(r: &mut Reader) -> Result<UnvoicedDft, SnapshotError> {
+        let mut dft = [Complex32::zero(); DFT_HALF];
+
+        for x in dft.iter_mut() {
+            *x = Complex32::new(r.f32()?, r.f32()?);
+        }
+
+        Ok(UnvoicedDft::from_dft(dft))
+    }
+}
+
+/// Draw a DFT value whose real and imaginary parts are independent samples of a Gaussian
+/// distribution with mean 0 and the given standard deviation.
+pub fn gaussian<R: Rng>(rng: &mut R, std_dev: f32) -> Complex32 {
+    let re = normal::standard(rng) * std_dev as f64;
+    let im = normal::standard(rng) * std_dev as f64;
+
+    Complex32::new(re as f32, im as f32)
+}
+
+/// IDFT u<sub>w</sub>(n) computed by FFT over the whole range -128 ≤ n < 128.
+pub struct IdftSignal(
+    /// Signal u<sub>w</sub>(n), with -128 ≤ n < 128 stored at n + 128.
+    [f32; IDFT_SIZE],
+);
+
+impl IdftSignal {
+    /// Compute the IDFT of the given unvoiced spectrum with an FFT.
+    pub fn new(dft: &UnvoicedDft) -> Self {
+        let mut points = [Complex32::zero(); IDFT_SIZE];
+        points[..DFT_HALF].copy_from_slice(&dft.0[..]);
+
+        fft::inverse(&mut points[..]);
+
+        let mut signal = [0.0; IDFT_SIZE];
+
+        for (i, s) in signal.iter_mut().enumerate() {
+            // The IDFT has period 256, so u_w(n) for negative n wraps to the upper half.
+            let n = (i + IDFT_SIZE - IDFT_HALF) % IDFT_SIZE;
+            *s = 2.0 / IDFT_SIZE as f32 * points[n].re;
+        }
+
+        IdftSignal(signal)
+    }
+
+    /// Retrieve the IDFT u<sub>w</sub>(n) at the given point n.
+    pub fn get(&self, n: isize) -> f32 {
+        // The IDFT is zero outside the defined range [p59].
+        if n < -(IDFT_HALF as isize) || n >= IDFT_HALF as isize {
+            return 0.0;
+        }
+
+        self.0[(n + IDFT_HALF as isize) as usize]
+    }
+}
+
 /// Synthesizes unvoiced spectrum signal s<sub>uv</sub>(n).
 pub struct Unvoiced<'a, 'b> {
     /// Unvoiced DFT/IDFT for current frame.
@@ -190,16 +285,46 @@ pub struct Unvoiced<'a, 'b> {
     prev: &'b UnvoicedDft,
     /// Synthesis window w<sub>s</sub>(n) for "weighted overlap add".
     window: window::Window,
+    /// IDFTs of the current and previous frames computed by FFT, if that method is used.
+    signals: Option<(IdftSignal, IdftSignal)>,
 }
 
 impl<'a, 'b> Unvoiced<'a, 'b> {
     /// Create a new `Unvoiced` from the given unvoiced spectrums of the current and
     /// previous frames.
     pub fn new(cur: &'a UnvoicedDft, prev: &'b UnvoicedDft) -> Self {
+        Self::with_method(cur, prev, IdftMethod::Direct)
+    }
+
+    /// Create a new `Unvoiced` from the given unvoiced spectrums of the current and
+    /// previous frames, evaluating their IDFTs with the given method.
+    pub fn with_method(cur: &'a UnvoicedDft, prev: &'b UnvoicedDft, method: IdftMethod)
+        -> Self
+    {
         Unvoiced {
             cur: cur,
             prev: prev,
             window: window::synthesis(),
+            signals: match method {
+                IdftMethod::Direct => None,
+                IdftMethod::Fft => Some((IdftSignal::new(cur), IdftSignal::new(prev))),
+            },
+        }
+    }
+
+    /// Compute the current frame's IDFT u<sub>w</sub>(n) at the given point n.
+    fn cur_idft(&self, n: isize) -> f32 {
+        match self.signals {
+            Some((ref cur, _)) => cur.get(n),
+            None => self.cur.idft(n),
+        }
+    }
+
+    /// Compute the previous frame's IDFT u<sub>w</sub>(n) at the given point n.
+    fn prev_idft(&self, n: isize) -> f32 {
+        match self.signals {
+            Some((_, ref prev)) => prev.get(n),
+            None => self.prev.idft(n),
         }
     }
 
@@ -210,17 +335,95 @@ impl<'a, 'b> Unvoiced<'a, 'b> {
         let n = n as isize;
 
         // Compute numerator in Eq 126.
-        let numer = self.window.get(n) * self.prev.idft(n) +
+        let numer = self.window.get(n) * self.prev_idft(n) +
             self.window.get(n - SAMPLES_PER_FRAME as isize) *
-                self.cur.idft(n - SAMPLES_PER_FRAME as isize);
+                self.cur_idft(n - SAMPLES_PER_FRAME as isize);
 
         // Compute denominator in Eq 126.
-        let denom = self.window.get(n).powi(2) +
-            self.window.get(n - SAMPLES_PER_FRAME as isize).powi(2);
+        let denom = math::powi(self.window.get(n), 2) +
+            math::powi(self.window.get(n - SAMPLES_PER_FRAME as isize), 2);
 
         // Compute Eq 126.
         numer / denom
     }
+
+    /// Add the unvoiced signal samples s<sub>uv</sub>(n), 0 ≤ n < 160, of each of the
+    /// given frames to the corresponding buffer.
+    ///
+    /// The results are identical to adding `get(n)` for each sample of each frame, but
+    /// the IDFTs evaluated directly are computed for up to `BATCH_LANES` frames at a
+    /// time with `kernel::phasor_dot_batch`, and the overlap-add runs across frames.
+    pub fn add_batch(unvoiced: &[Unvoiced], out: &mut [&mut AudioBuf]) {
+        assert!(unvoiced.len() == out.len() && unvoiced.len() <= BATCH_LANES);
+
+        let mut prev = [[0.0; BATCH_LANES]; SAMPLES_PER_FRAME];
+        let mut cur = [[0.0; BATCH_LANES]; SAMPLES_PER_FRAME];
+
+        idft_batch(unvoiced.iter().map(|u| (u.prev, u.signals.as_ref().map(|s| &s.1))),
+                   0, &mut prev[..]);
+        idft_batch(unvoiced.iter().map(|u| (u.cur, u.signals.as_ref().map(|s| &s.0))),
+                   -(SAMPLES_PER_FRAME as isize), &mut cur[..]);
+
+        // Every frame shares the window.
+        let window = window::synthesis();
+
+        for (n, (p, c)) in prev.iter().zip(cur.iter()).enumerate() {
+            let n = n as isize;
+            let wp = window.get(n);
+            let wc = window.get(n - SAMPLES_PER_FRAME as isize);
+
+            // Compute Eq 126 for each frame.
+            let denom = math::powi(wp, 2) + math::powi(wc, 2);
+
+            for (s, out) in out.iter_mut().enumerate() {
+                out[n as usize] += (wp * p[s] + wc * c[s]) / denom;
+            }
+        }
+    }
+}
+
+/// Compute the IDFT u<sub>w</sub>(n) at start ≤ n < start + N of each of the given frames
+/// into the corresponding column of the given N rows.
+///
+/// Frames with an `IdftSignal` read from it, and the rest are evaluated together.
+fn idft_batch<'c, I>(frames: I, start: isize, out: &mut [[f32; BATCH_LANES]])
+    where I: Iterator<Item = (&'c UnvoicedDft, Option<&'c IdftSignal>)>
+{
+    let frames = frames.collect::<ArrayVec<[_; BATCH_LANES]>>();
+
+    let mut re = [[0.0; BATCH_LANES]; DFT_HALF];
+    let mut im = [[0.0; BATCH_LANES]; DFT_HALF];
+
+    for (s, &(dft, _)) in frames.iter().enumerate().filter(|&(_, f)| f.1.is_none()) {
+        for (m, x) in dft.0.iter().enumerate() {
+            re[m][s] = x.re;
+            im[m][s] = x.im;
+        }
+    }
+
+    let direct = frames.iter().any(|f| f.1.is_none());
+
+    for (i, row) in out.iter_mut().enumerate() {
+        let n = start + i as isize;
+
+        // The IDFT is zero outside the defined range [p59].
+        if n < -(IDFT_HALF as isize) || n >= IDFT_HALF as isize {
+            continue;
+        }
+
+        let sums = if direct {
+            kernel::phasor_dot_batch(&re[..], &im[..], 2.0 / IDFT_SIZE as f32 * PI * n as f32)
+        } else {
+            [0.0; BATCH_LANES]
+        };
+
+        for (s, &(_, signal)) in frames.iter().enumerate() {
+            row[s] = match signal {
+                Some(signal) => signal.get(n),
+                None => 2.0 / IDFT_SIZE as f32 * sums[s],
+            };
+        }
+    }
 }
 
 /// Determine the lower and upper band edges (a<sub>l</sub>, b<sub>l</sub>) for the given
@@ -230,9 +433,9 @@ fn edges(l: usize, params: &BaseParams) -> (usize, usize) {
 
     (
         // Compute Eq 122.
-        (common * (l as f32 - 0.5)).ceil() as usize,
+        math::ceil(common * (l as f32 - 0.5)) as usize,
         // Compute Eq 123.
-        (common * (l as f32 + 0.5)).ceil() as usize,
+        math::ceil(common * (l as f32 + 0.5)) as usize,
     )
 }
 
@@ -241,8 +444,8 @@ mod test {
     use super::*;
     use descramble::{VoiceDecisions, Bootstrap};
     use params::BaseParams;
-    use num::complex::Complex32;
-    use rand::XorShiftRng;
+    use complex::Complex32;
+    use rand::{SeedableRng, XorShiftRng};
 
     #[test]
     fn test_edges() {
@@ -956,4 +1159,98 @@ mod test {
         assert!((uv.get(158) - 11.79304219252217400537574576446787).abs() < 1e-3);
         assert!((uv.get(159) - -31.93931117908418571005313424393535).abs() < 1e-3);
     }
+
+    #[test]
+    fn test_fft() {
+        let p = BaseParams::new(42);
+        let voice = VoiceDecisions::new(0b000110, &p);
+
+        let mut amps = EnhancedSpectrals::default();
+
+        for l in 0..p.harmonics {
+            amps.push(1.0 + (l * 7 % 11) as f32);
+        }
+
+        let rng = |seed| XorShiftRng::from_seed([seed, 2, 3, 4]);
+        let cur = UnvoicedDft::new(&p, &voice, &amps, rng(1));
+        let prev = UnvoicedDft::new(&p, &voice, &amps, rng(5));
+
+        let signal = IdftSignal::new(&cur);
+
+        for n in -130..130 {
+            assert!((signal.get(n) - cur.idft(n)).abs() < 5e-3);
+        }
+
+        assert_eq!(signal.get(-129), 0.0);
+        assert_eq!(signal.get(128), 0.0);
+        assert!((-128..128).any(|n| signal.get(n) != 0.0));
+
+        let direct = Unvoiced::new(&cur, &prev);
+        let fft = Unvoiced::with_method(&cur, &prev, IdftMethod::Fft);
+
+        for n in 0..SAMPLES_PER_FRAME {
+            assert!((direct.get(n) - fft.get(n)).abs() < 5e-3);
+        }
+
+        let zero = UnvoicedDft::default();
+        let signal = IdftSignal::new(&zero);
+        assert!((-128..128).all(|n| signal.get(n) == 0.0));
+    }
+
+    #[test]
+    fn test_batch() {
+        let p = BaseParams::new(42);
+        let voice = VoiceDecisions::new(0b001101, &p);
+
+        let mut amps = EnhancedSpectrals::default();
+
+        for l in 0..p.harmonics {
+            amps.push(1.0 + (l * 5 % 13) as f32);
+        }
+
+        let rng = |seed| XorShiftRng::from_seed([seed, 7, 3, 4]);
+        let dfts = (0..BATCH_LANES as u32 + 1)
+            .map(|i| UnvoicedDft::new(&p, &voice, &amps, rng(i + 1)))
+            .collect::<Vec<_>>();
+
+        for count in 0..=BATCH_LANES {
+            let unvoiced = (0..count).map(|i| {
+                let method = if i % 3 == 1 { IdftMethod::Fft } else { IdftMethod::Direct };
+                Unvoiced::with_method(&dfts[i + 1], &dfts[i], method)
+            }).collect::<Vec<_>>();
+
+            let mut bufs = vec![[1.0; SAMPLES_PER_FRAME]; count];
+
+            {
+                let mut out = bufs.iter_mut().collect::<Vec<_>>();
+                Unvoiced::add_batch(&unvoiced[..], &mut out[..]);
+            }
+
+            for (uv, buf) in unvoiced.iter().zip(bufs.iter()) {
+                for (n, &x) in buf.iter().enumerate() {
+                    assert_eq!(x, 1.0 + uv.get(n));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gaussian() {
+        let mut rng = XorShiftRng::new_unseeded();
+        let points = (0..20000).map(|_| gaussian(&mut rng, 3.0)).collect::<Vec<_>>();
+
+        for part in &[|x: &Complex32| x.re, |x: &Complex32| x.im] {
+            let mean = points.iter().map(part).fold(0.0, |s, x| s + x) /
+                points.len() as f32;
+            let var = points.iter().map(part).fold(0.0, |s, x| s + (x - mean).powi(2)) /
+                points.len() as f32;
+
+            assert!(mean.abs() < 0.1);
+            assert!((var - 9.0).abs() < 0.3);
+        }
+
+        // The parts are uncorrelated.
+        let cov = points.iter().fold(0.0, |s, x| s + x.re * x.im) / points.len() as f32;
+        assert!(cov.abs() < 0.3);
+    }
 }
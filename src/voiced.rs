@@ -1,20 +1,44 @@
 //! Voiced spectrum synthesis.
 
-use std::cmp::max;
-use std::f32::consts::PI;
+use core::cmp::{max, min};
+use core::f32::consts::PI;
 
-use collect_slice::CollectSlice;
-use slice_mip::MapInPlace;
+use arrayvec::ArrayVec;
 use rand::Rng;
 
 use consts::{SAMPLES_PER_FRAME, MAX_HARMONICS};
 use descramble::VoiceDecisions;
 use enhance::EnhancedSpectrals;
+use frame::AudioBuf;
+use kernel::{OscBank, OscBatch, BATCH_LANES};
+use math;
 use params::BaseParams;
 use prev::PrevFrame;
+use snapshot::{Persist, Reader, SnapshotError, Writer};
 use window;
 
+/// Number of samples between resets of the voiced synthesis oscillators.
+pub const RESYNC: usize = 40;
+/// Highest harmonic that can be synthesized by phase interpolation (Eq 134).
+const INTERP_HARMONICS: usize = 7;
+/// Maximum change in fundamental frequency, relative to the current frame, for which
+/// harmonics are synthesized by phase interpolation.
+const INTERP_CHANGE: f32 = 0.1;
+
+/// Method used to synthesize harmonics that are voiced in both the previous and current
+/// frames.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VoicedMethod {
+    /// Overlap-add the windowed sinusoids of each frame (Eq 133).
+    OverlapAdd,
+    /// Interpolate the amplitude linearly and the phase quadratically across the frame
+    /// (Eq 134) for the low harmonics of frames with a steady fundamental frequency, and
+    /// overlap-add the rest.
+    Interpolate,
+}
+
 /// Computes the base phase offsets Ψ<sub>l</sub>.
+#[derive(Clone)]
 pub struct PhaseBase([f32; MAX_HARMONICS]);
 
 impl PhaseBase {
@@ -27,9 +51,9 @@ impl PhaseBase {
             SAMPLES_PER_FRAME as f32 / 2.0;
 
         // Compute Eq 139.
-        (1..=MAX_HARMONICS).map(|l| {
-            prev.phase_base.get(l) + scale * l as f32
-        }).collect_slice_checked(&mut base[..]);
+        for (l, b) in (1..=MAX_HARMONICS).zip(base.iter_mut()) {
+            *b = prev.phase_base.get(l) + scale * l as f32;
+        }
 
         PhaseBase(base)
     }
@@ -46,7 +70,18 @@ impl Default for PhaseBase {
     }
 }
 
+impl Persist for PhaseBase {
+    fn save(&self, w: &mut Writer) { w.f32s(&self.0[..]) }
+
+    fn load(r: &mut Reader) -> Result<PhaseBase, SnapshotError> {
+        let mut base = PhaseBase::default();
+        r.f32s(&mut base.0[..])?;
+        Ok(base)
+    }
+}
+
 /// Computes the random phase terms Φ<sub>l</sub>.
+#[derive(Clone)]
 pub struct Phase([f32; MAX_HARMONICS]);
 
 impl Phase {
@@ -68,10 +103,10 @@ impl Phase {
         let scale = voice.unvoiced_count() as f32 / params.harmonics as f32;
 
         // Modify Ψ_l from start + 1 ≤ l ≤ stop. Since i = l - 1, start ≤ i ≤ stop - 1.
-        (&mut phase[start..stop]).map_in_place(|&x| {
+        for x in phase[start..stop].iter_mut() {
             // Compute Eq 140.
-            x + scale * noise.gen_range(-PI, PI)
-        });
+            *x += scale * noise.gen_range(-PI, PI);
+        }
 
         Phase(phase)
     }
@@ -88,6 +123,16 @@ impl Default for Phase {
     }
 }
 
+impl Persist for Phase {
+    fn save(&self, w: &mut Writer) { w.f32s(&self.0[..]) }
+
+    fn load(r: &mut Reader) -> Result<Phase, SnapshotError> {
+        let mut phase = Phase::default();
+        r.f32s(&mut phase.0[..])?;
+        Ok(phase)
+    }
+}
+
 /// Synthesizes voiced spectrum signal s<sub>v</sub>(n).
 pub struct Voiced<'a, 'b, 'c, 'd> {
     prev: &'a PrevFrame,
@@ -100,12 +145,25 @@ pub struct Voiced<'a, 'b, 'c, 'd> {
     fundamental: f32,
     /// Number of harmonics that make up each signal sample.
     end: usize,
+    /// Method used for harmonics voiced in both frames.
+    method: VoicedMethod,
 }
 
 impl<'a, 'b, 'c, 'd> Voiced<'a, 'b, 'c, 'd> {
     pub fn new(params: &BaseParams, prev: &'a PrevFrame, phase: &'b Phase,
                amps: &'c EnhancedSpectrals, voice: &'d VoiceDecisions)
         -> Self
+    {
+        Self::with_options(params, prev, phase, amps, voice, VoicedMethod::OverlapAdd,
+                           MAX_HARMONICS)
+    }
+
+    /// Create a new `Voiced` that synthesizes harmonics voiced in both frames with the
+    /// given method, and synthesizes at most the given number of harmonics of each frame.
+    pub fn with_options(params: &BaseParams, prev: &'a PrevFrame, phase: &'b Phase,
+                        amps: &'c EnhancedSpectrals, voice: &'d VoiceDecisions,
+                        method: VoicedMethod, limit: usize)
+        -> Self
     {
         Voiced {
             prev: prev,
@@ -115,7 +173,8 @@ impl<'a, 'b, 'c, 'd> Voiced<'a, 'b, 'c, 'd> {
             window: window::synthesis(),
             fundamental: params.fundamental,
             // Compute the sum bound in Eq 127.
-            end: max(params.harmonics, prev.params.harmonics) as usize,
+            end: min(max(params.harmonics, prev.params.harmonics) as usize, limit),
+            method: method,
         }
     }
 
@@ -129,28 +188,63 @@ impl<'a, 'b, 'c, 'd> Voiced<'a, 'b, 'c, 'd> {
             (false, true) => self.sig_prev(l, n),
             // Use Eq 132.
             (true, false) => self.sig_cur(l, n),
-            // Use Eq 133. The Eq 134 form for voiced/voiced frames isn't used due to its
-            // complexity and lack of rationale.
+            // Use Eq 134.
+            (true, true) if self.interpolates(l) => self.sig_interp(l, n),
+            // Use Eq 133.
             (true, true) => self.sig_prev(l, n) + self.sig_cur(l, n)
         }
     }
 
+    /// Check if the given harmonic is synthesized by phase interpolation.
+    fn interpolates(&self, l: usize) -> bool {
+        let change = self.fundamental - self.prev.params.fundamental;
+
+        self.method == VoicedMethod::Interpolate && l <= INTERP_HARMONICS &&
+            self.voice.is_voiced(l) && self.prev.voice.is_voiced(l) &&
+            change.abs() < INTERP_CHANGE * self.fundamental
+    }
+
+    /// Compute s<sub>v,l</sub>(n) for a harmonic voiced in both frames by interpolating
+    /// between the previous and current frame parameters.
+    fn sig_interp(&self, l: usize, n: isize) -> f32 {
+        let size = SAMPLES_PER_FRAME as f32;
+        let (prev, cur) = (self.prev.params.fundamental, self.fundamental);
+        let (k, n) = (l as f32, n as f32);
+
+        // Compute Eq 137.
+        let dphase = self.phase.get(l) - self.prev.phase.get(l) -
+            (prev + cur) * k * size / 2.0;
+        // Compute Eq 138.
+        let dfreq = (dphase - 2.0 * PI * math::floor((dphase + PI) / (2.0 * PI))) / size;
+
+        // Compute Eq 136.
+        let theta = self.prev.phase.get(l) + (prev * k + dfreq) * n +
+            (cur - prev) * k * n * n / (2.0 * size);
+        // Compute Eq 135.
+        let amp = self.prev.enhanced.get(l) +
+            n / size * (self.amps.get(l) - self.prev.enhanced.get(l));
+
+        // Compute Eq 134.
+        amp * math::cos(theta)
+    }
+
     /// Compute s<sub>v,l</sub>(n) for a voiced current frame and unvoiced previous frame.
     fn sig_cur(&self, l: usize, n: isize) -> f32 {
         // Compute Eq 132.
-        self.window.get(n - SAMPLES_PER_FRAME as isize) * self.amps.get(l) * (
-            self.fundamental * (n - SAMPLES_PER_FRAME as isize) as f32 * l as f32 +
-                self.phase.get(l)
-        ).cos()
+        let n = n - SAMPLES_PER_FRAME as isize;
+
+        self.window.get(n) * self.amps.get(l) * math::cos(
+            self.fundamental * n as f32 * l as f32 + self.phase.get(l)
+        )
     }
 
     /// Compute s<sub>v,l</sub>(n) for an unvoiced current frame and voiced previous frame.
     fn sig_prev(&self, l: usize, n: isize) -> f32 {
         // Compute Eq 131.
-        self.window.get(n) * self.prev.enhanced.get(l) * (
+        self.window.get(n) * self.prev.enhanced.get(l) * math::cos(
             self.prev.params.fundamental * n as f32 * l as f32 +
                 self.prev.phase.get(l)
-        ).cos()
+        )
     }
 
     /// Compute the voiced signal sample s<sub>v</sub>(n) for the given sample n, 0 ≤ n <
@@ -163,6 +257,174 @@ impl<'a, 'b, 'c, 'd> Voiced<'a, 'b, 'c, 'd> {
             .map(|l| self.get_pair(l, n as isize))
             .fold(0.0, |s, x| s + x)
     }
+
+    /// Compute the voiced signal samples s<sub>v</sub>(n) for start ≤ n < start + N into
+    /// the given buffer of N samples.
+    ///
+    /// This is equivalent to calling `get` for each sample, but the sinusoids of each
+    /// frame's harmonics are generated across the block with a bank of quadrature
+    /// oscillators rather than a cosine evaluation at every sample. Blocks that start on
+    /// a multiple of `RESYNC` produce identical samples however the frame is split.
+    pub fn fill(&self, start: usize, out: &mut [f32]) {
+        debug_assert!(start + out.len() <= SAMPLES_PER_FRAME);
+
+        let (mut prev, mut cur) = self.banks();
+        let mut pos = 0;
+
+        while pos < out.len() {
+            let n = start + pos;
+            // Reset the oscillator phases at fixed samples so roundoff doesn't
+            // accumulate and results don't depend on how the frame is split.
+            let len = min(RESYNC - n % RESYNC, out.len() - pos);
+
+            self.fill_block(n, &mut prev, &mut cur, &mut out[pos..pos + len]);
+
+            pos += len;
+        }
+    }
+
+    /// Compute the voiced signal samples s<sub>v</sub>(n), 0 ≤ n < 160, of each of the
+    /// given frames into the corresponding buffer.
+    ///
+    /// The results are identical to calling `fill` over the whole frame for each, but the
+    /// oscillators of up to `BATCH_LANES` frames are laid out together in a
+    /// `kernel::OscBatch` and stepped with one frame in each vector lane.
+    pub fn fill_batch(voiced: &[Voiced], out: &mut [&mut AudioBuf]) {
+        assert!(voiced.len() == out.len() && voiced.len() <= BATCH_LANES);
+
+        let (mut prev, mut cur) = {
+            let banks = voiced.iter().map(|v| v.banks())
+                .collect::<ArrayVec<[_; BATCH_LANES]>>();
+            let prev = banks.iter().map(|b| &b.0).collect::<ArrayVec<[_; BATCH_LANES]>>();
+            let cur = banks.iter().map(|b| &b.1).collect::<ArrayVec<[_; BATCH_LANES]>>();
+
+            (OscBatch::new(&prev[..]), OscBatch::new(&cur[..]))
+        };
+
+        // Every frame shares the window, so the same terms are skipped in each.
+        let window = window::synthesis();
+
+        for n in (0..SAMPLES_PER_FRAME).step_by(RESYNC) {
+            let prev_start = n as isize;
+            let cur_start = n as isize - SAMPLES_PER_FRAME as isize;
+
+            let mut prev_sig = [[0.0; BATCH_LANES]; RESYNC];
+            let mut cur_sig = [[0.0; BATCH_LANES]; RESYNC];
+
+            if overlaps(&window, prev_start, RESYNC) {
+                prev.seek(prev_start);
+                prev.accumulate(&mut prev_sig[..]);
+            }
+
+            if overlaps(&window, cur_start, RESYNC) {
+                cur.seek(cur_start);
+                cur.accumulate(&mut cur_sig[..]);
+            }
+
+            for (i, (p, c)) in prev_sig.iter().zip(cur_sig.iter()).enumerate() {
+                let t = i as isize;
+                let (wp, wc) = (window.get(prev_start + t), window.get(cur_start + t));
+
+                for (s, out) in out.iter_mut().enumerate() {
+                    out[n + i] = 2.0 * (wp * p[s] + wc * c[s]);
+                }
+            }
+
+            for (v, out) in voiced.iter().zip(out.iter_mut()) {
+                v.add_interp(n, &mut out[n..n + RESYNC]);
+            }
+        }
+    }
+
+    /// Create the oscillators for the previous and current frame terms.
+    fn banks(&self) -> (OscBank, OscBank) {
+        let mut prev = OscBank::new();
+        let mut cur = OscBank::new();
+
+        for l in 1..=self.end {
+            // These are added separately by `combine`.
+            if self.interpolates(l) {
+                continue;
+            }
+
+            // The previous frame term appears in Eqs 131 and 133 and the current frame
+            // term in Eqs 132 and 133, so Eq 130 contributes nothing.
+            if self.prev.voice.is_voiced(l) {
+                prev.push(self.prev.enhanced.get(l),
+                          self.prev.params.fundamental * l as f32,
+                          self.prev.phase.get(l));
+            }
+
+            if self.voice.is_voiced(l) {
+                cur.push(self.amps.get(l), self.fundamental * l as f32,
+                         self.phase.get(l));
+            }
+        }
+
+        (prev, cur)
+    }
+
+    /// Compute the voiced samples starting at sample n into the given block, using the
+    /// given oscillators for the previous and current frame terms.
+    fn fill_block(&self, n: usize, prev: &mut OscBank, cur: &mut OscBank,
+                  out: &mut [f32])
+    {
+        let prev_start = n as isize;
+        let cur_start = n as isize - SAMPLES_PER_FRAME as isize;
+
+        let mut prev_sig = [0.0; RESYNC];
+        let mut cur_sig = [0.0; RESYNC];
+
+        // Only evaluate terms where their window overlaps the block.
+        if overlaps(&self.window, prev_start, out.len()) {
+            prev.seek(prev_start);
+            prev.accumulate(&mut prev_sig[..out.len()]);
+        }
+
+        if overlaps(&self.window, cur_start, out.len()) {
+            cur.seek(cur_start);
+            cur.accumulate(&mut cur_sig[..out.len()]);
+        }
+
+        self.combine(n, &prev_sig[..], &cur_sig[..], out);
+    }
+
+    /// Combine the given summed oscillator outputs of the previous and current frame
+    /// terms into the voiced samples starting at sample n in the given block.
+    fn combine(&self, n: usize, prev_sig: &[f32], cur_sig: &[f32], out: &mut [f32]) {
+        let prev_start = n as isize;
+        let cur_start = n as isize - SAMPLES_PER_FRAME as isize;
+
+        for (i, x) in out.iter_mut().enumerate() {
+            let t = i as isize;
+
+            // Compute Eq 127 with the window factored out of each frame's terms.
+            *x = 2.0 * (self.window.get(prev_start + t) * prev_sig[i] +
+                        self.window.get(cur_start + t) * cur_sig[i]);
+        }
+
+        self.add_interp(n, out);
+    }
+
+    /// Add the interpolated harmonics, which are left out of the oscillators, to the
+    /// voiced samples starting at sample n in the given block.
+    fn add_interp(&self, n: usize, out: &mut [f32]) {
+        if self.method == VoicedMethod::OverlapAdd {
+            return;
+        }
+
+        for l in (1..=min(self.end, INTERP_HARMONICS)).filter(|&l| self.interpolates(l)) {
+            for (i, x) in out.iter_mut().enumerate() {
+                *x += 2.0 * self.sig_interp(l, (n + i) as isize);
+            }
+        }
+    }
+}
+
+/// Check if the given synthesis window is nonzero anywhere in the given number of samples
+/// starting at the given t.
+fn overlaps(window: &window::Window, start: isize, len: usize) -> bool {
+    (start..start + len as isize).any(|t| window.get(t) != 0.0)
 }
 
 #[cfg(test)]
@@ -348,4 +610,192 @@ mod test {
         assert!((p.get(55) - 1240.93191507999995337740983814001083).abs() < 1e-3);
         assert!((p.get(56) - 1263.49431353599993599345907568931580).abs() < 1e-3);
     }
+
+    /// Compute s<sub>v,l</sub>(n) by Eqs 134 through 138 in double precision from the
+    /// given previous and current fundamental frequency, amplitude, and phase.
+    fn reference_interp(l: usize, n: usize, w: (f64, f64), amp: (f64, f64),
+                        phase: (f64, f64))
+        -> f64
+    {
+        use std::f64::consts::PI;
+
+        let (l, n, size) = (l as f64, n as f64, SAMPLES_PER_FRAME as f64);
+
+        let dphase = phase.1 - phase.0 - (w.0 + w.1) * l * size / 2.0;
+        let dfreq = (dphase - 2.0 * PI * ((dphase + PI) / (2.0 * PI)).floor()) / size;
+        let theta = phase.0 + (w.0 * l + dfreq) * n +
+            (w.1 - w.0) * l * n * n / (2.0 * size);
+        let a = amp.0 + n / size * (amp.1 - amp.0);
+
+        a * theta.cos()
+    }
+
+    /// Create previous and current frames with the given periods, where the first
+    /// `voiced` harmonics of each are voiced.
+    fn steady(prev_period: u8, period: u8, voiced: usize)
+        -> (BaseParams, PrevFrame, Phase, EnhancedSpectrals, VoiceDecisions)
+    {
+        let params = BaseParams::new(period);
+        let mut voice = VoiceDecisions::new(0, &params);
+
+        let mut prev = PrevFrame::default();
+        prev.params = BaseParams::new(prev_period);
+        prev.voice = VoiceDecisions::new(0, &prev.params);
+
+        for l in 1..=voiced {
+            voice.force_voiced(l);
+            prev.voice.force_voiced(l);
+        }
+
+        for l in 1..=prev.params.harmonics as usize {
+            prev.enhanced.push(100.0 + 10.0 * l as f32);
+            prev.phase.0[l - 1] = 3.7 * l as f32;
+        }
+
+        let mut amps = EnhancedSpectrals::default();
+        let mut phase = Phase::default();
+
+        for l in 1..=params.harmonics as usize {
+            amps.push(400.0 - 20.0 * l as f32);
+            phase.0[l - 1] = 2.3 * l as f32 + 5.0;
+        }
+
+        (params, prev, phase, amps, voice)
+    }
+
+    #[test]
+    fn test_interp() {
+        // Harmonics 1 through 6 are voiced in both frames, and the pitch changes by 2%.
+        let (params, prev, phase, amps, voice) = steady(62, 60, 6);
+
+        let v = Voiced::with_options(&params, &prev, &phase, &amps, &voice,
+                                     VoicedMethod::Interpolate, MAX_HARMONICS);
+
+        let mut full = [0.0; SAMPLES_PER_FRAME];
+        v.fill(0, &mut full[..]);
+
+        for (n, &x) in full.iter().enumerate() {
+            let expected = 2.0 * (1..=6).map(|l| reference_interp(
+                l, n,
+                (prev.params.fundamental as f64, params.fundamental as f64),
+                (prev.enhanced.get(l) as f64, amps.get(l) as f64),
+                (prev.phase.get(l) as f64, phase.get(l) as f64),
+            )).fold(0.0, |s, x| s + x);
+
+            assert!((v.get(n) as f64 - expected).abs() < 0.05);
+            assert!((x as f64 - expected).abs() < 0.05);
+        }
+
+        // The phase at the end of the frame meets the current frame's phase.
+        for l in 1..=6 {
+            let end = v.sig_interp(l, SAMPLES_PER_FRAME as isize);
+            assert!((end - amps.get(l) * phase.get(l).cos()).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_interp_fill() {
+        // Harmonics 8 and 9 are voiced in both frames but overlap-added.
+        let (params, prev, phase, amps, voice) = steady(62, 60, 9);
+
+        let v = Voiced::with_options(&params, &prev, &phase, &amps, &voice,
+                                     VoicedMethod::Interpolate, MAX_HARMONICS);
+        let overlap = Voiced::new(&params, &prev, &phase, &amps, &voice);
+
+        let mut full = [0.0; SAMPLES_PER_FRAME];
+        v.fill(0, &mut full[..]);
+
+        let mut parts = [0.0; SAMPLES_PER_FRAME];
+
+        for (i, chunk) in parts.chunks_mut(70).enumerate() {
+            v.fill(i * 70, chunk);
+        }
+
+        for n in 0..SAMPLES_PER_FRAME {
+            let eq133 = (8..=9).map(|l| v.sig_prev(l, n as isize) +
+                                         v.sig_cur(l, n as isize));
+            let expected = 2.0 * (1..=7).map(|l| v.sig_interp(l, n as isize))
+                .chain(eq133)
+                .fold(0.0, |s, x| s + x);
+
+            assert!((v.get(n) - expected).abs() < 0.05);
+            assert!((full[n] - expected).abs() < 0.05);
+            assert!((parts[n] - expected).abs() < 0.05);
+        }
+
+        assert!((0..SAMPLES_PER_FRAME).any(|n| (v.get(n) - overlap.get(n)).abs() > 1.0));
+    }
+
+    #[test]
+    fn test_interp_pitch_change() {
+        // The pitch changes by over 10%, so every harmonic is overlap-added.
+        let (params, prev, phase, amps, voice) = steady(80, 60, 9);
+
+        let v = Voiced::with_options(&params, &prev, &phase, &amps, &voice,
+                                     VoicedMethod::Interpolate, MAX_HARMONICS);
+        let overlap = Voiced::new(&params, &prev, &phase, &amps, &voice);
+
+        let mut a = [0.0; SAMPLES_PER_FRAME];
+        let mut b = [0.0; SAMPLES_PER_FRAME];
+
+        v.fill(0, &mut a[..]);
+        overlap.fill(0, &mut b[..]);
+
+        assert!(a.iter().any(|&x| x != 0.0));
+        assert_eq!(&a[..], &b[..]);
+    }
+
+    #[test]
+    fn test_fill() {
+        let chunks = [
+            0b001000010010,
+            0b110011001100,
+            0b111000111000,
+            0b111111111111,
+            0b10101110101,
+            0b00101111010,
+            0b01110111011,
+            0b00001000,
+        ];
+
+        let b = Bootstrap::new(&chunks);
+        let params = BaseParams::new(b.unwrap_period());
+        let (_, voice, _) = descramble(&chunks, &params);
+
+        let mut prev = PrevFrame::default();
+        prev.params = BaseParams::new(60);
+        prev.voice = VoiceDecisions::new(0b111011, &prev.params);
+
+        for l in 1..=prev.params.harmonics as usize {
+            prev.enhanced.push(100.0 + 10.0 * l as f32);
+            prev.phase.0[l - 1] = 3.7 * l as f32;
+        }
+
+        let mut amps = EnhancedSpectrals::default();
+
+        for l in 1..=params.harmonics as usize {
+            amps.push(400.0 - 20.0 * l as f32);
+        }
+
+        let pb = PhaseBase::new(&params, &prev);
+        let phase = Phase::new(&pb, &params, &prev, &voice, XorShiftRng::new_unseeded());
+        let v = Voiced::new(&params, &prev, &phase, &amps, &voice);
+
+        let mut full = [0.0; SAMPLES_PER_FRAME];
+        v.fill(0, &mut full[..]);
+
+        // Use chunks that don't line up with the frame to exercise the starting phase.
+        let mut parts = [0.0; SAMPLES_PER_FRAME];
+
+        for (i, chunk) in parts.chunks_mut(70).enumerate() {
+            v.fill(i * 70, chunk);
+        }
+
+        assert!(full.iter().any(|&x| x.abs() > 100.0));
+
+        for n in 0..SAMPLES_PER_FRAME {
+            assert!((full[n] - v.get(n)).abs() < 0.05);
+            assert!((parts[n] - v.get(n)).abs() < 0.05);
+        }
+    }
 }
@@ -32,7 +32,7 @@ impl Window {
 }
 
 /// Coefficients of the speech synthesis window [p95].
-static WINDOW_SYNTHESIS: [f32; 211] = [
+pub static WINDOW_SYNTHESIS: [f32; 211] = [
     0.000000,
     0.020000,
     0.040000,